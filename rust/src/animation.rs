@@ -0,0 +1,107 @@
+//! Animated GIF/WebP inputs.
+//!
+//! By default animations are treated like stills and only their first frame is bordered.
+//! With `--animated keep`, every frame goes through the normal layout and the result is
+//! re-encoded as an animated GIF, one frame at a time so memory stays bounded by a couple
+//! of frames rather than the whole animation.
+
+use crate::stats::Stages;
+use crate::{compose, Config};
+use clap::ValueEnum;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frame, Frames, ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+/// What to do with animated inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AnimatedMode {
+    /// Border only the first frame, like any still image.
+    First,
+    /// Border every frame and write an animated GIF.
+    Keep,
+}
+
+/// Returns true if `path` is a GIF or WebP with more than one frame.
+///
+/// Only headers are read, except for GIF where the second frame descriptor has to be found.
+pub fn is_animated(path: &Path) -> bool {
+    let format = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .ok()
+        .and_then(|r| r.format());
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let reader = BufReader::new(file);
+    match format {
+        Some(ImageFormat::Gif) => gif::DecodeOptions::new()
+            .read_info(reader)
+            .map(|mut decoder| {
+                let mut frames = 0;
+                while frames < 2 && matches!(decoder.next_frame_info(), Ok(Some(_))) {
+                    frames += 1;
+                }
+                frames > 1
+            })
+            .unwrap_or(false),
+        Some(ImageFormat::WebP) => WebPDecoder::new(reader)
+            .map(|d| d.has_animation())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Borders every frame of an animated GIF/WebP and writes an animated GIF to `out`.
+///
+/// Frames come out of the decoder already composited according to their disposal method,
+/// so each one is a complete picture; delays and the loop count are carried over.
+pub fn process_animation(
+    input_path: &Path,
+    out: impl Write,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let format = ImageReader::open(input_path)?
+        .with_guessed_format()?
+        .format();
+    let open = || File::open(input_path).map(BufReader::new);
+
+    let (frames, repeat): (Frames, Repeat) = match format {
+        Some(ImageFormat::Gif) => {
+            let repeat = match gif::DecodeOptions::new().read_info(open()?)?.repeat() {
+                gif::Repeat::Infinite => Repeat::Infinite,
+                gif::Repeat::Finite(n) => Repeat::Finite(n),
+            };
+            (GifDecoder::new(open()?)?.into_frames(), repeat)
+        }
+        Some(ImageFormat::WebP) => {
+            let repeat = match image_webp::WebPDecoder::new(open()?)?.loop_count() {
+                image_webp::LoopCount::Forever => Repeat::Infinite,
+                image_webp::LoopCount::Times(n) => Repeat::Finite(n.get()),
+            };
+            (WebPDecoder::new(open()?)?.into_frames(), repeat)
+        }
+        _ => return Err("only GIF and WebP animations can be kept".into()),
+    };
+
+    {
+        let mut encoder = GifEncoder::new_with_speed(out, 10);
+        encoder.set_repeat(repeat)?;
+        let mut frames = frames.into_iter();
+        // Frames decode lazily, so each step is timed frame by frame.
+        while let Some(frame) = Stages::time(&mut stages.decode, || frames.next()) {
+            let frame = frame?;
+            let delay = frame.delay();
+            // GIF transparency is all-or-nothing, so blend soft edges onto the border.
+            let buffer = config.rotation.turn_frame(frame.into_buffer());
+            let canvas = Stages::time(&mut stages.border, || compose(&buffer, config, true))?;
+            let frame = Frame::from_parts(canvas, 0, 0, delay);
+            Stages::time(&mut stages.encode, || encoder.encode_frame(frame))?;
+        }
+        // Dropping the encoder writes the GIF trailer.
+    }
+    Ok(())
+}
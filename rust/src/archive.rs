@@ -0,0 +1,305 @@
+//! ZIP archive inputs.
+//!
+//! Every image entry is decoded straight from the archive and bordered like any other image;
+//! other entries are ignored. Outputs go into a new `<name>_bordered.zip` next to the other
+//! outputs, or with `--zip-output folder` into a `<name>` folder, keeping the archive's
+//! directory structure either way.
+
+use crate::atomic::{is_disk_full, AtomicFile};
+use crate::caption::CaptionSource;
+use crate::decode::decode_bytes;
+use crate::discover::{is_supported_image, SkipReason, Skipped};
+use crate::metadata::Metadata;
+use crate::output::{
+    existing_output, output_file_name, output_root, size_folder, thumbnail_path, unclaimed_path,
+    NameSource, Numbering, OutputFormat,
+};
+use crate::size_limit;
+use crate::stats::{Stages, Stats};
+use crate::{compose_image, encode_output, fit_note, format_size, report_skip, thumbnail, Config};
+use clap::ValueEnum;
+use image::DynamicImage;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Where the outputs of a ZIP input are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ZipOutput {
+    /// A new archive named after the input, e.g. `photos_bordered.zip`.
+    Archive,
+    /// A folder named after the input, e.g. `photos/`.
+    Folder,
+}
+
+/// Returns true if `path` looks like a ZIP archive.
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false)
+}
+
+/// Output destination for the entries of one archive.
+enum Sink {
+    Archive(Box<ZipWriter<AtomicFile>>),
+    Folder(PathBuf),
+}
+
+/// Borders every image entry of the archive at `zip_path`, recording results for `input`.
+///
+/// A corrupt entry fails on its own; only an unreadable archive or output fails the
+/// whole input.
+pub fn process_zip(
+    input: usize,
+    zip_path: &Path,
+    config: &Config,
+    stats: &mut Stats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(File::open(zip_path)?)?;
+    let parent = match zip_path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let stem = zip_path.file_stem().unwrap_or_default().to_string_lossy();
+    let output_folder = output_root(parent, config);
+    std::fs::create_dir_all(&output_folder)?;
+    let mut sink = match config.zip_output {
+        ZipOutput::Archive => {
+            let output = output_folder.join(format!("{}_bordered.zip", stem));
+            if !config.force && output.exists() {
+                let skipped = Skipped {
+                    input,
+                    path: zip_path.to_path_buf(),
+                    reason: SkipReason::OutputExists,
+                    detail: None,
+                };
+                report_skip(&skipped, stats);
+                return Ok(());
+            }
+            Sink::Archive(Box::new(ZipWriter::new(AtomicFile::create(&output)?)))
+        }
+        ZipOutput::Folder => Sink::Folder(output_folder.join(stem.as_ref())),
+    };
+    // Outputs are JPEG, PNG or WebP, which don't gain anything from deflate.
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut taken = HashSet::new();
+
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                stats.record_failure(input);
+                eprintln!(
+                    "❌ Error processing {} entry #{}: {}",
+                    zip_path.display(),
+                    index + 1,
+                    e
+                );
+                continue;
+            }
+        };
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if entry.is_dir() || !is_supported_image(&name, config) || is_hidden_entry(&name, config) {
+            continue;
+        }
+        let label = format!("{}/{}", zip_path.display(), name.display());
+        let ext = name.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let format = config
+            .format
+            .unwrap_or_else(|| OutputFormat::for_extension(ext));
+        let file_name = name.file_name().unwrap_or_default().to_string_lossy();
+        let source = NameSource {
+            file_name: &file_name,
+            path: None,
+            seq: index + 1,
+        };
+
+        // One output per `--size`, each with the label it's reported under.
+        let mut pending = Vec::new();
+        for size in config.output_sizes() {
+            let config = config.at_size(size);
+            let mut output_name = PathBuf::new();
+            if let Some(folder) = size_folder(&config) {
+                output_name.push(folder);
+            }
+            if let Some(dir) = name.parent().filter(|_| !config.flatten) {
+                output_name.push(dir);
+            }
+            output_name.push(output_file_name(&source, format, &config));
+            // Flattened entries of different folders can meet; an archive can't hold both.
+            let output_name = unclaimed_path(output_name, &mut taken, Numbering::Underscore);
+            let output_label = match size {
+                Some((width, height)) => format!("{} at {}x{}", label, width, height),
+                None => label.clone(),
+            };
+            if let Sink::Folder(folder) = &sink {
+                let existing = existing_output(&folder.join(&output_name), format, &config);
+                if !config.force && existing.is_some() {
+                    let skipped = Skipped {
+                        input,
+                        path: PathBuf::from(&label),
+                        reason: SkipReason::OutputExists,
+                        detail: size.map(|(width, height)| format!("{}x{}", width, height)),
+                    };
+                    report_skip(&skipped, stats);
+                    continue;
+                }
+            }
+            pending.push((config, output_name, output_label));
+        }
+        if pending.is_empty() {
+            continue;
+        }
+
+        // Decode once; the first output's time includes it.
+        let mut start = Instant::now();
+        let mut stages = Stages::default();
+        let mut data = Vec::new();
+        let decoded = entry
+            .read_to_end(&mut data)
+            .map_err(Box::<dyn std::error::Error>::from)
+            .and_then(|_| Stages::time(&mut stages.decode, || decode_bytes(&data, config)));
+        let img = match decoded {
+            Ok(img) => img,
+            Err(e) => {
+                stats.record_failure(input);
+                eprintln!("❌ Error processing {}: {}", label, e);
+                continue;
+            }
+        };
+        let metadata = Metadata::from_bytes(&data, &label, config.metadata);
+        let picked = config.pick_border_color(&img, &label);
+        let class_size = config.class_size(img.width(), img.height());
+        let caption_stem = name.file_stem().unwrap_or_default().to_string_lossy();
+        for (config, output_name, output_label) in pending {
+            let config = config.padded(Some((img.width(), img.height())));
+            // The names were chosen before the image was decoded, at the global size.
+            let config = config.at_size(class_size);
+            let config = config.with_border_color(picked);
+            let config = config.with_crop_focus(&img);
+            let config = config.with_caption(&caption_stem, CaptionSource::Bytes(&data));
+            let result =
+                border_decoded(&img, &metadata, format, &config, &mut stages).and_then(|encoded| {
+                    sink.write(&output_name, &encoded.output, options)?;
+                    if let Some(thumbnail) = &encoded.thumbnail {
+                        sink.write(&thumbnail_path(&output_name), thumbnail, options)?;
+                    }
+                    Ok(encoded.output.len())
+                });
+            match result {
+                Ok(size) => {
+                    let elapsed = start.elapsed();
+                    println!(
+                        "✅ Successfully processed {} in {:.2} seconds ({}; {}{})",
+                        output_label,
+                        elapsed.as_secs_f64(),
+                        stages,
+                        format_size(size as u64),
+                        stages.fitted_quality()
+                    );
+                    stats.record_success(input, &output_label, elapsed, size as u64);
+                    stats.record_dimensions(config.target_width, config.target_height);
+                    let (width, height) = (img.width(), img.height());
+                    if let Some(note) = fit_note(&output_label, width, height, &config) {
+                        println!("{}", note);
+                    }
+                    if let Some(fit) = &stages.fit {
+                        size_limit::warn_if_over(&output_label, size as u64, &config, &stages);
+                        stats.record_fit(fit);
+                    }
+                }
+                // Every later entry would fail the same way; the caller stops the run.
+                Err(e) if is_disk_full(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    stats.record_failure(input);
+                    eprintln!("❌ Error processing {}: {}", output_label, e);
+                }
+            }
+            start = Instant::now();
+            stages = Stages::default();
+        }
+    }
+
+    if let Sink::Archive(writer) = sink {
+        writer.finish()?.commit()?;
+    }
+    Ok(())
+}
+
+/// An entry's encoded output, with its `--thumbnail` if one was asked for.
+struct Encoded {
+    output: Vec<u8>,
+    thumbnail: Option<Vec<u8>>,
+}
+
+/// Borders and re-encodes one decoded entry.
+fn border_decoded(
+    img: &DynamicImage,
+    metadata: &Metadata,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Encoded, Box<dyn std::error::Error>> {
+    let canvas = Stages::time(&mut stages.border, || compose_image(img, format, config))?;
+    let output = encode_output(&canvas, metadata, format, config, stages)?;
+    let thumbnail = match config.thumbnail {
+        Some(size) => {
+            let thumbnail = Stages::time(&mut stages.border, || thumbnail(&canvas, size));
+            let config = config.for_thumbnail();
+            Some(encode_output(
+                &thumbnail, metadata, format, &config, stages,
+            )?)
+        }
+        None => None,
+    };
+    Ok(Encoded { output, thumbnail })
+}
+
+impl Sink {
+    fn write(
+        &mut self,
+        name: &Path,
+        bytes: &[u8],
+        options: SimpleFileOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Sink::Archive(writer) => {
+                writer.start_file(entry_name(name), options)?;
+                writer.write_all(bytes)?;
+            }
+            Sink::Folder(folder) => {
+                let path = folder.join(name);
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                let mut file = AtomicFile::create(&path)?;
+                file.write_all(bytes)?;
+                file.commit()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns a relative output path into a ZIP entry name, which always uses forward slashes.
+pub fn entry_name(name: &Path) -> String {
+    name.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns true for dotfiles and macOS `__MACOSX` resource forks, unless `--include-hidden`.
+fn is_hidden_entry(name: &Path, config: &Config) -> bool {
+    !config.include_hidden
+        && name.components().any(|c| {
+            let c = c.as_os_str().to_string_lossy();
+            c.starts_with('.') || c == "__MACOSX"
+        })
+}
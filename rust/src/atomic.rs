@@ -0,0 +1,104 @@
+//! Atomic output writes.
+//!
+//! Outputs are written to a hidden temporary file next to their destination and renamed
+//! into place once the encoder has finished and the data is on disk, so an interrupted run
+//! never leaves a truncated output that a later run would take as done.
+
+use image::ImageError;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Makes temporary names unique between the files this process writes at the same time.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A file that only appears at its destination once committed. Dropping it uncommitted
+/// removes the temporary file.
+pub struct AtomicFile {
+    file: BufWriter<File>,
+    temp: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file for `target`, e.g. `.bordered_x.jpg.1234-0.tmp`.
+    ///
+    /// The name holds the process id, and the file is created exclusively, so two instances
+    /// writing the same output never share a temporary file.
+    pub fn create(target: &Path) -> io::Result<Self> {
+        let dir = target.parent().unwrap_or(Path::new("."));
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let temp = dir.join(format!(".{}.{}-{}.tmp", name, std::process::id(), id));
+            match OpenOptions::new().write(true).create_new(true).open(&temp) {
+                Ok(file) => {
+                    return Ok(Self {
+                        file: BufWriter::new(file),
+                        temp,
+                        target: target.to_path_buf(),
+                        committed: false,
+                    })
+                }
+                // Left over from an earlier process with the same id; try the next name.
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Flushes and syncs the data, then moves the file to its destination, replacing any
+    /// existing file there.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        std::fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// ZIP writers seek back to fill in entry headers.
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Returns true if `error`, or any error it was caused by, is the disk running out of space.
+pub fn is_disk_full(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        // `ImageError` skips its I/O error when asked for the source, so unwrap it by hand.
+        let io = match e.downcast_ref::<ImageError>() {
+            Some(ImageError::IoError(io)) => Some(io),
+            _ => e.downcast_ref::<io::Error>(),
+        };
+        if io.is_some_and(|io| io.kind() == ErrorKind::StorageFull) {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
@@ -0,0 +1,73 @@
+//! `--background blur`: fill the canvas with a blurred, zoomed copy of the image instead of
+//! the border color.
+//!
+//! The copy is scaled to cover the whole canvas, its overflow cropped evenly, then blurred
+//! and optionally darkened. The blur runs on a copy scaled down by up to `MAX_REDUCTION`,
+//! with the radius scaled to match, and is scaled back up afterwards: a blurred image has
+//! no detail to lose, and even a 50 MP source only costs a small blur.
+
+use crate::Config;
+use clap::ValueEnum;
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Pixel, Primitive};
+use num_traits::{NumCast, ToPrimitive};
+
+/// Most the copy is scaled down by for blurring.
+const MAX_REDUCTION: f32 = 8.0;
+
+/// Smallest radius, in pixels of the reduced copy, it is blurred with: a copy reduced any
+/// further would show blocks once scaled back up.
+const REDUCED_RADIUS: f32 = 4.0;
+
+/// What fills the canvas around the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Background {
+    /// The border color (see --border-color).
+    Color,
+    /// A blurred copy of the image, zoomed to cover the canvas.
+    Blur,
+}
+
+/// Returns a canvas-sized, blurred and darkened copy of `img` covering the whole canvas.
+pub fn blurred<P>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    config: &Config,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    let (width, height) = (config.target_width, config.target_height);
+    let reduction = (config.blur_radius / REDUCED_RADIUS).clamp(1.0, MAX_REDUCTION);
+    let reduced = |size: u32| ((size as f32 / reduction).ceil() as u32).max(1);
+    let (small_width, small_height) = (reduced(width), reduced(height));
+
+    // Cover: the larger of the two scales, so both sides reach the canvas edges.
+    let (img_width, img_height) = img.dimensions();
+    let scale =
+        (small_width as f64 / img_width as f64).max(small_height as f64 / img_height as f64);
+    let cover_width = ((img_width as f64 * scale).ceil() as u32).max(small_width);
+    let cover_height = ((img_height as f64 * scale).ceil() as u32).max(small_height);
+    let cover = imageops::resize(img, cover_width, cover_height, FilterType::Triangle);
+    let cropped = imageops::crop_imm(
+        &cover,
+        (cover_width - small_width) / 2,
+        (cover_height - small_height) / 2,
+        small_width,
+        small_height,
+    )
+    .to_image();
+
+    let blurred = imageops::blur(&cropped, config.blur_radius / reduction);
+    let mut background = imageops::resize(&blurred, width, height, FilterType::Triangle);
+    if config.background_darken > 0.0 {
+        let keep = 1.0 - config.background_darken as f64;
+        for pixel in background.pixels_mut() {
+            pixel.apply_without_alpha(|value| {
+                let darker = value.to_f64().unwrap_or(0.0) * keep;
+                <P::Subpixel as NumCast>::from(darker).unwrap_or(P::Subpixel::DEFAULT_MIN_VALUE)
+            });
+        }
+    }
+    background
+}
@@ -0,0 +1,217 @@
+//! `--border-color`: the color the canvas around the image is filled with.
+//!
+//! Colors are 8-bit sRGB with an optional alpha. Outputs that keep alpha (PNG, WebP, AVIF,
+//! TIFF) get a translucent border from `#RRGGBBAA`; the others ignore the alpha and get the
+//! color opaque. `transparent` leaves the border fully transparent, which needs one of the
+//! former.
+//!
+//! `auto` picks an opaque color from each image instead, working on a copy scaled down to
+//! `SAMPLE_SIZE`, so even very large images take next to no time.
+
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{DynamicImage, Pixel, Primitive};
+use num_traits::{NumCast, ToPrimitive};
+use std::fmt;
+
+/// Colors that can be given by name.
+const NAMED: [(&str, [u8; 4]); 5] = [
+    ("white", [255, 255, 255, 255]),
+    ("black", [0, 0, 0, 255]),
+    ("gray", [128, 128, 128, 255]),
+    ("grey", [128, 128, 128, 255]),
+    ("transparent", [0, 0, 0, 0]),
+];
+
+/// Longest edge of the copy `auto` picks its color from.
+const SAMPLE_SIZE: u32 = 128;
+
+/// Rows and columns of the sample the `edge` mode averages along each side.
+const EDGE_DEPTH: u32 = 2;
+
+/// Weights of red, green and blue in the gray a grayscale canvas is filled with, the ones
+/// `image` converts with.
+const LUMA: [f64; 3] = [0.2126, 0.7152, 0.0722];
+
+/// A border color, as red, green, blue and alpha.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BorderColor(pub [u8; 4]);
+
+/// What `--border-color` asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderColorChoice {
+    Fixed(BorderColor),
+    /// Picked from each image, see `--auto-color-mode`.
+    Auto,
+}
+
+/// `--auto-color-mode`: how `--border-color auto` picks a color from an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AutoColorMode {
+    /// The mean of all pixels.
+    Average,
+    /// The most common color, from a histogram of coarsely quantized colors.
+    Dominant,
+    /// The mean of the pixels along the image's edges, which the border touches.
+    Edge,
+}
+
+impl BorderColor {
+    /// The color of `--border-color white`, also the one `auto` falls back to.
+    pub const WHITE: BorderColor = BorderColor([255, 255, 255, 255]);
+    pub const TRANSPARENT: BorderColor = BorderColor([0, 0, 0, 0]);
+    /// The default `--keyline-color`.
+    pub const BLACK: BorderColor = BorderColor([0, 0, 0, 255]);
+
+    /// Returns true if a grayscale canvas can hold the color.
+    pub fn is_gray(self) -> bool {
+        let [r, g, b, _] = self.0;
+        r == g && g == b
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.0[3] == u8::MAX
+    }
+
+    /// The color without its alpha, for outputs that can't hold one.
+    pub fn opaque(self) -> Self {
+        let [r, g, b, _] = self.0;
+        BorderColor([r, g, b, u8::MAX])
+    }
+
+    /// The color as a pixel of any type: scaled to its sample range, turned gray for
+    /// grayscale pixels and without alpha for pixels that have none.
+    pub fn pixel<P: Pixel>(self) -> P {
+        let max = P::Subpixel::DEFAULT_MAX_VALUE.to_f64().unwrap_or(255.0);
+        let sample = |value: f64| {
+            let scaled = value / 255.0 * max;
+            // Integer samples are rounded; float samples (with a maximum of 1.0) kept exact.
+            let scaled = if max > 1.0 { scaled.round() } else { scaled };
+            <P::Subpixel as NumCast>::from(scaled).unwrap_or(P::Subpixel::DEFAULT_MAX_VALUE)
+        };
+        let [r, g, b, a] = self.0.map(|v| v as f64);
+        let gray = LUMA[0] * r + LUMA[1] * g + LUMA[2] * b;
+        let channels = match P::CHANNEL_COUNT {
+            1 => vec![sample(gray)],
+            2 => vec![sample(gray), sample(a)],
+            3 => vec![sample(r), sample(g), sample(b)],
+            _ => vec![sample(r), sample(g), sample(b), sample(a)],
+        };
+        *P::from_slice(&channels[..P::CHANNEL_COUNT as usize])
+    }
+}
+
+/// Parses a `--border-color`: `#RRGGBB`, `#RRGGBBAA`, a name or `auto`.
+pub fn parse_border_color(s: &str) -> Result<BorderColorChoice, String> {
+    let invalid = || {
+        format!(
+            "invalid color '{}' (expected #RRGGBB, #RRGGBBAA, white, black, gray, transparent \
+             or auto, e.g. #1A1A1A or #FFFFFF80)",
+            s
+        )
+    };
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(BorderColorChoice::Auto);
+    }
+    if let Some(&(_, rgba)) = NAMED.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        return Ok(BorderColorChoice::Fixed(BorderColor(rgba)));
+    }
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let mut rgba = [u8::MAX; 4];
+    for (i, value) in rgba.iter_mut().take(hex.len() / 2).enumerate() {
+        *value = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(BorderColorChoice::Fixed(BorderColor(rgba)))
+}
+
+/// Parses a `--keyline-color`: like a border color, but opaque, so the line looks the same
+/// in every output format.
+pub fn parse_keyline_color(s: &str) -> Result<BorderColor, String> {
+    match parse_border_color(s)? {
+        BorderColorChoice::Fixed(color) if color.is_opaque() => Ok(color),
+        BorderColorChoice::Fixed(_) => Err(format!(
+            "invalid keyline color '{}' (it can't be translucent)",
+            s
+        )),
+        BorderColorChoice::Auto => Err(format!(
+            "invalid keyline color '{}' (auto is only for --border-color)",
+            s
+        )),
+    }
+}
+
+/// Picks a border color for `img` (`--border-color auto`). Translucent pixels count by
+/// their alpha; `None` if the image has nothing opaque enough to go by.
+pub fn pick(img: &DynamicImage, mode: AutoColorMode) -> Option<BorderColor> {
+    let sample = match img.width().max(img.height()) > SAMPLE_SIZE {
+        true => img
+            .resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Nearest)
+            .to_rgba8(),
+        false => img.to_rgba8(),
+    };
+    let (width, height) = sample.dimensions();
+    let pixels = sample.enumerate_pixels();
+    let rgba = match mode {
+        AutoColorMode::Average => mean(pixels.map(|(_, _, p)| p.0)),
+        AutoColorMode::Edge => {
+            let depth = EDGE_DEPTH.min(width / 2).min(height / 2).max(1);
+            let on_edge = |x: u32, y: u32| {
+                x < depth || y < depth || x >= width - depth || y >= height - depth
+            };
+            mean(
+                pixels
+                    .filter(|&(x, y, _)| on_edge(x, y))
+                    .map(|(_, _, p)| p.0),
+            )
+        }
+        AutoColorMode::Dominant => {
+            // 4 bits per channel: close shades share a bin, and the winner is their mean.
+            let bin = |[r, g, b, _]: [u8; 4]| {
+                (r as usize >> 4) << 8 | (g as usize >> 4) << 4 | b as usize >> 4
+            };
+            let mut weights = vec![0u64; 1 << 12];
+            for (_, _, p) in sample.enumerate_pixels() {
+                weights[bin(p.0)] += p.0[3] as u64;
+            }
+            let (dominant, _) = weights.iter().enumerate().max_by_key(|&(_, &w)| w)?;
+            mean(pixels.map(|(_, _, p)| p.0).filter(|&p| bin(p) == dominant))
+        }
+    }?;
+    Some(BorderColor(rgba))
+}
+
+/// The alpha-weighted mean color of `pixels`, opaque; `None` if all are transparent.
+fn mean(pixels: impl Iterator<Item = [u8; 4]>) -> Option<[u8; 4]> {
+    let mut sums = [0u64; 3];
+    let mut weight = 0u64;
+    for [r, g, b, a] in pixels {
+        let a = a as u64;
+        for (sum, value) in sums.iter_mut().zip([r, g, b]) {
+            *sum += value as u64 * a;
+        }
+        weight += a;
+    }
+    if weight == 0 {
+        return None;
+    }
+    let [r, g, b] = sums.map(|sum| ((sum + weight / 2) / weight) as u8);
+    Some([r, g, b, u8::MAX])
+}
+
+/// Writes the color's name, or `#RRGGBB` (`#RRGGBBAA` if translucent).
+impl fmt::Display for BorderColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((name, _)) = NAMED.iter().find(|&&(_, rgba)| rgba == self.0) {
+            return write!(f, "{}", name);
+        }
+        let [r, g, b, a] = self.0;
+        write!(f, "#{:02X}{:02X}{:02X}", r, g, b)?;
+        if a != u8::MAX {
+            write!(f, "{:02X}", a)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! `.borderignore` files: gitignore-style patterns that leave images out of folder scans.
+//!
+//! Any folder of an input may hold one. Its patterns apply to that folder's whole subtree,
+//! and deeper files take precedence, so a `!keep.jpg` line in a subfolder re-includes an
+//! image its parent's file ignores.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const IGNORE_FILE_NAME: &str = ".borderignore";
+
+/// The ignore files below one input folder, parsed the first time they're needed.
+pub struct IgnoreFiles {
+    root: PathBuf,
+    loaded: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl IgnoreFiles {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Returns the ignore file that excludes `path`, or `None` if no file does or the
+    /// deciding one re-includes it with a `!` pattern.
+    pub fn ignoring(&mut self, path: &Path) -> Result<Option<PathBuf>, String> {
+        let dirs: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in dirs {
+            let Some(matcher) = self.load(&dir)? else {
+                continue;
+            };
+            let rel = path.strip_prefix(&dir).unwrap_or(path);
+            match matcher.matched_path_or_any_parents(rel, false) {
+                Match::None => {}
+                Match::Ignore(_) => return Ok(Some(dir.join(IGNORE_FILE_NAME))),
+                Match::Whitelist(_) => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    fn load(&mut self, dir: &Path) -> Result<Option<&Gitignore>, String> {
+        if !self.loaded.contains_key(dir) {
+            let file = dir.join(IGNORE_FILE_NAME);
+            let matcher = if file.is_file() {
+                let invalid = |e: ignore::Error| format!("invalid {}: {}", file.display(), e);
+                let mut builder = GitignoreBuilder::new(dir);
+                if let Some(e) = builder.add(&file) {
+                    return Err(invalid(e));
+                }
+                Some(builder.build().map_err(invalid)?)
+            } else {
+                None
+            };
+            self.loaded.insert(dir.to_path_buf(), matcher);
+        }
+        Ok(self.loaded[dir].as_ref())
+    }
+}
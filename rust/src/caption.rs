@@ -0,0 +1,524 @@
+//! `--caption`: a line of text in the border below the image, e.g. its name and date, and
+//! `--caption-exif`: the camera settings it was taken with, from its EXIF (see `shot_info`).
+//!
+//! A word of the text whose placeholder has no value for an image is left out whole, so
+//! `ISO{iso}` doesn't leave a bare `ISO`, and so are separators like `·` that would be left
+//! dangling at either end or next to each other.
+//!
+//! Glyphs come from a TrueType or OpenType font, `--caption-font` or the first of
+//! `DEFAULT_FONTS` that is installed, and are laid out one after another from its character
+//! map and advances, so any script the font covers renders (accents, CJK), but there is no
+//! kerning or shaping: scripts whose letters join, like Arabic, come out unjoined. The
+//! outlines are filled anti-aliased into a coverage mask, which blends the color into
+//! whatever lies below, border, frames or shadow.
+//!
+//! The line is centered vertically in the room between the image and the bottom of the
+//! canvas. Text wider than the image is shrunk, down to `MIN_SHRINK` of its size, and then
+//! cut short with an ellipsis.
+
+use crate::border_color::{parse_border_color, BorderColor, BorderColorChoice};
+use crate::capture_date::{capture_time, format_date, original_time};
+use crate::shot_info::{read_exif, read_exif_bytes, ShotInfo};
+use crate::{corners, parse_border_size, BorderSize, Placement};
+use clap::ValueEnum;
+use image::{ImageBuffer, Pixel};
+use skrifa::instance::{LocationRef, Size};
+use skrifa::outline::{DrawSettings, OutlinePen};
+use skrifa::{FontRef, GlyphId, MetadataProvider};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tiny_skia::{FillRule, Mask, PathBuilder, Transform};
+
+/// Fonts tried, in order, when no `--caption-font` is given.
+const DEFAULT_FONTS: [&str; 10] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// The text of a `--caption-exif` given no template.
+pub const DEFAULT_EXIF_CAPTION: &str = "{camera} · {focal}mm {aperture} {shutter} ISO{iso}";
+
+/// The default `--caption-size`.
+pub const DEFAULT_SIZE: BorderSize = BorderSize::Ratio(0.35);
+
+/// Least a caption is shrunk to, as a fraction of its size, before it is cut short instead.
+const MIN_SHRINK: f32 = 0.7;
+
+/// Smallest text in pixels worth drawing; a caption that would be smaller is left out.
+const MIN_PIXELS: f32 = 4.0;
+
+/// What cut-short text ends in, with the fallback for fonts that lack it.
+const ELLIPSIS: &str = "…";
+const ELLIPSIS_FALLBACK: &str = "...";
+
+/// A `--caption` or `--caption-exif`: its text, with placeholders, and how it is drawn.
+#[derive(Clone, Debug)]
+pub struct Caption {
+    pub text: CaptionText,
+    /// `--caption-exif`: images without camera settings in their EXIF get `missing` instead,
+    /// or no caption.
+    pub from_exif: bool,
+    /// `--caption-exif-missing`.
+    pub missing: Option<CaptionText>,
+    /// `None` if there was no `--caption-font` and none of `DEFAULT_FONTS` is installed.
+    pub font: Option<CaptionFont>,
+    /// A ratio of the room below the image or pixels: the font size, its em.
+    pub size: BorderSize,
+    /// `None` to pick black or white, whichever stands out from the border.
+    pub color: Option<BorderColor>,
+    pub align: CaptionAlign,
+}
+
+/// `--caption-align`: where the caption sits along the image's width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CaptionAlign {
+    /// Starting at the image's left edge.
+    Left,
+    Center,
+    /// Ending at the image's right edge.
+    Right,
+}
+
+/// Where the image a caption is for came from, to read its date and EXIF from.
+#[derive(Clone, Copy, Debug)]
+pub enum CaptionSource<'a> {
+    File(&'a Path),
+    /// The encoded image, e.g. from an archive or standard input.
+    Bytes(&'a [u8]),
+    /// Neither, e.g. a downloaded image.
+    Unknown,
+}
+
+/// The text of a `--caption`, parsed into literal text and placeholders.
+#[derive(Clone, Debug)]
+pub struct CaptionText {
+    /// As given, for the settings record and the configuration summary.
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// `{stem}`: the input's file name without its extension.
+    Stem,
+    /// `{date}`: when the image was taken, from EXIF or a file's modification time, as
+    /// `2024-06-01`.
+    Date,
+    /// The rest are what `ShotInfo` has of the same name.
+    Camera,
+    Lens,
+    Focal,
+    Aperture,
+    Shutter,
+    Iso,
+}
+
+const PLACEHOLDERS: [(&str, Part); 8] = [
+    ("stem", Part::Stem),
+    ("date", Part::Date),
+    ("camera", Part::Camera),
+    ("lens", Part::Lens),
+    ("focal", Part::Focal),
+    ("aperture", Part::Aperture),
+    ("shutter", Part::Shutter),
+    ("iso", Part::Iso),
+];
+
+/// A word of a caption as it is filled in.
+#[derive(Default)]
+struct Word {
+    text: String,
+    /// Whether it has a placeholder, so it is no separator.
+    filled: bool,
+    /// Whether one of its placeholders has no value, so it is left out.
+    missing: bool,
+}
+
+/// A font loaded for `--caption`.
+#[derive(Clone)]
+pub struct CaptionFont {
+    pub path: PathBuf,
+    data: Arc<[u8]>,
+}
+
+/// Parses a `--caption`: text with `{stem}`, `{date}` and camera setting placeholders.
+pub fn parse_caption(s: &str) -> Result<CaptionText, String> {
+    let mut parts = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let literal_end = rest.find(['{', '}']).unwrap_or(rest.len());
+        if literal_end > 0 {
+            parts.push(Part::Literal(rest[..literal_end].to_string()));
+            rest = &rest[literal_end..];
+            continue;
+        }
+        if rest.starts_with('}') {
+            return Err("unmatched '}' in caption".to_string());
+        }
+        let close = rest.find('}').ok_or("unclosed '{' in caption")?;
+        let name = &rest[1..close];
+        let part = PLACEHOLDERS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, part)| part.clone())
+            .ok_or_else(|| {
+                let known: Vec<String> = PLACEHOLDERS
+                    .iter()
+                    .map(|(known, _)| format!("{{{}}}", known))
+                    .collect();
+                let known = known.join(", ");
+                format!(
+                    "unknown placeholder {{{}}} in caption (expected {})",
+                    name, known
+                )
+            })?;
+        parts.push(part);
+        rest = &rest[close + 1..];
+    }
+    Ok(CaptionText {
+        source: s.to_string(),
+        parts,
+    })
+}
+
+/// Parses a `--caption-font`: the path of a TrueType or OpenType font (the first of a
+/// collection), loaded right away so a bad one fails before anything is processed.
+pub fn parse_caption_font(s: &str) -> Result<CaptionFont, String> {
+    CaptionFont::load(Path::new(s)).map_err(|e| format!("can't use font '{}': {}", s, e))
+}
+
+/// Parses a `--caption-size`: a ratio of the room below the image or pixels.
+pub fn parse_caption_size(s: &str) -> Result<BorderSize, String> {
+    parse_border_size(s).map_err(|_| {
+        format!(
+            "invalid caption size '{}' (expected a ratio or pixels, e.g. 0.4 or 24px)",
+            s
+        )
+    })
+}
+
+/// Parses a `--caption-color`: like a border color, but not `auto`.
+pub fn parse_caption_color(s: &str) -> Result<BorderColor, String> {
+    match parse_border_color(s)? {
+        BorderColorChoice::Fixed(color) => Ok(color),
+        BorderColorChoice::Auto => Err(format!(
+            "invalid caption color '{}' (auto is only for --border-color)",
+            s
+        )),
+    }
+}
+
+/// The first of `DEFAULT_FONTS` that is installed and loads.
+pub fn default_font() -> Option<CaptionFont> {
+    DEFAULT_FONTS
+        .iter()
+        .find_map(|path| CaptionFont::load(Path::new(path)).ok())
+}
+
+impl CaptionFont {
+    fn load(path: &Path) -> Result<Self, String> {
+        let data: Arc<[u8]> = std::fs::read(path).map_err(|e| e.to_string())?.into();
+        let font = FontRef::from_index(&data, 0).map_err(|e| e.to_string())?;
+        if font.outline_glyphs().format().is_none() {
+            return Err("it has no glyph outlines".to_string());
+        }
+        Ok(CaptionFont {
+            path: path.to_path_buf(),
+            data,
+        })
+    }
+
+    fn font(&self) -> Option<FontRef<'_>> {
+        FontRef::from_index(&self.data, 0).ok()
+    }
+}
+
+/// Shows the path only, not the data.
+impl fmt::Debug for CaptionFont {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CaptionFont({})", self.path.display())
+    }
+}
+
+impl fmt::Display for CaptionText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl CaptionText {
+    /// Returns true if the text has a `{date}`, so callers only look the date up when needed.
+    pub fn uses_date(&self) -> bool {
+        self.parts.contains(&Part::Date)
+    }
+
+    /// Returns true if the text has a camera setting placeholder, such as `{iso}`.
+    pub fn uses_shot(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| !matches!(part, Part::Literal(_) | Part::Stem | Part::Date))
+    }
+
+    /// The text for the image whose file name has `stem`, taken on `date` with the settings
+    /// in `shot`, its words separated by single spaces.
+    fn render(&self, stem: &str, date: Option<&str>, shot: &ShotInfo) -> String {
+        let mut words = Vec::new();
+        let mut word = Word::default();
+        for part in &self.parts {
+            let value = match part {
+                Part::Literal(literal) => {
+                    for c in literal.chars() {
+                        match c.is_whitespace() {
+                            true => words.push(std::mem::take(&mut word)),
+                            false => word.text.push(c),
+                        }
+                    }
+                    continue;
+                }
+                Part::Stem => Some(stem),
+                Part::Date => date,
+                Part::Camera => shot.camera.as_deref(),
+                Part::Lens => shot.lens.as_deref(),
+                Part::Focal => shot.focal.as_deref(),
+                Part::Aperture => shot.aperture.as_deref(),
+                Part::Shutter => shot.shutter.as_deref(),
+                Part::Iso => shot.iso.as_deref(),
+            };
+            word.filled = true;
+            match value {
+                Some(value) => word.text.push_str(value),
+                None => word.missing = true,
+            }
+        }
+        words.push(word);
+
+        let mut text = String::new();
+        // A separator waits for the next word, and is dropped if none comes.
+        let mut separator = None;
+        for word in words
+            .into_iter()
+            .filter(|word| !word.missing && !word.text.is_empty())
+        {
+            if !word.filled && !word.text.chars().any(char::is_alphanumeric) {
+                if !text.is_empty() {
+                    separator.get_or_insert(word.text);
+                }
+                continue;
+            }
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            if let Some(separator) = separator.take() {
+                text.push_str(&separator);
+                text.push(' ');
+            }
+            text.push_str(&word.text);
+        }
+        text
+    }
+}
+
+impl Caption {
+    /// The text for the image whose file name has `stem`, its date and EXIF read from
+    /// `source` as needed. `None` for a `--caption-exif` on an image without camera settings,
+    /// unless there is a `--caption-exif-missing`.
+    pub fn text_for(&self, stem: &str, source: CaptionSource) -> Option<String> {
+        let texts = || std::iter::once(&self.text).chain(&self.missing);
+        let wants_date = texts().any(CaptionText::uses_date);
+        let wants_shot = self.from_exif || texts().any(CaptionText::uses_shot);
+        let exif = match source {
+            CaptionSource::File(path) if wants_shot => read_exif(path),
+            CaptionSource::Bytes(data) if wants_shot || wants_date => read_exif_bytes(data),
+            _ => None,
+        };
+        // Files fall back to their modification time; the others have none.
+        let date = match source {
+            CaptionSource::File(path) if wants_date => capture_time(path).map(|(time, _)| time),
+            _ => exif.as_ref().and_then(original_time),
+        };
+        let shot = exif.as_ref().and_then(ShotInfo::of);
+        let text = match shot.is_none() && self.from_exif {
+            true => self.missing.as_ref()?,
+            false => &self.text,
+        };
+        let date = date.map(format_date);
+        Some(text.render(stem, date.as_deref(), &shot.unwrap_or_default()))
+    }
+
+    /// Why the caption can't be drawn, if it can't.
+    pub fn unavailable(&self) -> Option<String> {
+        match self.font {
+            Some(_) => None,
+            None => Some(
+                "--caption found none of the usual system fonts; give one with --caption-font"
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// The color the caption is drawn in on a `border` of this color.
+    pub fn color_on(&self, border: BorderColor) -> BorderColor {
+        self.color.unwrap_or_else(|| {
+            let [r, g, b, a] = border.0;
+            let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+            // A mostly transparent border shows whatever is behind it, likely a light page.
+            match luma < 128.0 && a >= 128 {
+                true => BorderColor::WHITE,
+                false => BorderColor::BLACK,
+            }
+        })
+    }
+
+    /// Draws `text` in `color` on `canvas`, in the room below the image at `placement`.
+    /// Nothing is drawn if there is no room for it.
+    pub fn draw<P: Pixel>(
+        &self,
+        canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+        placement: Placement,
+        text: &str,
+        color: P,
+    ) {
+        let Some(font) = self.font.as_ref().and_then(CaptionFont::font) else {
+            return;
+        };
+        let (canvas_width, canvas_height) = canvas.dimensions();
+        let top = placement.y + placement.height;
+        let room = canvas_height.saturating_sub(top);
+        let max_width = placement.width as f32;
+
+        // The line's height, ascent to descent, per pixel of font size.
+        let unscaled = font.metrics(Size::unscaled(), LocationRef::default());
+        let em = unscaled.units_per_em.max(1) as f32;
+        let line = (unscaled.ascent - unscaled.descent) / em;
+        let mut size = (self.size.resolve(room) as f32).min(room as f32 / line);
+        let width = advance(&font, text, size);
+        if width > max_width {
+            size *= (max_width / width).max(MIN_SHRINK);
+        }
+        if size < MIN_PIXELS {
+            return;
+        }
+        let text = elide(&font, text, size, max_width);
+        let width = advance(&font, &text, size);
+
+        let left = match self.align {
+            CaptionAlign::Left => placement.x as f32,
+            CaptionAlign::Center => placement.x as f32 + (max_width - width) / 2.0,
+            CaptionAlign::Right => placement.x as f32 + max_width - width,
+        };
+        let ascent = unscaled.ascent / em * size;
+        let baseline = top as f32 + (room as f32 - line * size) / 2.0 + ascent;
+
+        // Glyphs may reach past their advances, so the mask covers the room's full width;
+        // whatever reaches above it is cut off rather than drawn over the image.
+        let Some(mut mask) = Mask::new(canvas_width, room) else {
+            return;
+        };
+        let mut pen = Pen {
+            path: PathBuilder::new(),
+            x: left,
+            y: baseline - top as f32,
+        };
+        let settings = || DrawSettings::unhinted(Size::new(size), LocationRef::default());
+        let outlines = font.outline_glyphs();
+        let metrics = font.glyph_metrics(Size::new(size), LocationRef::default());
+        for glyph in glyphs(&font, &text) {
+            if let Some(outline) = outlines.get(glyph) {
+                let _ = outline.draw(settings(), &mut pen);
+            }
+            pen.x += metrics.advance_width(glyph).unwrap_or(0.0);
+        }
+        let Some(path) = pen.path.finish() else {
+            return;
+        };
+        mask.fill_path(&path, FillRule::Winding, true, Transform::identity());
+
+        for (i, &coverage) in mask.data().iter().enumerate() {
+            if coverage == 0 {
+                continue;
+            }
+            let (x, y) = (i as u32 % canvas_width, top + i as u32 / canvas_width);
+            let under = *canvas.get_pixel(x, y);
+            canvas.put_pixel(x, y, corners::mix(under, color, coverage as f64 / 255.0));
+        }
+    }
+}
+
+/// The glyphs of `text`, the font's missing-glyph box for characters it doesn't have.
+fn glyphs<'a>(font: &'a FontRef, text: &'a str) -> impl Iterator<Item = GlyphId> + 'a {
+    let charmap = font.charmap();
+    text.chars()
+        .map(move |c| charmap.map(c).unwrap_or(GlyphId::NOTDEF))
+}
+
+/// How wide `text` is at `size`, in pixels.
+fn advance(font: &FontRef, text: &str, size: f32) -> f32 {
+    let metrics = font.glyph_metrics(Size::new(size), LocationRef::default());
+    glyphs(font, text)
+        .map(|glyph| metrics.advance_width(glyph).unwrap_or(0.0))
+        .sum()
+}
+
+/// `text` cut short with an ellipsis to fit `max_width` at `size`, or as it is if it fits.
+fn elide(font: &FontRef, text: &str, size: f32, max_width: f32) -> String {
+    if advance(font, text, size) <= max_width {
+        return text.to_string();
+    }
+    let ellipsis = match font.charmap().map('…') {
+        Some(_) => ELLIPSIS,
+        None => ELLIPSIS_FALLBACK,
+    };
+    let mut chars: Vec<char> = text.chars().collect();
+    while chars.pop().is_some() {
+        let kept: String = chars.iter().collect();
+        let elided = format!("{}{}", kept.trim_end(), ellipsis);
+        if advance(font, &elided, size) <= max_width {
+            return elided;
+        }
+    }
+    String::new()
+}
+
+/// Traces glyph outlines into a path, each moved to the pen's position: font units point
+/// up, the canvas's down.
+struct Pen {
+    path: PathBuilder,
+    /// Where the current glyph starts along the baseline.
+    x: f32,
+    /// The baseline.
+    y: f32,
+}
+
+impl OutlinePen for Pen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.path.move_to(self.x + x, self.y - y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to(self.x + x, self.y - y);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.path
+            .quad_to(self.x + cx0, self.y - cy0, self.x + x, self.y - y);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        let (x0, y0, x1, y1) = (self.x + cx0, self.y - cy0, self.x + cx1, self.y - cy1);
+        self.path.cubic_to(x0, y0, x1, y1, self.x + x, self.y - y);
+    }
+
+    fn close(&mut self) {
+        self.path.close();
+    }
+}
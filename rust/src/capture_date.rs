@@ -0,0 +1,173 @@
+//! `--since`/`--until`: filter images by when they were taken, plus the date and duration
+//! parsing shared with `--modified-within`.
+//!
+//! The capture time comes from the EXIF `DateTimeOriginal` tag, read from the file's
+//! metadata segments without decoding any pixels, and falls back to the modification time.
+//! Times without a recorded UTC offset are taken as UTC, as are the bounds.
+
+use crate::shot_info::read_exif;
+use clap::ValueEnum;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECS_PER_DAY: i64 = 86_400;
+
+/// What to do with images that have neither an EXIF date nor a modification time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MissingDate {
+    Include,
+    Exclude,
+}
+
+/// Where a capture time was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateSource {
+    Exif,
+    Mtime,
+}
+
+/// Parses a lower bound: a date (start of that day) or a duration before now.
+pub fn parse_since(s: &str) -> Result<i64, String> {
+    parse_bound(s, false)
+}
+
+/// Parses an upper bound: a date (end of that day, so it's inclusive) or a duration before now.
+pub fn parse_until(s: &str) -> Result<i64, String> {
+    parse_bound(s, true)
+}
+
+/// Parses a duration before now ("30m", "2h", "7d", "2w") into the time it points back to.
+pub fn parse_age(s: &str) -> Result<i64, String> {
+    let secs = parse_duration(s)
+        .ok_or_else(|| format!("invalid duration '{}' (expected e.g. 30m, 2h, 7d or 2w)", s))?;
+    Ok(now() - secs)
+}
+
+fn parse_duration(s: &str) -> Option<i64> {
+    let unit = s.chars().last()?;
+    let count: i64 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+    let unit_secs = match unit.to_ascii_lowercase() {
+        'm' => 60,
+        'h' => 3_600,
+        'd' => SECS_PER_DAY,
+        'w' => 7 * SECS_PER_DAY,
+        _ => return None,
+    };
+    Some(count * unit_secs)
+}
+
+fn parse_bound(s: &str, end_of_day: bool) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "invalid date '{}' (expected e.g. 2024-06-01, 12h, 7d or 2w)",
+            s
+        )
+    };
+    if s.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        return Ok(now() - parse_duration(s).ok_or_else(invalid)?);
+    }
+    let parts: Vec<&str> = s.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day) = (
+        year.parse().map_err(|_| invalid())?,
+        month.parse().map_err(|_| invalid())?,
+        day.parse().map_err(|_| invalid())?,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let start = days_from_civil(year, month, day) * SECS_PER_DAY;
+    Ok(if end_of_day {
+        start + SECS_PER_DAY - 1
+    } else {
+        start
+    })
+}
+
+/// Returns when the image at `path` was taken, in seconds since the Unix epoch.
+pub fn capture_time(path: &Path) -> Option<(i64, DateSource)> {
+    if let Some(time) = read_exif(path).as_ref().and_then(original_time) {
+        return Some((time, DateSource::Exif));
+    }
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some((system_secs(modified), DateSource::Mtime))
+}
+
+/// Returns when `exif` says its image was taken, in seconds since the Unix epoch.
+pub fn original_time(exif: &exif::Exif) -> Option<i64> {
+    use exif::{DateTime, In, Tag, Value};
+
+    let ascii = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first().cloned(),
+        _ => None,
+    };
+    let mut time = DateTime::from_ascii(&ascii(Tag::DateTimeOriginal)?).ok()?;
+    if let Some(offset) = ascii(Tag::OffsetTimeOriginal) {
+        let _ = time.parse_offset(&offset);
+    }
+    let days = days_from_civil(time.year as i64, time.month as i64, time.day as i64);
+    let secs = days * SECS_PER_DAY
+        + time.hour as i64 * 3_600
+        + time.minute as i64 * 60
+        + time.second as i64;
+    Some(secs - time.offset.unwrap_or(0) as i64 * 60)
+}
+
+/// Formats a time as its UTC date, e.g. "2024-06-01".
+pub fn format_date(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats a time as its UTC date and time, e.g. "2024-06-01 14:05 UTC".
+pub fn format_date_time(secs: i64) -> String {
+    let minutes = secs.rem_euclid(SECS_PER_DAY) / 60;
+    format!(
+        "{} {:02}:{:02} UTC",
+        format_date(secs),
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+fn now() -> i64 {
+    system_secs(SystemTime::now())
+}
+
+pub fn system_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of `days_from_civil`.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
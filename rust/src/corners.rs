@@ -0,0 +1,166 @@
+//! `--corner-radius`: round the photo's corners where it sits on the canvas.
+//!
+//! Corner pixels are mixed with what was under the photo by how much of them the rounded
+//! shape covers, so the edge is anti-aliased rather than stepped. On outputs with alpha, a
+//! transparent border stays transparent behind the corners.
+
+use crate::Placement;
+use image::imageops;
+use image::{ImageBuffer, Pixel, Primitive};
+use num_traits::{NumCast, ToPrimitive};
+
+/// A `--corner-radius`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CornerRadius {
+    Pixels(u32),
+    /// Percent of the placed photo's shorter side.
+    Percent(f64),
+}
+
+impl CornerRadius {
+    /// The radius in pixels on a photo placed at `width` x `height`, at most half its
+    /// shorter side, which makes a pill of it.
+    pub fn resolve(self, width: u32, height: u32) -> f64 {
+        let short = width.min(height) as f64;
+        let radius = match self {
+            CornerRadius::Pixels(pixels) => pixels as f64,
+            CornerRadius::Percent(percent) => short * percent / 100.0,
+        };
+        radius.min(short / 2.0)
+    }
+}
+
+impl std::fmt::Display for CornerRadius {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CornerRadius::Pixels(pixels) => write!(f, "{} px", pixels),
+            CornerRadius::Percent(percent) => write!(f, "{}% of the shorter side", percent),
+        }
+    }
+}
+
+/// Parses a `--corner-radius`: pixels, with or without a "px" suffix, or a percentage.
+pub fn parse_corner_radius(s: &str) -> Result<CornerRadius, String> {
+    let invalid = || {
+        format!(
+            "invalid corner radius '{}' (expected pixels or a percentage, e.g. 24 or 5%)",
+            s
+        )
+    };
+    let s = s.trim();
+    if let Some(percent) = s.strip_suffix('%') {
+        return match percent.trim().parse::<f64>() {
+            Ok(percent) if percent.is_finite() && percent > 0.0 => {
+                Ok(CornerRadius::Percent(percent))
+            }
+            _ => Err(invalid()),
+        };
+    }
+    match s.strip_suffix("px").unwrap_or(s).trim().parse::<u32>() {
+        Ok(pixels) if pixels > 0 => Ok(CornerRadius::Pixels(pixels)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Returns the parts of `canvas` under the corner areas of an image at `placement`, to be
+/// handed to `round` once the image is on the canvas. Top left, top right, bottom left,
+/// bottom right.
+pub fn backgrounds<P>(
+    canvas: &ImageBuffer<P, Vec<P::Subpixel>>,
+    placement: Placement,
+    radius: CornerRadius,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    let size = corner_size(placement, radius);
+    corner_origins(placement, size)
+        .map(|(x, y)| imageops::crop_imm(canvas, x, y, size, size).to_image())
+        .collect()
+}
+
+/// Rounds the corners of the image at `placement` on `canvas`, mixing them with the
+/// `backgrounds` that were under them.
+pub fn round<P: Pixel>(
+    canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    backgrounds: &[ImageBuffer<P, Vec<P::Subpixel>>],
+    placement: Placement,
+    radius: CornerRadius,
+) {
+    let size = corner_size(placement, radius);
+    let radius = radius.resolve(placement.width, placement.height);
+    for ((x0, y0), background) in corner_origins(placement, size).zip(backgrounds) {
+        for (x, y, under) in background.enumerate_pixels() {
+            let (canvas_x, canvas_y) = (x0 + x, y0 + y);
+            let coverage = coverage(
+                (canvas_x - placement.x) as f64 + 0.5,
+                (canvas_y - placement.y) as f64 + 0.5,
+                placement.width as f64,
+                placement.height as f64,
+                radius,
+            );
+            if coverage >= 1.0 {
+                continue;
+            }
+            let photo = *canvas.get_pixel(canvas_x, canvas_y);
+            canvas.put_pixel(canvas_x, canvas_y, mix(*under, photo, coverage));
+        }
+    }
+}
+
+/// How much of the pixel centered at `x`, `y` inside a `width` x `height` rectangle with
+/// corners of `radius` the rectangle covers, from 0.0 to 1.0.
+pub fn coverage(x: f64, y: f64, width: f64, height: f64, radius: f64) -> f64 {
+    // How far the pixel's center lies into the corner, from the rectangle's two edges.
+    let dx = radius - x.min(width - x);
+    let dy = radius - y.min(height - y);
+    if dx <= 0.0 || dy <= 0.0 {
+        return 1.0;
+    }
+    (radius - dx.hypot(dy) + 0.5).clamp(0.0, 1.0)
+}
+
+/// `photo` over `under` at `coverage`: it is laid over with its alpha scaled down if the
+/// pixels have alpha, and the two are mixed if they don't.
+pub fn mix<P: Pixel>(mut under: P, photo: P, coverage: f64) -> P {
+    let value = |sample: P::Subpixel| sample.to_f64().unwrap_or(0.0);
+    if P::HAS_ALPHA {
+        let photo = photo.map_with_alpha(|sample| sample, |alpha| sample(value(alpha) * coverage));
+        under.blend(&photo);
+    } else {
+        under.apply2(&photo, |under, photo| {
+            sample(value(under) + (value(photo) - value(under)) * coverage)
+        });
+    }
+    under
+}
+
+/// `value` as a sample, rounded for integer samples.
+fn sample<S: Primitive>(value: f64) -> S {
+    let value = match S::DEFAULT_MAX_VALUE.to_f64().unwrap_or(255.0) > 1.0 {
+        true => value.round(),
+        false => value,
+    };
+    <S as NumCast>::from(value).unwrap_or(S::DEFAULT_MIN_VALUE)
+}
+
+/// Side of the square at each corner that rounding touches.
+fn corner_size(placement: Placement, radius: CornerRadius) -> u32 {
+    let radius = radius.resolve(placement.width, placement.height);
+    (radius.ceil() as u32)
+        .min(placement.width / 2)
+        .min(placement.height / 2)
+}
+
+fn corner_origins(placement: Placement, size: u32) -> impl Iterator<Item = (u32, u32)> {
+    let right = placement.x + placement.width - size;
+    let bottom = placement.y + placement.height - size;
+    [
+        (placement.x, placement.y),
+        (right, placement.y),
+        (placement.x, bottom),
+        (right, bottom),
+    ]
+    .into_iter()
+}
@@ -0,0 +1,153 @@
+//! `--rename-by-date`: output file names built from when the image was taken.
+//!
+//! The pattern is a small strftime subset, parsed once at startup. Dates are written as the
+//! camera recorded them; see `capture_date` for how times without an offset are read.
+//! Images taken in the same second get `-1`, `-2`, ... appended when their output paths
+//! are settled.
+
+use crate::capture_date::{civil_from_days, SECS_PER_DAY};
+use std::fmt;
+
+/// Pattern of `--rename-by-date` without a value, e.g. `2024-06-01_14-32-11`.
+pub const DEFAULT_DATE_PATTERN: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// The specifiers a pattern may use, with the number of digits each writes.
+const SPECIFIERS: &[(char, usize)] = &[
+    ('Y', 4),
+    ('y', 2),
+    ('m', 2),
+    ('d', 2),
+    ('H', 2),
+    ('M', 2),
+    ('S', 2),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// A specifier letter of `SPECIFIERS`.
+    Specifier(char),
+}
+
+#[derive(Clone, Debug)]
+pub struct DatePattern {
+    parts: Vec<Part>,
+}
+
+/// Parses and validates a `--rename-by-date` pattern.
+pub fn parse_date_pattern(s: &str) -> Result<DatePattern, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => literal.push('%'),
+            Some(spec) if SPECIFIERS.iter().any(|&(known, _)| known == spec) => {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(Part::Specifier(spec));
+            }
+            Some(spec) => {
+                return Err(format!(
+                    "unknown date specifier %{} (expected %Y, %y, %m, %d, %H, %M, %S or %%)",
+                    spec
+                ))
+            }
+            None => return Err("date pattern ends in a lone '%'".to_string()),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    if !parts.iter().any(|p| matches!(p, Part::Specifier(_))) {
+        return Err("date pattern needs at least one specifier such as %Y".to_string());
+    }
+    if parts
+        .iter()
+        .any(|p| matches!(p, Part::Literal(l) if l.contains(['/', '\\'])))
+    {
+        return Err("date pattern can't contain path separators".to_string());
+    }
+    Ok(DatePattern { parts })
+}
+
+impl DatePattern {
+    /// Formats `secs` since the Unix epoch, without an extension.
+    pub fn render(&self, secs: i64) -> String {
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let time = secs.rem_euclid(SECS_PER_DAY);
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Specifier(spec) => {
+                    let value = match spec {
+                        'Y' => year,
+                        'y' => year.rem_euclid(100),
+                        'm' => month,
+                        'd' => day,
+                        'H' => time / 3_600,
+                        'M' => time / 60 % 60,
+                        _ => time % 60,
+                    };
+                    name.push_str(&format!("{:0width$}", value, width = digits(*spec)));
+                }
+            }
+        }
+        name
+    }
+
+    /// Returns true if `stem` could have been rendered from this pattern, with or without a
+    /// `-N` disambiguator, which is how earlier outputs are recognised.
+    pub fn matches(&self, stem: &str) -> bool {
+        if matches_from(&self.parts, stem) {
+            return true;
+        }
+        stem.rsplit_once('-').is_some_and(|(stem, n)| {
+            !n.is_empty()
+                && n.bytes().all(|b| b.is_ascii_digit())
+                && matches_from(&self.parts, stem)
+        })
+    }
+}
+
+fn digits(spec: char) -> usize {
+    SPECIFIERS
+        .iter()
+        .find(|&&(known, _)| known == spec)
+        .map_or(2, |&(_, digits)| digits)
+}
+
+fn matches_from(parts: &[Part], text: &str) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return text.is_empty();
+    };
+    match part {
+        Part::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| matches_from(rest, text)),
+        Part::Specifier(spec) => {
+            let width = digits(*spec);
+            text.get(..width)
+                .is_some_and(|value| value.bytes().all(|b| b.is_ascii_digit()))
+                && matches_from(rest, &text[width..])
+        }
+    }
+}
+
+impl fmt::Display for DatePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => write!(f, "{}", literal.replace('%', "%%"))?,
+                Part::Specifier(spec) => write!(f, "%{}", spec)?,
+            }
+        }
+        Ok(())
+    }
+}
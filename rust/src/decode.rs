@@ -0,0 +1,659 @@
+//! Decoding of input files into pixel buffers.
+//!
+//! Most formats go through the `image` crate. Formats it can't read are handled here behind
+//! cargo features, so the rest of the pipeline only ever sees a `DynamicImage`.
+//!
+//! Images come out upright: the rotation or mirroring an EXIF Orientation tag asks for, as
+//! phones record for photos held in portrait, is applied to the pixels, so they are classed
+//! and bordered the way they're viewed. `--rotate` turns them further, for scans and other
+//! images without EXIF to go by. Dimensions read from headers are turned to match.
+
+use crate::Config;
+use clap::ValueEnum;
+use image::metadata::Orientation;
+use image::{
+    imageops, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, RgbImage, RgbaImage,
+};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Input formats whose decoder is behind an optional cargo feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptionalCodec {
+    Heif,
+    Avif,
+    Raw,
+    Svg,
+}
+
+impl OptionalCodec {
+    /// Returns the codec needed for `path`, judging by its extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "heic" | "heif" => Some(OptionalCodec::Heif),
+            "avif" => Some(OptionalCodec::Avif),
+            "cr2" | "nef" | "arw" | "dng" | "raf" | "orf" | "rw2" | "pef" => {
+                Some(OptionalCodec::Raw)
+            }
+            "svg" | "svgz" => Some(OptionalCodec::Svg),
+            _ => None,
+        }
+    }
+
+    /// Returns true if support for this codec was compiled in.
+    pub fn enabled(self) -> bool {
+        match self {
+            OptionalCodec::Heif => cfg!(feature = "heif"),
+            OptionalCodec::Avif => cfg!(feature = "avif"),
+            OptionalCodec::Raw => cfg!(feature = "raw"),
+            OptionalCodec::Svg => cfg!(feature = "svg"),
+        }
+    }
+
+    /// Explains how to get support for this codec when its feature is off.
+    pub fn disabled_message(self) -> &'static str {
+        match self {
+            OptionalCodec::Heif => {
+                "HEIC/HEIF support not compiled in, rebuild with --features heif"
+            }
+            OptionalCodec::Avif => "AVIF support not compiled in, rebuild with --features avif",
+            OptionalCodec::Raw => "RAW support not compiled in, rebuild with --features raw",
+            OptionalCodec::Svg => "SVG support not compiled in, rebuild with --features svg",
+        }
+    }
+}
+
+/// `--rotate`: how far to turn every image clockwise, after its EXIF orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Clockwise90,
+    #[value(name = "180")]
+    Clockwise180,
+    #[value(name = "270")]
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Returns true if the rotation swaps width and height.
+    pub fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+
+    /// Turns a frame of an animation, which doesn't go through `turn_upright`.
+    pub fn turn_frame(self, frame: RgbaImage) -> RgbaImage {
+        match self {
+            Rotation::None => frame,
+            Rotation::Clockwise90 => imageops::rotate90(&frame),
+            Rotation::Clockwise180 => imageops::rotate180(&frame),
+            Rotation::Clockwise270 => imageops::rotate270(&frame),
+        }
+    }
+}
+
+/// Turns decoded pixels the way `orientation`, from the image's EXIF, says, then by
+/// `--rotate`.
+pub fn turn_upright(img: &mut DynamicImage, orientation: Orientation, config: &Config) {
+    img.apply_orientation(orientation);
+    match config.rotation {
+        Rotation::None => {}
+        Rotation::Clockwise90 => *img = img.rotate90(),
+        Rotation::Clockwise180 => img.apply_orientation(Orientation::Rotate180),
+        Rotation::Clockwise270 => *img = img.rotate270(),
+    }
+}
+
+/// Decodes an image, detecting the format from its contents rather than trusting the extension,
+/// and turns it upright (see `turn_upright`).
+///
+/// Animated formats decode to their first frame. High bit depth sources (16-bit TIFF/PNG,
+/// 10-bit AVIF) decode at full precision and are narrowed by the caller. Vector images are
+/// rendered at the size they'll have on the canvas, which depends on `config`.
+pub fn decode_image(
+    path: &Path,
+    config: &Config,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let (mut img, orientation) = decode_image_as_stored(path, config)?;
+    turn_upright(&mut img, orientation, config);
+    Ok(img)
+}
+
+/// Like `decode_image`, but leaves the pixels as they are stored and returns the orientation
+/// to pass to `turn_upright`, for `--salvage`, which works in the order they were stored in.
+///
+/// HEIF and RAW decoders turn their images upright themselves.
+pub fn decode_image_as_stored(
+    path: &Path,
+    config: &Config,
+) -> Result<(DynamicImage, Orientation), Box<dyn std::error::Error>> {
+    let upright = |img| (img, Orientation::NoTransforms);
+    match OptionalCodec::for_path(path) {
+        Some(codec) if !codec.enabled() => return Err(codec.disabled_message().into()),
+        Some(OptionalCodec::Heif) => return decode_heif(path, config).map(upright),
+        Some(OptionalCodec::Raw) => return decode_raw(path, config).map(upright),
+        Some(OptionalCodec::Svg) => return decode_svg(path, config).map(upright),
+        _ => {}
+    }
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    let Some(format) = reader.format() else {
+        return Err("unsupported image format (the decoder doesn't recognise this file)".into());
+    };
+    reader.limits(decoder_limits(config));
+    let mut decoder = reader.into_decoder()?;
+    check_pixel_limit(decoder.dimensions(), config)?;
+    let orientation = orientation(&mut decoder);
+    if format == ImageFormat::Jpeg {
+        if let Some(img) = decode_cmyk_jpeg(&std::fs::read(path)?)? {
+            return Ok((img, orientation));
+        }
+    }
+    Ok((DynamicImage::from_decoder(decoder)?, orientation))
+}
+
+/// Decodes an image held in memory, detecting the format from its magic bytes.
+///
+/// Used for piped input, so formats behind optional codecs (HEIC/HEIF) aren't available.
+pub fn decode_bytes(
+    data: &[u8],
+    config: &Config,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    let Some(format) = reader.format() else {
+        return Err("unsupported image format (the decoder doesn't recognise this data)".into());
+    };
+    reader.limits(decoder_limits(config));
+    let mut decoder = reader.into_decoder()?;
+    check_pixel_limit(decoder.dimensions(), config)?;
+    let orientation = orientation(&mut decoder);
+    let cmyk = match format {
+        ImageFormat::Jpeg => decode_cmyk_jpeg(data)?,
+        _ => None,
+    };
+    let mut img = match cmyk {
+        Some(img) => img,
+        None => DynamicImage::from_decoder(decoder)?,
+    };
+    turn_upright(&mut img, orientation, config);
+    Ok(img)
+}
+
+/// The orientation an image's EXIF asks for; upright if it has none or it can't be read.
+fn orientation(decoder: &mut impl ImageDecoder) -> Orientation {
+    decoder.orientation().unwrap_or(Orientation::NoTransforms)
+}
+
+/// Reads an image's dimensions from its header, without decoding any pixels, swapped if its
+/// EXIF Orientation or `--rotate` turns it on its side.
+///
+/// Returns `None` for files whose header the `image` crate can't read, and for RAW files,
+/// which look like TIFFs whose first image is the thumbnail.
+pub fn header_dimensions(path: &Path, config: &Config) -> Option<(u32, u32)> {
+    match OptionalCodec::for_path(path) {
+        Some(OptionalCodec::Raw) => return None,
+        Some(OptionalCodec::Svg) => {
+            let (width, height) = svg_size(path)?;
+            return match config.rotation.is_sideways() {
+                true => Some((height, width)),
+                false => Some((width, height)),
+            };
+        }
+        _ => {}
+    }
+    let mut decoder = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    let sideways = matches!(
+        orientation(&mut decoder),
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    );
+    match sideways != config.rotation.is_sideways() {
+        true => Some((height, width)),
+        false => Some((width, height)),
+    }
+}
+
+/// Returns true if an image of `width` x `height` is over `--max-megapixels`.
+pub fn exceeds_pixel_limit((width, height): (u32, u32), config: &Config) -> bool {
+    config
+        .max_pixels
+        .is_some_and(|max| width as u64 * height as u64 > max)
+}
+
+/// Rejects images over `--max-megapixels` from their header, before any pixel memory is
+/// allocated.
+fn check_pixel_limit(dimensions: (u32, u32), config: &Config) -> Result<(), String> {
+    if !exceeds_pixel_limit(dimensions, config) {
+        return Ok(());
+    }
+    let (width, height) = dimensions;
+    Err(format!(
+        "image is {}x{} ({:.0} megapixels), over --max-megapixels",
+        width,
+        height,
+        width as f64 * height as f64 / 1e6
+    ))
+}
+
+/// Allocation limits for the decoders, sized for the largest image `--max-megapixels` lets
+/// through at the widest pixel layout (four 32-bit float channels).
+fn decoder_limits(config: &Config) -> Limits {
+    let mut limits = Limits::no_limits();
+    limits.max_alloc = config.max_pixels.map(|max| max.saturating_mul(16));
+    limits
+}
+
+/// Decodes a CMYK or YCCK JPEG through its embedded CMYK ICC profile.
+///
+/// Returns `None` for any other JPEG, or when the profile is missing or unusable; those
+/// go through the `image` crate, which converts CMYK to RGB naively. Like that decoder,
+/// this assumes the Adobe convention of storing inverted ink values, which is what
+/// Photoshop writes.
+fn decode_cmyk_jpeg(data: &[u8]) -> Result<Option<DynamicImage>, Box<dyn std::error::Error>> {
+    use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::DecoderOptions;
+    use zune_jpeg::JpegDecoder;
+
+    let options = DecoderOptions::default()
+        .set_strict_mode(false)
+        .set_max_width(usize::MAX)
+        .set_max_height(usize::MAX);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    decoder.decode_headers()?;
+    let color_space = match decoder.input_colorspace() {
+        Some(cs @ (ColorSpace::CMYK | ColorSpace::YCCK)) => cs,
+        _ => return Ok(None),
+    };
+    let Some(profile) = decoder
+        .icc_profile()
+        .and_then(|icc| ColorProfile::new_from_slice(&icc).ok())
+        .filter(|profile| profile.color_space == DataColorSpace::Cmyk)
+    else {
+        return Ok(None);
+    };
+    let Ok(transform) = profile.create_transform_8bit(
+        Layout::Rgba,
+        &ColorProfile::new_srgb(),
+        Layout::Rgb,
+        TransformOptions::default(),
+    ) else {
+        return Ok(None);
+    };
+
+    // Ask for the raw four channels so no naive conversion happens inside the decoder.
+    decoder.set_options(decoder.options().jpeg_set_out_colorspace(color_space));
+    let mut inks = decoder.decode()?;
+    let (width, height) = decoder.dimensions().ok_or("JPEG has no dimensions")?;
+    for px in inks.chunks_exact_mut(4) {
+        if color_space == ColorSpace::YCCK {
+            // YCCK stores the inverted C, M and Y as YCbCr, so undoing that gives the inks.
+            let (y, cb, cr) = (px[0] as f32, px[1] as f32 - 128.0, px[2] as f32 - 128.0);
+            px[0] = (y + 1.402 * cr).round().clamp(0.0, 255.0) as u8;
+            px[1] = (y - 0.344_136 * cb - 0.714_136 * cr)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+            px[2] = (y + 1.772 * cb).round().clamp(0.0, 255.0) as u8;
+        } else {
+            px[0] = 255 - px[0];
+            px[1] = 255 - px[1];
+            px[2] = 255 - px[2];
+        }
+        px[3] = 255 - px[3];
+    }
+
+    let mut rgb = vec![0u8; width * height * 3];
+    transform.transform(&inks, &mut rgb)?;
+    let buffer = RgbImage::from_raw(width as u32, height as u32, rgb)
+        .ok_or("CMYK JPEG decoded to unexpected dimensions")?;
+    Ok(Some(DynamicImage::ImageRgb8(buffer)))
+}
+
+/// Decodes the primary image of a HEIC/HEIF file.
+///
+/// libheif applies the container's rotation and mirroring during decode, so the result
+/// is already upright.
+#[cfg(feature = "heif")]
+fn decode_heif(path: &Path, config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let path_str = path.to_str().ok_or("HEIF path is not valid UTF-8")?;
+    let context = HeifContext::read_from_file(path_str)?;
+    let handle = context.primary_image_handle()?;
+    check_pixel_limit((handle.width(), handle.height()), config)?;
+    let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or("HEIF decoder returned no interleaved RGBA plane")?;
+
+    // Rows may be padded, so copy them out one at a time.
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    let buffer = image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or("HEIF plane has unexpected dimensions")?;
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_path: &Path, _config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    Err(OptionalCodec::Heif.disabled_message().into())
+}
+
+/// Develops a camera RAW file into an 8-bit sRGB image.
+///
+/// imagepipe's default pipeline demosaics, applies the camera's white balance and color
+/// matrix and a base tone curve, and rotates the result by the orientation the camera
+/// recorded, so it comes out upright like an in-camera JPEG.
+#[cfg(feature = "raw")]
+fn decode_raw(path: &Path, config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    use imagepipe::{ImageSource, Pipeline};
+
+    // Decode with rawloader directly so a file it can't read isn't handed to `image`.
+    let raw = rawloader::decode_file(path)?;
+    // rawloader reads the sensor data with the header, but developing it takes several
+    // times more memory, so the limit still holds before that.
+    check_pixel_limit((raw.width as u32, raw.height as u32), config)?;
+    let mut pipeline = Pipeline::new_from_source(ImageSource::Raw(raw))?;
+    let developed = pipeline.output_8bit(None)?;
+    let buffer = RgbImage::from_raw(
+        developed.width as u32,
+        developed.height as u32,
+        developed.data,
+    )
+    .ok_or("RAW pipeline returned unexpected dimensions")?;
+    Ok(DynamicImage::ImageRgb8(buffer))
+}
+
+#[cfg(not(feature = "raw"))]
+fn decode_raw(_path: &Path, _config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    Err(OptionalCodec::Raw.disabled_message().into())
+}
+
+/// Rasterizes an SVG straight at its scaled size on the canvas, so edges stay as sharp as
+/// the output resolution allows instead of being softened by a later downscale.
+///
+/// Transparent areas stay transparent and are composited onto the border like any other
+/// image with alpha.
+#[cfg(feature = "svg")]
+fn decode_svg(path: &Path, config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    use resvg::tiny_skia::{Pixmap, Transform};
+
+    let tree = svg_tree(path)?;
+    let size = tree.size();
+    let natural_width = (size.width().round() as u32).max(1);
+    let natural_height = (size.height().round() as u32).max(1);
+    check_pixel_limit((natural_width, natural_height), config)?;
+    let upright = match config.rotation.is_sideways() {
+        true => (natural_height, natural_width),
+        false => (natural_width, natural_height),
+    };
+    // Drawn as it is stored and turned by `--rotate` after.
+    let (width, height) = match (
+        svg_render_size(upright, config),
+        config.rotation.is_sideways(),
+    ) {
+        ((width, height), true) => (height, width),
+        (size, false) => size,
+    };
+    let mut pixmap =
+        Pixmap::new(width.max(1), height.max(1)).ok_or("SVG renders to an empty image")?;
+    let transform = Transform::from_scale(
+        pixmap.width() as f32 / size.width(),
+        pixmap.height() as f32 / size.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia works in premultiplied alpha; the rest of the pipeline expects straight alpha.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let buffer = image::RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixels)
+        .ok_or("SVG pixmap has unexpected dimensions")?;
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+#[cfg(not(feature = "svg"))]
+fn decode_svg(_path: &Path, _config: &Config) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    Err(OptionalCodec::Svg.disabled_message().into())
+}
+
+/// The size an SVG that is `upright` in size, once turned by `--rotate`, is drawn at for an
+/// output of `config`: the size it is scaled to on that canvas, so it is never resampled.
+pub fn svg_render_size(upright: (u32, u32), config: &Config) -> (u32, u32) {
+    // Vectors stay sharp at any size, so --no-upscale doesn't hold them to their own.
+    let config = Config {
+        max_upscale: None,
+        ..config.clone()
+    };
+    let fit = crate::fit(upright.0, upright.1, &config);
+    (fit.scaled_width, fit.scaled_height)
+}
+
+/// Parses an SVG, with its images resolved from its folder and text set in system fonts.
+#[cfg(feature = "svg")]
+fn svg_tree(path: &Path) -> Result<resvg::usvg::Tree, Box<dyn std::error::Error>> {
+    use resvg::usvg::{Options, Tree};
+
+    let mut options = Options {
+        resources_dir: path.parent().map(Path::to_path_buf),
+        ..Options::default()
+    };
+    options.fontdb_mut().load_system_fonts();
+    Ok(Tree::from_data(&std::fs::read(path)?, &options)?)
+}
+
+/// The size an SVG declares, rounded to pixels, or `None` if it can't be parsed.
+#[cfg(feature = "svg")]
+fn svg_size(path: &Path) -> Option<(u32, u32)> {
+    let size = svg_tree(path).ok()?.size();
+    Some((
+        (size.width().round() as u32).max(1),
+        (size.height().round() as u32).max(1),
+    ))
+}
+
+#[cfg(not(feature = "svg"))]
+fn svg_size(_path: &Path) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{config, exif, temp_dir};
+    use exif::{Field, In, Tag, Value};
+    use image::codecs::png::PngEncoder;
+    use image::{ExtendedColorType, ImageEncoder};
+    use std::path::PathBuf;
+
+    /// A 3x2 image whose every pixel has a color of its own.
+    fn upright() -> RgbImage {
+        RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8 * 100, y as u8 * 200, 50]))
+    }
+
+    /// Writes `upright()` the way a camera stores it beside EXIF Orientation `orientation`,
+    /// as a PNG with that EXIF, and returns its path.
+    fn stored(orientation: u16) -> PathBuf {
+        let img = upright();
+        let (w, h) = (img.width(), img.height());
+        let sideways = orientation >= 5;
+        let (stored_w, stored_h) = if sideways { (h, w) } else { (w, h) };
+        // Which pixel of the upright image each stored one shows, by where the EXIF spec
+        // puts the stored 0th row and column.
+        let stored = RgbImage::from_fn(stored_w, stored_h, |x, y| {
+            let (ux, uy) = match orientation {
+                1 => (x, y),
+                2 => (w - 1 - x, y),
+                3 => (w - 1 - x, h - 1 - y),
+                4 => (x, h - 1 - y),
+                5 => (y, x),
+                6 => (w - 1 - y, x),
+                7 => (w - 1 - y, h - 1 - x),
+                _ => (y, h - 1 - x),
+            };
+            *img.get_pixel(ux, uy)
+        });
+        let mut png = Vec::new();
+        let mut encoder = PngEncoder::new(&mut png);
+        let field = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![orientation]),
+        };
+        encoder.set_exif_metadata(exif(&[field])).unwrap();
+        encoder
+            .write_image(stored.as_raw(), stored_w, stored_h, ExtendedColorType::Rgb8)
+            .unwrap();
+        let path = temp_dir(&format!("orientation-{}", orientation)).join("photo.png");
+        std::fs::write(&path, png).unwrap();
+        path
+    }
+
+    /// An ICC profile for ideal inks: each filters out one of red, green and blue, black all
+    /// of them.
+    fn cmyk_profile() -> Vec<u8> {
+        use moxcms::{
+            ColorProfile, DataColorSpace, LutDataType, LutStore, LutType, LutWarehouse, Matrix3d,
+            ProfileClass, Xyzd,
+        };
+
+        let filters = [
+            [0.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+        ];
+        // Linear sRGB to the D50 XYZ of the profile connection space.
+        let to_xyz = [
+            [0.4360747, 0.3850649, 0.1430804],
+            [0.2225045, 0.7168786, 0.0606169],
+            [0.0139322, 0.0971045, 0.7141733],
+        ];
+        let mut clut = Vec::new();
+        // The corners of the CMYK cube, the first ink varying slowest.
+        for corner in 0..16 {
+            let mut rgb = [1.0f64; 3];
+            for (ink, filter) in filters.iter().enumerate() {
+                if corner >> (3 - ink) & 1 == 1 {
+                    rgb = [0, 1, 2].map(|i| rgb[i] * filter[i]);
+                }
+            }
+            for row in to_xyz {
+                let xyz: f64 = (0..3).map(|i| row[i] * rgb[i]).sum();
+                clut.push((xyz * 32768.0).round().min(65535.0) as u16);
+            }
+        }
+        let d50 = Xyzd {
+            x: 0.9642,
+            y: 1.0,
+            z: 0.8249,
+        };
+        let mut profile = ColorProfile::default();
+        profile.color_space = DataColorSpace::Cmyk;
+        profile.pcs = DataColorSpace::Xyz;
+        profile.profile_class = ProfileClass::OutputDevice;
+        profile.white_point = d50;
+        profile.media_white_point = Some(d50);
+        profile.lut_a_to_b_perceptual = Some(LutWarehouse::Lut(LutDataType {
+            num_input_channels: 4,
+            num_output_channels: 3,
+            num_clut_grid_points: 2,
+            matrix: Matrix3d {
+                v: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            },
+            num_input_table_entries: 2,
+            num_output_table_entries: 2,
+            input_table: LutStore::Store16([0, 65535].repeat(4)),
+            clut_table: LutStore::Store16(clut),
+            output_table: LutStore::Store16([0, 65535].repeat(3)),
+            lut_type: LutType::Lut16,
+        }));
+        profile.encode().unwrap()
+    }
+
+    #[test]
+    fn cmyk_jpegs_keep_their_colors() {
+        use jpeg_encoder::{ColorType, Encoder};
+
+        // Full cyan ink on the left half, no ink on the right.
+        let inks: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| match i % 16 < 8 {
+                true => [255, 0, 0, 0],
+                false => [0, 0, 0, 0],
+            })
+            .collect();
+        for color_type in [ColorType::Cmyk, ColorType::CmykAsYcck] {
+            let mut jpeg = Vec::new();
+            let mut encoder = Encoder::new(&mut jpeg, 100);
+            encoder.add_icc_profile(&cmyk_profile()).unwrap();
+            encoder.encode(&inks, 16, 16, color_type).unwrap();
+            let img = decode_cmyk_jpeg(&jpeg)
+                .unwrap()
+                .expect("decoded through the profile");
+            let img = img.to_rgb8();
+            // Read inverted, no ink would come out black and cyan ink red.
+            let near = |x, y, expected: [u8; 3]| {
+                let pixel = img.get_pixel(x, y).0;
+                let close = (0..3).all(|i| pixel[i].abs_diff(expected[i]) <= 16);
+                assert!(close, "{:?}: {:?} at {},{}", color_type, pixel, x, y);
+            };
+            near(2, 8, [0, 255, 255]);
+            near(13, 8, [255, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn every_exif_orientation_comes_out_upright() {
+        let config = config(&[]);
+        for orientation in 1..=8 {
+            let path = stored(orientation);
+            let img = decode_image(&path, &config).unwrap();
+            assert_eq!(img.to_rgb8(), upright(), "Orientation {}", orientation);
+            assert_eq!(
+                header_dimensions(&path, &config),
+                Some((3, 2)),
+                "Orientation {}",
+                orientation
+            );
+        }
+    }
+
+    #[test]
+    fn rotation_turns_after_the_exif_orientation() {
+        let config = config(&["--rotate", "90"]);
+        for orientation in 1..=8 {
+            let path = stored(orientation);
+            let img = decode_image(&path, &config).unwrap();
+            assert_eq!(
+                img.to_rgb8(),
+                imageops::rotate90(&upright()),
+                "Orientation {}",
+                orientation
+            );
+            assert_eq!(
+                header_dimensions(&path, &config),
+                Some((2, 3)),
+                "Orientation {}",
+                orientation
+            );
+        }
+    }
+}
@@ -0,0 +1,804 @@
+//! File discovery: turns the command-line inputs into a flat worklist of images.
+//!
+//! Discovery never decodes anything; it only decides which files will be processed and
+//! where their outputs go, so the processing loop is the same however the list was built.
+
+use crate::archive::is_zip;
+use crate::borderignore::IgnoreFiles;
+use crate::capture_date::{
+    capture_time, format_date, format_date_time, system_secs, DateSource, MissingDate,
+};
+use crate::decode::{exceeds_pixel_limit, header_dimensions, OptionalCodec};
+use crate::fetch::{file_name_from_url, is_url};
+use crate::output::{is_output_name, output_root};
+use crate::{format_size, Args, Config, Orientation};
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where the images of one input come from.
+pub enum Source {
+    /// A folder to scan.
+    Folder(PathBuf),
+    /// A single image file.
+    File(PathBuf),
+    /// A text file listing image paths, one per line.
+    List(PathBuf),
+    /// Image paths read from standard input, newline- or NUL-delimited.
+    Stdin { nul: bool },
+    /// An http(s) URL to download.
+    Url(String),
+    /// A ZIP archive whose image entries are processed in place, see `archive`.
+    Zip(PathBuf),
+}
+
+/// One input passed on the command line.
+pub struct Input {
+    pub label: String,
+    pub source: Source,
+}
+
+/// Order in which the images of a scanned folder are processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Path relative to the input folder.
+    Name,
+    /// File size, smallest first.
+    Size,
+    /// Modification time, oldest first.
+    Mtime,
+}
+
+/// A single image to process, with the folder its output location is derived from.
+pub struct Job {
+    /// Index of the input this job came from.
+    pub input: usize,
+    pub path: PathBuf,
+    pub root: PathBuf,
+    /// For URL inputs, the URL to download; `path` is then the local name under `root`
+    /// that output naming works from, and doesn't exist.
+    pub url: Option<String>,
+}
+
+impl Job {
+    fn for_url(input: usize, url: &str, root: &Path) -> Self {
+        Job {
+            input,
+            path: root.join(file_name_from_url(url)),
+            root: root.to_path_buf(),
+            url: Some(url.to_string()),
+        }
+    }
+}
+
+/// A listed entry that can't be processed, reported as a failure of its input.
+pub struct Rejected {
+    pub input: usize,
+    pub label: String,
+    pub reason: String,
+}
+
+/// Why a discovered file was deliberately left out of the worklist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// Matched an `--exclude` pattern.
+    Excluded,
+    /// A listed file that no `--pattern` matches.
+    NotMatched,
+    /// Matched a pattern of a `.borderignore` file.
+    BorderIgnored,
+    /// A dotfile or AppleDouble `._*` file, and `--include-hidden` is off.
+    Hidden,
+    /// Named like one of our outputs, and `--reprocess-outputs` is off.
+    AlreadyOutput,
+    /// Needs a decoder whose cargo feature isn't compiled in.
+    CodecDisabled(OptionalCodec),
+    /// A symlink, and `--follow-symlinks` is off.
+    Symlink,
+    /// A symlink whose target doesn't exist.
+    DanglingSymlink,
+    /// Bigger on disk than `--max-file-size`.
+    TooLarge,
+    /// More pixels than `--max-megapixels`.
+    TooManyPixels,
+    /// Smaller than `--min-width`/`--min-height`.
+    TooSmall,
+    /// Not of the orientation asked for with `--only`.
+    WrongOrientation,
+    /// Last modified before the `--modified-within` cutoff.
+    NotRecent,
+    /// Older than the `--newest` images that were kept.
+    NotNewest,
+    /// Taken before `--since` or after `--until`.
+    OutsideDateRange,
+    /// Has no date to check `--since`/`--until` against, and `--missing-date exclude`.
+    NoDate,
+    /// Its output already exists, and `--force` is off.
+    OutputExists,
+    /// Its output is newer than it and was written with the same settings (`--incremental`).
+    UpToDate,
+    /// Another input is written to the same output, and `--on-collision skip`.
+    OutputCollision,
+    /// An original kept by `--in-place`.
+    Backup,
+    /// Already replaced by `--in-place`: its original is still kept as a backup.
+    BackedUp,
+}
+
+impl SkipReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            SkipReason::Excluded => "excluded by pattern",
+            SkipReason::NotMatched => "not matched by --pattern",
+            SkipReason::BorderIgnored => "listed in .borderignore",
+            SkipReason::Hidden => "hidden file",
+            SkipReason::AlreadyOutput => "already an output, use --reprocess-outputs",
+            SkipReason::CodecDisabled(codec) => codec.disabled_message(),
+            SkipReason::Symlink => "symlink, use --follow-symlinks",
+            SkipReason::DanglingSymlink => "dangling symlink",
+            SkipReason::TooLarge => "larger than --max-file-size",
+            SkipReason::TooManyPixels => "too large, over --max-megapixels",
+            SkipReason::TooSmall => "below the minimum dimensions",
+            SkipReason::WrongOrientation => "filtered out by --only",
+            SkipReason::NotRecent => "not modified within --modified-within",
+            SkipReason::NotNewest => "older than the --newest images, left untouched",
+            SkipReason::OutsideDateRange => "outside --since/--until",
+            SkipReason::NoDate => "no capture date, use --missing-date include",
+            SkipReason::OutputExists => "exists",
+            SkipReason::UpToDate => "up to date",
+            SkipReason::OutputCollision => "output name collision",
+            SkipReason::Backup => "original kept by --in-place",
+            SkipReason::BackedUp => "already bordered in place, --restore it first",
+        }
+    }
+}
+
+/// A file that was found but intentionally not processed.
+pub struct Skipped {
+    pub input: usize,
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    /// Extra context shown next to the reason, e.g. the size of a file over the limit.
+    pub detail: Option<String>,
+}
+
+/// The outcome of discovery across all inputs.
+#[derive(Default)]
+pub struct Worklist {
+    pub jobs: Vec<Job>,
+    pub skipped: Vec<Skipped>,
+    pub rejected: Vec<Rejected>,
+}
+
+/// Validates the positional, `-i`, `--files-from` and `--stdin` inputs, dropping duplicates
+/// that resolve to the same path.
+pub fn resolve_inputs(
+    args: &Args,
+    config: &Config,
+) -> Result<Vec<Input>, Box<dyn std::error::Error>> {
+    let paths: Vec<&PathBuf> = args.input.iter().chain(&args.input_flag).collect();
+    let use_stdin = args.stdin || args.null;
+    if paths.is_empty() && args.files_from.is_none() && !use_stdin {
+        return Err("Error: Input folder is required (pass as argument or use -i/--input)".into());
+    }
+
+    let mut seen = HashSet::new();
+    let mut inputs = Vec::new();
+    for path in paths {
+        if let Some(url) = path.to_str().filter(|p| is_url(p)) {
+            if seen.insert(path.clone()) {
+                inputs.push(Input {
+                    label: url.to_string(),
+                    source: Source::Url(url.to_string()),
+                });
+            }
+            continue;
+        }
+        if !path.exists() {
+            return Err(format!("Error: Input path does not exist: {}", path.display()).into());
+        }
+        if !seen.insert(path.canonicalize()?) {
+            continue;
+        }
+
+        let source = if path.is_file() && is_zip(path) {
+            Source::Zip(path.clone())
+        } else if path.is_file() {
+            if !is_supported_image(path, config) {
+                return Err(format!(
+                    "Error: Not a supported image extension (expected {}): {}",
+                    config.extensions.join(", "),
+                    path.display()
+                )
+                .into());
+            }
+            Source::File(path.clone())
+        } else {
+            Source::Folder(path.clone())
+        };
+        inputs.push(Input {
+            label: path.display().to_string(),
+            source,
+        });
+    }
+
+    if let Some(list) = &args.files_from {
+        if !list.is_file() {
+            return Err(format!("Error: File list does not exist: {}", list.display()).into());
+        }
+        inputs.push(Input {
+            label: list.display().to_string(),
+            source: Source::List(list.clone()),
+        });
+    }
+
+    if use_stdin {
+        inputs.push(Input {
+            label: "<stdin>".to_string(),
+            source: Source::Stdin { nul: args.null },
+        });
+    }
+    Ok(inputs)
+}
+
+/// Builds the worklist for all inputs, in input order.
+pub fn discover(inputs: &[Input], config: &Config) -> Result<Worklist, Box<dyn std::error::Error>> {
+    let mut worklist = Worklist::default();
+    for (index, input) in inputs.iter().enumerate() {
+        match &input.source {
+            Source::Folder(folder) => {
+                // Never walk back into our own output folder when it lives inside the input.
+                let output_folder = output_root(folder, config);
+                let mut scan = collect_images(folder, &output_folder, config)?;
+                scan.skipped.sort();
+                let mut images = Vec::new();
+                let mut ignores = IgnoreFiles::new(folder);
+                for (path, reason) in scan.skipped {
+                    worklist.skipped.push(Skipped {
+                        input: index,
+                        path,
+                        reason,
+                        detail: None,
+                    });
+                }
+                for path in scan.images {
+                    let ignored = ignores
+                        .ignoring(&path)
+                        .map_err(|e| format!("Error: {}", e))?;
+                    if let Some(ignore_file) = ignored {
+                        worklist.skipped.push(Skipped {
+                            input: index,
+                            path,
+                            reason: SkipReason::BorderIgnored,
+                            detail: Some(ignore_file.display().to_string()),
+                        });
+                        continue;
+                    }
+                    match screen_image(&path, folder, config) {
+                        Screened::Ignored => {}
+                        Screened::Skipped(reason, detail) => worklist.skipped.push(Skipped {
+                            input: index,
+                            path,
+                            reason,
+                            detail,
+                        }),
+                        Screened::Accepted => images.push(path),
+                    }
+                }
+                // Sorting after filtering keeps the order independent of what was skipped.
+                sort_images(&mut images, folder, config);
+                worklist.jobs.extend(images.into_iter().map(|path| Job {
+                    input: index,
+                    path,
+                    root: folder.clone(),
+                    url: None,
+                }));
+            }
+            // Named files are held to the same limits as the images of a folder.
+            Source::File(path) => match file_skip_reason(path, config) {
+                Some((reason, detail)) => worklist.skipped.push(Skipped {
+                    input: index,
+                    path: path.clone(),
+                    reason,
+                    detail,
+                }),
+                None => worklist.jobs.push(Job {
+                    input: index,
+                    path: path.clone(),
+                    root: parent_folder(path),
+                    url: None,
+                }),
+            },
+            Source::List(list) => {
+                let content = std::fs::read_to_string(list)?;
+                let base = parent_folder(list);
+                let entries = parse_path_list(&content, '\n', &base);
+                add_listed(&mut worklist, index, &input.label, entries, &base, config);
+            }
+            Source::Stdin { nul } => {
+                let mut content = String::new();
+                std::io::stdin().read_to_string(&mut content)?;
+                let delimiter = if *nul { '\0' } else { '\n' };
+                let entries = parse_path_list(&content, delimiter, Path::new(""));
+                add_listed(
+                    &mut worklist,
+                    index,
+                    &input.label,
+                    entries,
+                    Path::new("."),
+                    config,
+                );
+            }
+            // Downloaded into the current folder's output location.
+            Source::Url(url) => worklist.jobs.push(Job::for_url(index, url, Path::new("."))),
+            // Archives are read entry by entry while processing, not listed up front.
+            Source::Zip(_) => {}
+        }
+    }
+    if let Some(newest) = config.newest {
+        keep_newest(&mut worklist, newest);
+    }
+    Ok(worklist)
+}
+
+/// Moves all but the `newest` most recently modified file jobs of the worklist to its
+/// skipped entries, keeping the processing order of the rest. URL jobs are always kept.
+fn keep_newest(worklist: &mut Worklist, newest: usize) {
+    let mut by_age: Vec<(Option<SystemTime>, usize)> = worklist
+        .jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| job.url.is_none())
+        .map(|(index, job)| {
+            let mtime = std::fs::metadata(&job.path).and_then(|m| m.modified()).ok();
+            (mtime, index)
+        })
+        .collect();
+    by_age.sort_by(|a, b| b.cmp(a));
+    let older: HashSet<usize> = by_age
+        .iter()
+        .skip(newest)
+        .map(|&(_, index)| index)
+        .collect();
+
+    let jobs = std::mem::take(&mut worklist.jobs);
+    for (index, job) in jobs.into_iter().enumerate() {
+        if older.contains(&index) {
+            worklist.skipped.push(Skipped {
+                input: job.input,
+                path: job.path,
+                reason: SkipReason::NotNewest,
+                detail: None,
+            });
+        } else {
+            worklist.jobs.push(job);
+        }
+    }
+}
+
+/// What the per-file filters of a folder scan decided about one image.
+pub enum Screened {
+    /// Not matched by `--pattern`; left out without being reported.
+    Ignored,
+    Skipped(SkipReason, Option<String>),
+    Accepted,
+}
+
+/// Applies `--pattern`, `--exclude` and the shared skip rules to an image found under `folder`.
+pub fn screen_image(path: &Path, folder: &Path, config: &Config) -> Screened {
+    if !is_included(path, folder, config) {
+        return Screened::Ignored;
+    }
+    if is_excluded(path, folder, config) {
+        return Screened::Skipped(SkipReason::Excluded, None);
+    }
+    match file_skip_reason(path, config) {
+        Some((reason, detail)) => Screened::Skipped(reason, detail),
+        None => Screened::Accepted,
+    }
+}
+
+/// Checks explicitly listed paths, queueing the usable ones and rejecting the rest.
+///
+/// `--pattern` and `--exclude` match listed files relative to `base`, the folder the list's
+/// relative paths start from, as they would in a scan of that folder. Files they leave out
+/// are reported as skipped, as they were asked for by name. Listed URLs are queued as-is,
+/// with their outputs placed under `base`.
+fn add_listed(
+    worklist: &mut Worklist,
+    input: usize,
+    label: &str,
+    entries: Vec<(usize, PathBuf)>,
+    base: &Path,
+    config: &Config,
+) {
+    for (line, path) in entries {
+        if let Some(url) = path.to_str().filter(|p| is_url(p)) {
+            worklist.jobs.push(Job::for_url(input, url, base));
+            continue;
+        }
+        if path.is_file() && is_supported_image(&path, config) {
+            let skip = match screen_image(&path, base, config) {
+                Screened::Ignored => Some((SkipReason::NotMatched, None)),
+                Screened::Skipped(reason, detail) => Some((reason, detail)),
+                Screened::Accepted => None,
+            };
+            if let Some((reason, detail)) = skip {
+                worklist.skipped.push(Skipped {
+                    input,
+                    path,
+                    reason,
+                    detail,
+                });
+                continue;
+            }
+        }
+        let reason = if !path.exists() {
+            "path does not exist"
+        } else if !path.is_file() {
+            "not a file"
+        } else if !is_supported_image(&path, config) {
+            "not a supported image extension"
+        } else {
+            worklist.jobs.push(Job {
+                input,
+                root: parent_folder(&path),
+                path,
+                url: None,
+            });
+            continue;
+        };
+        worklist.rejected.push(Rejected {
+            input,
+            label: format!("{}:{}", label, line),
+            reason: format!("{} ({})", reason, path.display()),
+        });
+    }
+}
+
+/// Checks the per-file skip rules shared by folder scans, named files and path lists,
+/// returning the reason and any detail worth showing with it.
+///
+/// The size limit only needs file metadata, the pixel and dimension limits only the image
+/// header and the date range only the EXIF segment, so rejected files never reach the
+/// decoder. Files whose header can't be read are left for the decoder to report.
+fn file_skip_reason(path: &Path, config: &Config) -> Option<(SkipReason, Option<String>)> {
+    let is_previous_output = path
+        .file_name()
+        .map(|name| is_output_name(&name.to_string_lossy(), config))
+        .unwrap_or(false);
+    if is_previous_output && !config.reprocess_outputs {
+        return Some((SkipReason::AlreadyOutput, None));
+    }
+    if let Some(backup) = config.in_place {
+        if backup.holds(path) {
+            return Some((SkipReason::Backup, None));
+        }
+        if backup.path(path).exists() {
+            return Some((SkipReason::BackedUp, None));
+        }
+    }
+    if let Some(codec) = OptionalCodec::for_path(path).filter(|codec| !codec.enabled()) {
+        return Some((SkipReason::CodecDisabled(codec), None));
+    }
+    if let Some(limit) = config.max_file_size {
+        let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        if size > limit {
+            return Some((SkipReason::TooLarge, Some(format_size(size))));
+        }
+    }
+    let needs_dimensions = config.max_pixels.is_some()
+        || config.min_width > 0
+        || config.min_height > 0
+        || config.only.is_some();
+    if needs_dimensions {
+        if let Some((width, height)) = header_dimensions(path, config) {
+            if exceeds_pixel_limit((width, height), config) {
+                return Some((
+                    SkipReason::TooManyPixels,
+                    Some(format!("{}x{}", width, height)),
+                ));
+            }
+            if width < config.min_width || height < config.min_height {
+                return Some((SkipReason::TooSmall, Some(format!("{}x{}", width, height))));
+            }
+            if config
+                .only
+                .is_some_and(|only| Orientation::of(width, height, config) != only)
+            {
+                return Some((
+                    SkipReason::WrongOrientation,
+                    Some(format!("{}x{}", width, height)),
+                ));
+            }
+        }
+    }
+    if let Some(cutoff) = config.modified_after {
+        let modified = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(system_secs);
+        if let Some(modified) = modified.filter(|&modified| modified < cutoff) {
+            let detail = format!("modified {}", format_date_time(modified));
+            return Some((SkipReason::NotRecent, Some(detail)));
+        }
+    }
+    if config.since.is_some() || config.until.is_some() {
+        match capture_time(path) {
+            Some((time, source)) => {
+                let too_early = config.since.is_some_and(|since| time < since);
+                let too_late = config.until.is_some_and(|until| time > until);
+                if too_early || too_late {
+                    let verb = match source {
+                        DateSource::Exif => "taken",
+                        DateSource::Mtime => "modified",
+                    };
+                    let detail = format!("{} {}", verb, format_date(time));
+                    return Some((SkipReason::OutsideDateRange, Some(detail)));
+                }
+            }
+            None if config.missing_date == MissingDate::Exclude => {
+                return Some((SkipReason::NoDate, None));
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+/// Sorts the images of a folder by `--sort-by`, ties broken by relative path, and
+/// reverses the result with `--reverse`.
+fn sort_images(images: &mut [PathBuf], folder: &Path, config: &Config) {
+    let rel = |path: &PathBuf| path.strip_prefix(folder).unwrap_or(path).to_path_buf();
+    match config.sort_by {
+        SortKey::Name => images.sort_by_cached_key(rel),
+        SortKey::Size => images.sort_by_cached_key(|path| {
+            let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            (size, rel(path))
+        }),
+        SortKey::Mtime => images.sort_by_cached_key(|path| {
+            let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            (mtime, rel(path))
+        }),
+    }
+    if config.reverse {
+        images.reverse();
+    }
+}
+
+/// Returns the folder containing `path`, using "." for bare file names.
+fn parent_folder(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Splits a path list into entries, resolving relative paths against `base`.
+///
+/// Newline-delimited lists skip blank lines and `#` comments and trim surrounding
+/// whitespace; NUL-delimited lists are taken verbatim. URLs are kept as they are rather than
+/// resolved. Returns each path with its 1-based position in the list.
+fn parse_path_list(content: &str, delimiter: char, base: &Path) -> Vec<(usize, PathBuf)> {
+    let verbatim = delimiter == '\0';
+    content
+        .split(delimiter)
+        .enumerate()
+        .map(|(i, entry)| (i + 1, if verbatim { entry } else { entry.trim() }))
+        .filter(|(_, entry)| !entry.is_empty() && (verbatim || !entry.starts_with('#')))
+        .map(|(n, entry)| {
+            (
+                n,
+                if is_url(entry) {
+                    entry.into()
+                } else {
+                    base.join(entry)
+                },
+            )
+        })
+        .collect()
+}
+
+/// Returns true if `path`, relative to `folder`, matches one of the `--pattern` globs, or
+/// none were given.
+fn is_included(path: &Path, folder: &Path, config: &Config) -> bool {
+    let rel = path.strip_prefix(folder).unwrap_or(path);
+    config.include_patterns.is_empty()
+        || config.include_patterns.iter().any(|p| glob_matches(p, rel))
+}
+
+/// Returns true if `path`, relative to `folder`, matches one of the `--exclude` globs.
+fn is_excluded(path: &Path, folder: &Path, config: &Config) -> bool {
+    let rel = path.strip_prefix(folder).unwrap_or(path);
+    config.exclude_patterns.iter().any(|p| glob_matches(p, rel))
+}
+
+/// Matches `pattern` against the file name of `rel`, or against the whole relative path
+/// when the pattern itself contains a separator.
+fn glob_matches(pattern: &glob::Pattern, rel: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        pattern.matches_path_with(rel, options)
+    } else {
+        rel.file_name()
+            .map(|name| pattern.matches(&name.to_string_lossy()))
+            .unwrap_or(false)
+    }
+}
+
+/// Extensions scanned for when `--extensions` isn't given.
+pub const DEFAULT_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jpe", "jfif", "png", "webp", "tif", "tiff", "bmp", "gif", "heic", "heif",
+    "avif", "cr2", "nef", "arw", "dng", "raf", "orf", "rw2", "pef", "svg", "svgz",
+];
+
+/// Returns true if the file extension is one of the configured input extensions.
+pub fn is_supported_image(path: &Path, config: &Config) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    config.extensions.contains(&ext)
+}
+
+/// Supported image files found under a folder, plus the ones deliberately passed over.
+struct Scan {
+    images: Vec<PathBuf>,
+    skipped: Vec<(PathBuf, SkipReason)>,
+}
+
+/// Collects supported image files in `root`.
+///
+/// With `recursive`, subfolders are walked too. `skip_dir` (the output folder) is never
+/// entered, and each directory is visited at most once so symlink cycles terminate.
+/// Hidden files and folders are passed over unless `--include-hidden` is set.
+/// Symlinks below `root` are only followed with `--follow-symlinks`; dangling ones are
+/// always reported as skipped rather than left to fail at decode time.
+fn collect_images(root: &Path, skip_dir: &Path, config: &Config) -> std::io::Result<Scan> {
+    let skip_dir = skip_dir.canonicalize().ok();
+    let mut visited = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut scan = Scan {
+        images: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    while let Some(dir) = pending.pop() {
+        let canonical = dir.canonicalize()?;
+        if !visited.insert(canonical) {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // Dotfiles include the `._*` AppleDouble forks macOS leaves next to every image.
+            if !config.include_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                if is_supported_image(&path, config) {
+                    scan.skipped.push((path, SkipReason::Hidden));
+                }
+                continue;
+            }
+            if entry.file_type()?.is_symlink() {
+                let Ok(target) = std::fs::metadata(&path) else {
+                    scan.skipped.push((path, SkipReason::DanglingSymlink));
+                    continue;
+                };
+                let relevant = if target.is_dir() {
+                    config.recursive
+                } else {
+                    is_supported_image(&path, config)
+                };
+                if !relevant {
+                    continue;
+                }
+                if !config.follow_symlinks {
+                    scan.skipped.push((path, SkipReason::Symlink));
+                    continue;
+                }
+            }
+            if path.is_dir() {
+                if config.recursive && path.canonicalize().ok() != skip_dir {
+                    pending.push(path);
+                }
+            } else if path.is_file() && is_supported_image(&path, config) {
+                scan.images.push(path);
+            }
+        }
+    }
+
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{config, temp_dir};
+    #[cfg(unix)]
+    use std::os::unix::fs::symlink;
+
+    /// Scans `root` with `args`, the output folder outside it.
+    #[cfg(unix)]
+    fn scan(root: &Path, args: &[&str]) -> Scan {
+        collect_images(root, &root.join("bordered_images"), &config(args)).unwrap()
+    }
+
+    #[test]
+    fn listed_files_are_held_to_the_patterns() {
+        let dir = temp_dir("list-patterns");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        for name in ["a.jpg", "sub/b.jpg", "c.png"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let list = dir.join("list.txt");
+        std::fs::write(&list, "a.jpg\nsub/b.jpg\nc.png\n").unwrap();
+        let input = Input {
+            label: list.display().to_string(),
+            source: Source::List(list),
+        };
+        let config = config(&["--pattern", "*.jpg", "--exclude", "sub/*"]);
+        let worklist = discover(&[input], &config).unwrap();
+        let jobs: Vec<&Path> = worklist.jobs.iter().map(|job| job.path.as_path()).collect();
+        assert_eq!(jobs, [dir.join("a.jpg")]);
+        let skipped: Vec<(&Path, SkipReason)> = worklist
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.path.as_path(), skipped.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (dir.join("sub/b.jpg").as_path(), SkipReason::Excluded),
+                (dir.join("c.png").as_path(), SkipReason::NotMatched),
+            ]
+        );
+        assert!(worklist.rejected.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_walked_once() {
+        let root = temp_dir("symlink-loop");
+        std::fs::create_dir(root.join("a")).unwrap();
+        std::fs::write(root.join("a/photo.jpg"), b"").unwrap();
+        symlink(&root, root.join("a/loop")).unwrap();
+        let scan = scan(&root, &["--recursive", "--follow-symlinks"]);
+        assert_eq!(scan.images, [root.join("a/photo.jpg")]);
+        assert!(scan.skipped.is_empty(), "{:?}", scan.skipped);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_are_skipped() {
+        let root = temp_dir("symlink-dangling");
+        symlink(root.join("missing.jpg"), root.join("photo.jpg")).unwrap();
+        for args in [&[][..], &["--follow-symlinks"]] {
+            let scan = scan(&root, args);
+            assert!(scan.images.is_empty());
+            assert_eq!(
+                scan.skipped,
+                [(root.join("photo.jpg"), SkipReason::DanglingSymlink)]
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_folders_are_walked_once() {
+        let root = temp_dir("symlink-visited");
+        std::fs::create_dir(root.join("a")).unwrap();
+        std::fs::write(root.join("a/photo.jpg"), b"").unwrap();
+        symlink(root.join("a"), root.join("b")).unwrap();
+        // Whichever of the two is walked first, the other is the same folder.
+        let followed = scan(&root, &["--recursive", "--follow-symlinks"]);
+        assert_eq!(followed.images.len(), 1, "{:?}", followed.images);
+        let unfollowed = scan(&root, &["--recursive"]);
+        assert_eq!(unfollowed.images, [root.join("a/photo.jpg")]);
+        assert_eq!(unfollowed.skipped, [(root.join("b"), SkipReason::Symlink)]);
+    }
+}
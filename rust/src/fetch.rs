@@ -0,0 +1,75 @@
+//! http(s) URL inputs.
+//!
+//! URLs are downloaded into memory right before processing and decoded from there. The HTTP
+//! client is behind the `http` cargo feature; without it URL inputs fail with a hint.
+
+use crate::Config;
+
+/// Returns true if `s` is an http:// or https:// URL.
+pub fn is_url(s: &str) -> bool {
+    let lower = s.get(..8).unwrap_or(s).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Derives a file name from the last path segment of a URL, ignoring any query or fragment.
+///
+/// Percent-escapes are decoded; URLs without a usable segment are named "download".
+pub fn file_name_from_url(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = rest.split(['?', '#']).next().unwrap_or("");
+    let segment = path
+        .split_once('/')
+        .and_then(|(_, path)| path.rsplit('/').find(|s| !s.is_empty()))
+        .unwrap_or("");
+    let name = percent_decode(segment).replace(['/', '\\'], "_");
+    if name.is_empty() || name == "." || name == ".." {
+        "download".to_string()
+    } else {
+        name
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Downloads `url` into memory, honouring `--timeout` and `--max-download-size`.
+///
+/// HTTP error statuses are returned as errors, so they count as failures of that input.
+#[cfg(feature = "http")]
+pub fn download(url: &str, config: &Config) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(config.download_timeout))
+        .build()
+        .into();
+    let mut response = agent.get(url).call()?;
+    let data = response
+        .body_mut()
+        .with_config()
+        .limit(config.max_download_size)
+        .read_to_vec()?;
+    Ok(data)
+}
+
+#[cfg(not(feature = "http"))]
+pub fn download(_url: &str, _config: &Config) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Err("URL inputs not compiled in, rebuild with --features http".into())
+}
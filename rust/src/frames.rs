@@ -0,0 +1,77 @@
+//! `--frames`: the border as nested bands of color, a mat within a mat.
+//!
+//! Bands are listed from the outside in. The photo is fitted inside all of them and each
+//! band is drawn as a filled rectangle around it, innermost last, so every band but the
+//! outermost keeps its width all the way round; the outermost takes whatever the photo's
+//! shape leaves of the canvas.
+
+use crate::border_color::{parse_border_color, BorderColor, BorderColorChoice};
+use crate::{parse_border_size, BorderSize, Placement};
+use image::{ImageBuffer, Pixel};
+
+/// One band of `--frames`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub color: BorderColor,
+    /// A ratio of the canvas's shorter side, so the band is as wide on every side.
+    pub width: BorderSize,
+}
+
+/// Parses one band of `--frames`: a color and a width, e.g. `white:0.05` or `#222222:4px`.
+pub fn parse_frame(s: &str) -> Result<Frame, String> {
+    let (color, width) = s.rsplit_once(':').ok_or_else(|| {
+        format!(
+            "invalid frame '{}' (expected COLOR:WIDTH, e.g. white:0.05 or #222222:4px)",
+            s
+        )
+    })?;
+    let color = match parse_border_color(color.trim())? {
+        BorderColorChoice::Fixed(color) => color,
+        BorderColorChoice::Auto => {
+            return Err(format!(
+                "invalid frame '{}' (auto is only for --border-color)",
+                s
+            ))
+        }
+    };
+    Ok(Frame {
+        color,
+        width: parse_border_size(width)?,
+    })
+}
+
+/// Width in pixels all of `frames` add up to on a canvas whose shorter side is `short`.
+pub fn total_width(frames: &[Frame], short: u32) -> f64 {
+    frames.iter().map(|frame| frame.width.resolve(short)).sum()
+}
+
+/// Draws the bands inside the outermost one around the image at `placement`, on a canvas
+/// already filled with the outermost band's color. With `flatten`, the colors are drawn
+/// opaque.
+pub fn draw<P: Pixel>(
+    canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    placement: Placement,
+    frames: &[Frame],
+    flatten: bool,
+) {
+    let (width, height) = canvas.dimensions();
+    let short = width.min(height);
+    for (i, frame) in frames.iter().enumerate().skip(1) {
+        // The band reaches from the image out past itself and every band inside it.
+        let outset = total_width(&frames[i..], short);
+        let start = |offset: u32| (offset as f64 - outset).round().max(0.0) as u32;
+        let end = |offset: u32, size: u32, limit: u32| {
+            ((offset + size) as f64 + outset).round().min(limit as f64) as u32
+        };
+        let color = match flatten {
+            true => frame.color.opaque(),
+            false => frame.color,
+        };
+        let pixel: P = color.pixel();
+        for y in start(placement.y)..end(placement.y, placement.height, height) {
+            for x in start(placement.x)..end(placement.x, placement.width, width) {
+                canvas.put_pixel(x, y, pixel);
+            }
+        }
+    }
+}
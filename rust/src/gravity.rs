@@ -0,0 +1,154 @@
+//! `--gravity`: where an image sits in the area inside the borders when it doesn't fill it,
+//! and `--crop-gravity`: which part of an image `--fit cover` keeps when it crops the overflow.
+//!
+//! `smart` looks for the busiest part of the image: the crop window with the most edges,
+//! measured on a grayscale copy scaled down to `SAMPLE_SIZE`. Summed into an integral image,
+//! every candidate window costs four lookups, so even trying all of them takes next to no
+//! time. Where no window stands out, e.g. a clear sky, the crop stays centered.
+
+use crate::{Crop, Placement};
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Longest edge of the copy `smart` measures on.
+const SAMPLE_SIZE: u32 = 128;
+
+/// How much busier than the quietest window the busiest one must be, as a fraction of its
+/// own edges, for `smart` to move away from the center.
+const MIN_CONTRAST: f64 = 0.1;
+
+/// Where the image sits in the area inside the borders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Gravity {
+    Center,
+    /// Against the top border.
+    North,
+    /// Against the bottom border.
+    South,
+    /// Against the right border.
+    East,
+    /// Against the left border.
+    West,
+    Northwest,
+    Northeast,
+    Southwest,
+    Southeast,
+}
+
+impl Gravity {
+    /// Where the image sits along each axis, from 0 (left or top) to 1 (right or bottom).
+    pub fn position(self) -> (f64, f64) {
+        match self {
+            Gravity::Center => (0.5, 0.5),
+            Gravity::North => (0.5, 0.0),
+            Gravity::South => (0.5, 1.0),
+            Gravity::East => (1.0, 0.5),
+            Gravity::West => (0.0, 0.5),
+            Gravity::Northwest => (0.0, 0.0),
+            Gravity::Northeast => (1.0, 0.0),
+            Gravity::Southwest => (0.0, 1.0),
+            Gravity::Southeast => (1.0, 1.0),
+        }
+    }
+}
+
+/// Where the crop window sits on the scaled image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CropGravity {
+    /// Crop evenly from both sides.
+    Center,
+    /// Keep the top, cropping from the bottom.
+    Top,
+    /// Keep the bottom, cropping from the top.
+    Bottom,
+    /// Keep the left, cropping from the right.
+    Left,
+    /// Keep the right, cropping from the left.
+    Right,
+    /// Keep the part with the most detail, or the center if nothing stands out.
+    Smart,
+}
+
+impl CropGravity {
+    /// Where the window sits along each axis, from 0 (left or top) to 1 (right or bottom).
+    /// `smart` has none of its own: it is picked for each image.
+    pub fn position(self) -> (f64, f64) {
+        match self {
+            CropGravity::Center | CropGravity::Smart => (0.5, 0.5),
+            CropGravity::Top => (0.5, 0.0),
+            CropGravity::Bottom => (0.5, 1.0),
+            CropGravity::Left => (0.0, 0.5),
+            CropGravity::Right => (1.0, 0.5),
+        }
+    }
+}
+
+/// Picks the position of the crop window (see `CropGravity::position`) that keeps the most
+/// edges of `img`, scaled and cropped as `placement` says. `None` if nothing is cropped or
+/// no window stands out.
+pub fn smart_position(img: &DynamicImage, placement: Placement) -> Option<(f64, f64)> {
+    if placement.crop == Crop::default() {
+        return None;
+    }
+    let sample = match img.width().max(img.height()) > SAMPLE_SIZE {
+        true => img
+            .resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Nearest)
+            .to_luma8(),
+        false => img.to_luma8(),
+    };
+    let (width, height) = sample.dimensions();
+    let value = |x: u32, y: u32| sample.get_pixel(x.min(width - 1), y.min(height - 1)).0[0];
+
+    // integral[y][x] holds the edges of everything above and left of (x, y).
+    let stride = width as usize + 1;
+    let mut integral = vec![0u64; stride * (height as usize + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            let here = value(x, y);
+            let edge =
+                here.abs_diff(value(x + 1, y)) as u64 + here.abs_diff(value(x, y + 1)) as u64;
+            let (x, y) = (x as usize, y as usize);
+            integral[(y + 1) * stride + x + 1] =
+                edge + integral[(y + 1) * stride + x] + integral[y * stride + x + 1]
+                    - integral[y * stride + x];
+        }
+    }
+
+    let (scaled_width, scaled_height) = placement.scaled_size();
+    let window = |sample: u32, shown: u32, scaled: u32| {
+        ((sample as f64 * shown as f64 / scaled as f64).round() as u32).clamp(1, sample)
+    };
+    let window_width = window(width, placement.width, scaled_width);
+    let window_height = window(height, placement.height, scaled_height);
+    let (slack_x, slack_y) = (width - window_width, height - window_height);
+    let edges = |x: u32, y: u32| {
+        let at = |x: u32, y: u32| integral[y as usize * stride + x as usize];
+        let (right, bottom) = (x + window_width, y + window_height);
+        at(right, bottom) + at(x, y) - at(x, bottom) - at(right, y)
+    };
+
+    // The busiest window; between equally busy ones, the one nearest the center.
+    let off_center =
+        |x: u32, y: u32| (2 * x).abs_diff(slack_x) as u64 + (2 * y).abs_diff(slack_y) as u64;
+    let mut best = (0, 0, edges(0, 0));
+    let mut quietest = best.2;
+    for y in 0..=slack_y {
+        for x in 0..=slack_x {
+            let score = edges(x, y);
+            quietest = quietest.min(score);
+            if score > best.2 || score == best.2 && off_center(x, y) < off_center(best.0, best.1) {
+                best = (x, y, score);
+            }
+        }
+    }
+    let (x, y, busiest) = best;
+    if busiest == 0 || (busiest - quietest) as f64 <= MIN_CONTRAST * busiest as f64 {
+        return None;
+    }
+    let position = |offset: u32, slack: u32| match slack {
+        0 => 0.5,
+        slack => offset as f64 / slack as f64,
+    };
+    Some((position(x, slack_x), position(y, slack_y)))
+}
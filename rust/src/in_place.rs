@@ -0,0 +1,105 @@
+//! `--in-place`: replace each image with its bordered version, keeping the original as a
+//! backup, and `--restore` to put the backups back.
+//!
+//! The original is renamed to its backup only once the image is decoded, and the bordered
+//! version is then written through a temporary file renamed over the original's name. At
+//! every point one of the two names holds the original, so a crash can't lose it.
+
+use crate::discover::{SkipReason, Worklist};
+use clap::ValueEnum;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Folder next to an image its original is moved into with `--backup folder`.
+const BACKUP_FOLDER: &str = "originals";
+
+/// Where `--in-place` keeps the originals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backup {
+    /// Next to the image, with ".bak" appended, e.g. `photo.jpg.bak`.
+    Bak,
+    /// In an "originals" folder next to the image, e.g. `originals/photo.jpg`.
+    Folder,
+}
+
+impl Backup {
+    /// Returns where the original of `path` is kept.
+    pub fn path(self, path: &Path) -> PathBuf {
+        match self {
+            Backup::Bak => {
+                let mut backup = path.as_os_str().to_owned();
+                backup.push(".bak");
+                PathBuf::from(backup)
+            }
+            Backup::Folder => {
+                let file_name = path.file_name().unwrap_or_default();
+                path.with_file_name(BACKUP_FOLDER).join(file_name)
+            }
+        }
+    }
+
+    /// Returns true if `path` is itself a backup. `.bak` files are never images, so only
+    /// the originals folder needs checking.
+    pub fn holds(self, path: &Path) -> bool {
+        let folder = path.parent().and_then(|dir| dir.file_name());
+        self == Backup::Folder && folder.is_some_and(|folder| folder == BACKUP_FOLDER)
+    }
+
+    /// Moves the original at `path` to its backup, which must not exist yet, and returns
+    /// the backup's path.
+    pub fn back_up(self, path: &Path) -> io::Result<PathBuf> {
+        let backup = self.path(path);
+        if backup.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", backup.display()),
+            ));
+        }
+        if let Some(dir) = backup.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::rename(path, &backup)?;
+        Ok(backup)
+    }
+
+    /// Moves the backup of `path` back over it. Returns false if there is no backup.
+    pub fn restore(self, path: &Path) -> io::Result<bool> {
+        let backup = self.path(path);
+        if !backup.exists() {
+            return Ok(false);
+        }
+        std::fs::rename(&backup, path)?;
+        if self == Backup::Folder {
+            // Only removed once empty, so nothing the user put there goes with it.
+            if let Some(dir) = backup.parent() {
+                let _ = std::fs::remove_dir(dir);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Restores every image of `worklist` that has a backup (`--restore`), returning how many
+/// were restored and how many failed.
+pub fn restore_all(worklist: &Worklist, backup: Backup) -> (usize, usize) {
+    let (mut restored, mut failed) = (0, 0);
+    // Discovery set the images with a backup aside as already bordered.
+    let backed_up = worklist
+        .skipped
+        .iter()
+        .filter(|s| s.reason == SkipReason::BackedUp);
+    for skipped in backed_up {
+        match backup.restore(&skipped.path) {
+            Ok(true) => {
+                println!("↩️  Restored {}", skipped.path.display());
+                restored += 1;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("❌ Could not restore {}: {}", skipped.path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    (restored, failed)
+}
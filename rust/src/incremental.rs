@@ -0,0 +1,176 @@
+//! `--incremental`: make-style rebuilds that only reprocess images changed since their output
+//! was written.
+//!
+//! An output is up to date when it is newer than its image and the image's sidecar, and was
+//! written with the same settings. The settings of the last incremental run are kept in a
+//! small file in each output folder; a folder without one counts as written with unknown
+//! settings, so its outputs are rebuilt once.
+
+use crate::sidecar::sidecar_path;
+use crate::Config;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Settings of the last incremental run, kept next to its outputs.
+const SETTINGS_FILE_NAME: &str = ".white_border_adder_settings";
+
+/// How much newer an input must be than its output to count as changed. Covers coarse
+/// timestamps (FAT stores 2-second steps) and clock skew between a network share and us.
+const MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Returns the settings that affect how an output looks, one `key=value` per line.
+fn settings_text(config: &Config) -> String {
+    format!(
+        "width={}\nheight={}\nsizes={:?}\nclass_sizes={:?}\nthumbnail={:?}\nrotation={:?}\n\
+         landscape_vert={}\nlandscape_horiz={}\nportrait_vert={}\nportrait_horiz={}\n\
+         square_vert={}\nsquare_horiz={}\nsquare_tolerance={}\n\
+         panorama_vert={}\npanorama_horiz={}\npanorama_threshold={}\n\
+         border_px={:?}\n\
+         border_top={:?}\nborder_bottom={:?}\nborder_left={:?}\nborder_right={:?}\n\
+         min_border_px={:?}\nmax_upscale={:?}\nfit_mode={:?}\ncrop_gravity={:?}\n\
+         gravity={:?}\noffset={:?}\npad_only={}\nwrap={}\nlongest_edge={:?}\n\
+         style={:?}\nframes={:?}\n\
+         border_color={}\nauto_color={:?}\n\
+         background={:?}\nblur_radius={}\nbackground_darken={}\n\
+         corner_radius={:?}\nshadow={:?}\nkeyline={:?}\nkeyline_color={}\n\
+         caption={:?}\n\
+         jpeg_quality={}\njpeg_progressive={}\n\
+         jpeg_subsampling={:?}\njpeg_encoder={:?}\n\
+         webp_quality={}\nwebp_lossless={}\navif_quality={}\n\
+         avif_speed={}\ntiff_compression={:?}\n\
+         png_compression={:?}\npng_filter={:?}\n\
+         optimize_png={}\noptimize_level={}\nmetadata={:?}\nstrip_gps={}\n\
+         stamp_metadata={}\n\
+         dpi={:?}\nbit_depth={:?}\n\
+         animated={:?}\nformat={:?}\nmax_output_size={:?}\nmin_quality={}\n",
+        config.target_width,
+        config.target_height,
+        config.sizes,
+        config.class_sizes,
+        config.thumbnail,
+        config.rotation,
+        config.landscape_vert_border,
+        config.landscape_horiz_border,
+        config.portrait_vert_border,
+        config.portrait_horiz_border,
+        config.square_vert_border,
+        config.square_horiz_border,
+        config.square_tolerance,
+        config.panorama_vert_border,
+        config.panorama_horiz_border,
+        config.panorama_threshold,
+        config.border_px,
+        config.border_top,
+        config.border_bottom,
+        config.border_left,
+        config.border_right,
+        config.min_border_px,
+        config.max_upscale,
+        config.fit_mode,
+        config.crop_gravity,
+        config.gravity,
+        config.offset,
+        config.pad_only,
+        config.wrap,
+        config.longest_edge,
+        config.style,
+        config.frames,
+        config.border_color,
+        config.auto_color,
+        config.background,
+        config.blur_radius,
+        config.background_darken,
+        config.corner_radius,
+        config.shadow,
+        config.keyline,
+        config.keyline_color,
+        config.caption,
+        config.jpeg_quality,
+        config.jpeg_progressive,
+        config.jpeg_subsampling,
+        config.jpeg_encoder,
+        config.webp_quality,
+        config.webp_lossless,
+        config.avif_quality,
+        config.avif_speed,
+        config.tiff_compression,
+        config.png_compression,
+        config.png_filter,
+        config.optimize_png,
+        config.optimize_level,
+        config.metadata,
+        config.strip_gps,
+        config.stamp_metadata,
+        config.dpi,
+        config.bit_depth,
+        config.animated,
+        config.format,
+        config.max_output_size,
+        config.min_quality,
+    )
+}
+
+/// Per output folder, whether its outputs were written with the current settings.
+#[derive(Default)]
+pub struct SettingsCache {
+    /// Read once per folder before anything is written to it, so outputs left from an older
+    /// run stay stale after the first new output updates the file.
+    matching: HashMap<PathBuf, bool>,
+    /// Folders whose settings file this run already updated.
+    written: HashSet<PathBuf>,
+}
+
+impl SettingsCache {
+    fn settings_match(&mut self, output_root: &Path, config: &Config) -> bool {
+        *self
+            .matching
+            .entry(output_root.to_path_buf())
+            .or_insert_with(|| {
+                let stored = std::fs::read_to_string(output_root.join(SETTINGS_FILE_NAME));
+                stored.is_ok_and(|stored| stored == settings_text(config))
+            })
+    }
+
+    /// Returns true if the existing `output` of `input` can be kept.
+    ///
+    /// `config` must be the run's settings, before any sidecar is applied; sidecars are
+    /// covered by comparing their modification time instead.
+    pub fn is_up_to_date(
+        &mut self,
+        input: &Path,
+        output: &Path,
+        output_root: &Path,
+        config: &Config,
+    ) -> bool {
+        if !config.incremental_ignore_settings && !self.settings_match(output_root, config) {
+            return false;
+        }
+        let Some(output_time) = modified(output) else {
+            return false;
+        };
+        let newest_input = [Some(input.to_path_buf()), sidecar_path(input)]
+            .into_iter()
+            .flatten()
+            .filter_map(|path| modified(&path))
+            .max();
+        match newest_input {
+            Some(input_time) => input_time <= output_time + MTIME_TOLERANCE,
+            None => false,
+        }
+    }
+
+    /// Records that an output with the current settings was written below `output_root`.
+    pub fn record_written(&mut self, output_root: &Path, config: &Config) -> std::io::Result<()> {
+        if !self.written.insert(output_root.to_path_buf()) {
+            return Ok(());
+        }
+        // Cache the old state first: outputs not yet revisited still need it.
+        self.settings_match(output_root, config);
+        std::fs::write(output_root.join(SETTINGS_FILE_NAME), settings_text(config))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
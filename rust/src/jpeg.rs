@@ -0,0 +1,244 @@
+//! JPEG encoding.
+//!
+//! `image`'s encoder writes baseline JPEGs with full-resolution colour and stays the default.
+//! Progressive scans and chroma subsampling need `jpeg-encoder`, which is picked only when
+//! one of them is asked for. `--encoder mozjpeg` (the `mozjpeg` feature) trades encoding
+//! time for smaller files. `--dpi` goes into the JFIF density fields, whichever encoder
+//! writes them.
+
+use crate::Config;
+use clap::ValueEnum;
+use image::error::{EncodingError, LimitError, LimitErrorKind};
+use image::{DynamicImage, ImageError, ImageFormat};
+use std::io::Write;
+
+/// `--jpeg-subsampling`: how much colour resolution JPEG outputs keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Subsampling {
+    /// Full colour resolution; keeps fine coloured text and lines sharp.
+    #[value(name = "444")]
+    Full,
+    /// Colour at half the horizontal resolution.
+    #[value(name = "422")]
+    Half,
+    /// Colour at half the horizontal and vertical resolution; smallest, fine for photos.
+    #[value(name = "420")]
+    Quarter,
+}
+
+impl From<Subsampling> for jpeg_encoder::SamplingFactor {
+    fn from(subsampling: Subsampling) -> Self {
+        match subsampling {
+            Subsampling::Full => jpeg_encoder::SamplingFactor::R_4_4_4,
+            Subsampling::Half => jpeg_encoder::SamplingFactor::R_4_2_2,
+            Subsampling::Quarter => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// `--encoder`: the JPEG encoder to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EncoderChoice {
+    /// The encoders built into this program.
+    Builtin,
+    /// mozjpeg with trellis quantization and optimized Huffman tables: typically 20–35%
+    /// smaller at the same --jpeg-quality, but several times slower. Only with the `mozjpeg`
+    /// feature.
+    Mozjpeg,
+}
+
+impl EncoderChoice {
+    /// Explains why the encoder can't be used by this build, if it can't.
+    pub fn unavailable(self) -> Option<&'static str> {
+        match self {
+            EncoderChoice::Mozjpeg if !cfg!(feature = "mozjpeg") => {
+                Some("mozjpeg encoder not compiled in, rebuild with --features mozjpeg")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The library a JPEG is encoded with.
+enum Library {
+    /// `image::codecs::jpeg`: baseline, 4:4:4.
+    Image,
+    /// `jpeg-encoder`, for progressive scans and subsampling.
+    JpegEncoder,
+    Mozjpeg,
+}
+
+impl Library {
+    fn for_config(config: &Config) -> Self {
+        if config.jpeg_encoder == EncoderChoice::Mozjpeg {
+            Library::Mozjpeg
+        } else if config.jpeg_progressive || config.jpeg_subsampling != Subsampling::Full {
+            Library::JpegEncoder
+        } else {
+            Library::Image
+        }
+    }
+}
+
+/// Encodes `canvas` as a JPEG with the `--jpeg-*` settings of `config`.
+pub fn encode_jpeg(
+    canvas: &DynamicImage,
+    config: &Config,
+    writer: impl Write,
+) -> Result<(), ImageError> {
+    match Library::for_config(config) {
+        Library::Image => {
+            let mut encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(writer, config.jpeg_quality);
+            if let Some(dpi) = config.dpi {
+                encoder.set_pixel_density(image::codecs::jpeg::PixelDensity::dpi(dpi));
+            }
+            canvas.write_with_encoder(encoder)
+        }
+        Library::JpegEncoder => encode_with_jpeg_encoder(canvas, config, writer),
+        #[cfg(feature = "mozjpeg")]
+        Library::Mozjpeg => encode_with_mozjpeg(canvas, config, writer),
+        #[cfg(not(feature = "mozjpeg"))]
+        Library::Mozjpeg => Err(ImageError::Encoding(EncodingError::new(
+            ImageFormat::Jpeg.into(),
+            EncoderChoice::Mozjpeg.unavailable().unwrap_or_default(),
+        ))),
+    }
+}
+
+fn encode_with_jpeg_encoder(
+    canvas: &DynamicImage,
+    config: &Config,
+    writer: impl Write,
+) -> Result<(), ImageError> {
+    // Keep I/O errors as such, so a full disk is still recognised.
+    let jpeg_error = |e| match e {
+        jpeg_encoder::EncodingError::IoError(e) => ImageError::IoError(e),
+        e => ImageError::Encoding(EncodingError::new(ImageFormat::Jpeg.into(), e)),
+    };
+    // JPEG dimensions are 16-bit, like with `image`'s encoder.
+    let (Ok(width), Ok(height)) = (
+        u16::try_from(canvas.width()),
+        u16::try_from(canvas.height()),
+    ) else {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    };
+    let mut encoder = jpeg_encoder::Encoder::new(writer, config.jpeg_quality);
+    encoder.set_progressive(config.jpeg_progressive);
+    encoder.set_sampling_factor(config.jpeg_subsampling.into());
+    if let Some(dpi) = config.dpi {
+        encoder.set_density(jpeg_encoder::PixelDensity::dpi(dpi));
+    }
+    if canvas.color().has_color() {
+        encoder.encode(
+            &canvas.to_rgb8(),
+            width,
+            height,
+            jpeg_encoder::ColorType::Rgb,
+        )
+    } else {
+        encoder.encode(
+            &canvas.to_luma8(),
+            width,
+            height,
+            jpeg_encoder::ColorType::Luma,
+        )
+    }
+    .map_err(jpeg_error)
+}
+
+/// Encodes with mozjpeg. `--jpeg-quality` is passed on unchanged: mozjpeg uses the same
+/// libjpeg quality scale, its savings come from trellis quantization and optimized Huffman
+/// tables rather than a lower quality.
+#[cfg(feature = "mozjpeg")]
+fn encode_with_mozjpeg(
+    canvas: &DynamicImage,
+    config: &Config,
+    mut writer: impl Write,
+) -> Result<(), ImageError> {
+    use mozjpeg::{ColorSpace, Compress, PixelDensity, PixelDensityUnit};
+
+    let (color_space, pixels) = if canvas.color().has_color() {
+        (ColorSpace::JCS_RGB, canvas.to_rgb8().into_raw())
+    } else {
+        (ColorSpace::JCS_GRAYSCALE, canvas.to_luma8().into_raw())
+    };
+    // libjpeg reports errors by unwinding; encode to memory so only its own errors are caught.
+    let encoded = std::panic::catch_unwind(|| -> std::io::Result<Vec<u8>> {
+        let mut compress = Compress::new(color_space);
+        compress.set_size(canvas.width() as usize, canvas.height() as usize);
+        compress.set_quality(config.jpeg_quality as f32);
+        compress.set_optimize_coding(true);
+        // mozjpeg's defaults are progressive with optimized scans.
+        if !config.jpeg_progressive {
+            compress.set_optimize_scans(false);
+        }
+        if color_space == ColorSpace::JCS_RGB {
+            let size = match config.jpeg_subsampling {
+                Subsampling::Full => (1, 1),
+                Subsampling::Half => (2, 1),
+                Subsampling::Quarter => (2, 2),
+            };
+            compress.set_chroma_sampling_pixel_sizes(size, size);
+        }
+        if let Some(dpi) = config.dpi {
+            compress.set_pixel_density(PixelDensity {
+                unit: PixelDensityUnit::Inches,
+                x: dpi,
+                y: dpi,
+            });
+        }
+        let mut started = compress.start_compress(Vec::new())?;
+        started.write_scanlines(&pixels)?;
+        started.finish()
+    });
+    let encoded = match encoded {
+        Ok(result) => result
+            .map_err(|e| ImageError::Encoding(EncodingError::new(ImageFormat::Jpeg.into(), e)))?,
+        Err(_) => {
+            return Err(ImageError::Encoding(EncodingError::new(
+                ImageFormat::Jpeg.into(),
+                "mozjpeg failed to encode the image",
+            )))
+        }
+    };
+    Ok(writer.write_all(&encoded)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::config;
+    use image::RgbImage;
+
+    /// The start-of-frame marker of a JPEG, which tells baseline (SOF0) from progressive
+    /// (SOF2) apart.
+    fn frame_marker(jpeg: &[u8]) -> u8 {
+        let mut at = 2;
+        loop {
+            let marker = jpeg[at + 1];
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                return marker;
+            }
+            at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        }
+    }
+
+    fn encoded(args: &[&str]) -> Vec<u8> {
+        let canvas = RgbImage::from_fn(64, 48, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 90]));
+        let mut jpeg = Vec::new();
+        encode_jpeg(&canvas.into(), &config(args), &mut jpeg).unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn progressive_outputs_have_a_progressive_frame() {
+        assert_eq!(frame_marker(&encoded(&[])), 0xC0);
+        assert_eq!(frame_marker(&encoded(&["--jpeg-subsampling", "420"])), 0xC0);
+        assert_eq!(frame_marker(&encoded(&["--jpeg-progressive"])), 0xC2);
+        let subsampled = encoded(&["--jpeg-progressive", "--jpeg-subsampling", "420"]);
+        assert_eq!(frame_marker(&subsampled), 0xC2);
+    }
+}
@@ -1,266 +1,4064 @@
 //! White border adder — adds configurable white borders and scales images to a target size.
 //! Serial version (no parallelism).
 
-use clap::Parser;
+mod animation;
+mod archive;
+mod atomic;
+mod background;
+mod border_color;
+mod borderignore;
+mod caption;
+mod capture_date;
+mod corners;
+mod date_name;
+mod decode;
+mod discover;
+mod fetch;
+mod frames;
+mod gravity;
+mod in_place;
+mod incremental;
+mod jpeg;
+mod metadata;
+mod mtime;
+mod name_template;
+mod optimize;
+mod output;
+mod output_zip;
+mod pipe;
+mod plan;
+mod presets;
+mod report;
+mod salvage;
+mod shadow;
+mod shot_info;
+mod sidecar;
+mod size_limit;
+mod stats;
+mod style;
+mod watch;
+
+use animation::AnimatedMode;
+use archive::ZipOutput;
+use atomic::AtomicFile;
+use background::Background;
+use border_color::{
+    parse_border_color, parse_keyline_color, AutoColorMode, BorderColor, BorderColorChoice,
+};
+use caption::{
+    parse_caption, parse_caption_color, parse_caption_font, parse_caption_size, Caption,
+    CaptionAlign, CaptionFont, CaptionSource, CaptionText,
+};
+use capture_date::{format_date, format_date_time, MissingDate};
+use clap::{ArgAction, Parser, ValueEnum};
+use corners::{parse_corner_radius, CornerRadius};
+use date_name::{DatePattern, DEFAULT_DATE_PATTERN};
+use decode::{
+    decode_bytes, decode_image, decode_image_as_stored, svg_render_size, turn_upright,
+    OptionalCodec, Rotation,
+};
+use discover::{
+    discover, resolve_inputs, Input, SkipReason, Skipped, SortKey, Source, DEFAULT_EXTENSIONS,
+};
+use frames::{parse_frame, Frame};
+use gravity::{CropGravity, Gravity};
 use image::imageops::FilterType;
-use image::{imageops, GenericImage, ImageBuffer, RgbaImage, Rgba};
+use image::{imageops, DynamicImage, GenericImage, ImageBuffer, Pixel};
+use in_place::Backup;
+use incremental::SettingsCache;
+use jpeg::{encode_jpeg, EncoderChoice, Subsampling};
+use metadata::{Metadata, MetadataPolicy};
+use name_template::NameTemplate;
+use output::{
+    output_format, BitDepth, FormatChoice, NormalizeExtension, OutputFormat, PngCompression,
+    PngFilter, Sequence, TiffCompression,
+};
+use output_zip::OutputZip;
+use plan::{OnCollision, Plan, WorkItem};
+use presets::{parse_preset, Preset, PresetChoice};
+use report::ImageReport;
+use serde::Serialize;
+use shadow::{parse_shadow_offset, Shadow};
+use stats::{Stages, Stats};
+use std::borrow::Cow;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use style::Style;
+
+/// The border ratio flags `--border-px` replaces.
+const BORDER_RATIO_FLAGS: [&str; 8] = [
+    "landscape_vert",
+    "landscape_horiz",
+    "portrait_vert",
+    "portrait_horiz",
+    "square_vert",
+    "square_horiz",
+    "panorama_vert",
+    "panorama_horiz",
+];
+
+/// The flags `--frames` replaces.
+const FRAMES_CONFLICTS: [&str; 18] = [
+    "landscape_vert",
+    "landscape_horiz",
+    "portrait_vert",
+    "portrait_horiz",
+    "square_vert",
+    "square_horiz",
+    "panorama_vert",
+    "panorama_horiz",
+    "border_px",
+    "border_px_vert",
+    "border_px_horiz",
+    "border_top",
+    "border_bottom",
+    "border_left",
+    "border_right",
+    "style",
+    "border_color",
+    "background",
+];
+
+/// The canvas size flags `--pad-only` replaces.
+const PAD_ONLY_CONFLICTS: [&str; 8] = [
+    "sizes",
+    "landscape_size",
+    "portrait_size",
+    "square_size",
+    "panorama_size",
+    "tall_size",
+    "preset",
+    "fit",
+];
 
-const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+/// The canvas size flags `--wrap` can't be combined with.
+const WRAP_CONFLICTS: [&str; 8] = [
+    "sizes",
+    "landscape_size",
+    "portrait_size",
+    "square_size",
+    "panorama_size",
+    "tall_size",
+    "fit",
+    "pad_only",
+];
+
+/// How many times `--wrap` refines the scale of an image before settling on it.
+const WRAP_PASSES: usize = 4;
+
+/// The canvas size flags `--longest-edge` replaces.
+const LONGEST_EDGE_CONFLICTS: [&str; 10] = [
+    "width",
+    "height",
+    "sizes",
+    "landscape_size",
+    "portrait_size",
+    "square_size",
+    "panorama_size",
+    "tall_size",
+    "preset",
+    "pad_only",
+];
 
 /// Add white borders to images and scale to target dimensions.
 #[derive(Parser, Debug)]
 #[command(name = "white_border_adder")]
 #[command(about = "Add white borders to images in a folder")]
 struct Args {
-    /// Input folder containing images (required unless using -i)
+    /// Input folders, single image files, ZIP archives or http(s) URLs (required unless
+    /// using -i)
     #[arg(index = 1)]
-    input: Option<PathBuf>,
+    input: Vec<PathBuf>,
 
-    /// Input folder (alternative to positional)
+    /// Input folder or image file (alternative to positional, repeatable)
     #[arg(short = 'i', long = "input")]
-    input_flag: Option<PathBuf>,
+    input_flag: Vec<PathBuf>,
 
-    /// Target width for output images
-    #[arg(long, default_value_t = 1080)]
-    width: u32,
+    /// Target width for output images. Default: the --preset's, or 1080
+    #[arg(long)]
+    width: Option<u32>,
 
-    /// Target height for output images
-    #[arg(long, default_value_t = 1080)]
-    height: u32,
+    /// Target height for output images. Default: the --preset's, or 1080
+    #[arg(long)]
+    height: Option<u32>,
 
-    /// Vertical border ratio for landscape images (0.0–1.0)
-    #[arg(long, default_value_t = 0.05)]
-    landscape_vert: f64,
+    /// Canvas size and border ratios for a social platform, e.g. ig-portrait (1080x1350) or
+    /// x-landscape (1600x900). --width, --height and the ratio flags still win over it;
+    /// "--preset list" prints them all
+    #[arg(long, value_name = "NAME", value_parser = parse_preset)]
+    preset: Option<PresetChoice>,
 
-    /// Horizontal border ratio for landscape images
-    #[arg(long, default_value_t = 0.03)]
-    landscape_horiz: f64,
+    /// Write one output per canvas size instead of one at --width x --height, e.g.
+    /// "--size 1080x1080 --size 1080x1350" or "--sizes 1080x1080,1080x1350". Each image is
+    /// decoded once. Outputs go into a "WIDTHxHEIGHT" subfolder, unless the --name-template
+    /// has both {width} and {height}
+    #[arg(
+        long = "size",
+        visible_alias = "sizes",
+        value_name = "WxH",
+        value_delimiter = ',',
+        value_parser = parse_canvas_size,
+        conflicts_with_all = ["width", "height"]
+    )]
+    sizes: Vec<(u32, u32)>,
 
-    /// Vertical border ratio for portrait images
-    #[arg(long, default_value_t = 0.005)]
-    portrait_vert: f64,
+    /// Canvas size for landscape images, e.g. 1080x566, instead of --width x --height.
+    /// Classes without a size of their own get --width x --height
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas_size, conflicts_with = "sizes")]
+    landscape_size: Option<(u32, u32)>,
 
-    /// Horizontal border ratio for portrait images
-    #[arg(long, default_value_t = 0.18)]
-    portrait_horiz: f64,
+    /// Canvas size for portrait images, e.g. 1080x1350
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas_size, conflicts_with = "sizes")]
+    portrait_size: Option<(u32, u32)>,
 
-    /// JPEG output quality (1–100)
-    #[arg(long, default_value_t = 100)]
-    jpeg_quality: u8,
+    /// Canvas size for square images
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas_size, conflicts_with = "sizes")]
+    square_size: Option<(u32, u32)>,
+
+    /// Canvas size for panoramas (see --panorama-threshold)
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas_size, conflicts_with = "sizes")]
+    panorama_size: Option<(u32, u32)>,
+
+    /// Canvas size for tall images, panoramas on their side
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas_size, conflicts_with = "sizes")]
+    tall_size: Option<(u32, u32)>,
+
+    /// Also write a thumbnail of each output, scaled from the bordered canvas to this
+    /// longest edge in pixels, e.g. 256. It goes next to the output as "thumb_<output name>",
+    /// in the same format. Animated outputs get none
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    thumbnail: Option<u32>,
+
+    /// Turn every image clockwise by this many degrees before it is classed and laid out,
+    /// e.g. for scans that come in on their side. Applied after the EXIF orientation
+    #[arg(long, value_enum, value_name = "DEGREES", default_value_t = Rotation::None)]
+    rotate: Rotation,
+
+    /// Also write a JSON file next to each output describing how it was made: source size,
+    /// orientation, borders, scale and placement, encoder settings, file sizes and time,
+    /// e.g. "bordered_photo.jpg.json". Not for ZIP inputs
+    #[arg(long)]
+    write_sidecar: bool,
+
+    /// Vertical border ratio for landscape images (0.0–1.0). Default: the --preset's, or 0.05
+    #[arg(long)]
+    landscape_vert: Option<f64>,
+
+    /// Horizontal border ratio for landscape images. Default: the --preset's, or 0.03
+    #[arg(long)]
+    landscape_horiz: Option<f64>,
+
+    /// Vertical border ratio for portrait images. Default: the --preset's, or 0.005
+    #[arg(long)]
+    portrait_vert: Option<f64>,
+
+    /// Horizontal border ratio for portrait images. Default: the --preset's, or 0.18
+    #[arg(long)]
+    portrait_horiz: Option<f64>,
+
+    /// Vertical border ratio for square images. Default: the --preset's, or 0.05
+    #[arg(long)]
+    square_vert: Option<f64>,
+
+    /// Horizontal border ratio for square images. Default: the --preset's, or 0.05
+    #[arg(long)]
+    square_horiz: Option<f64>,
+
+    /// How far from 1:1 an image's aspect ratio may be and still count as square, e.g. 0.02
+    /// for 2%: 1000x1020 is square, 1000x1021 portrait. 0 for exactly square only
+    #[arg(long, value_name = "RATIO", default_value_t = 0.02, value_parser = parse_tolerance)]
+    square_tolerance: f64,
+
+    /// Vertical border ratio for panoramas, images more than --panorama-threshold times as
+    /// wide as tall. Very tall images get the panorama ratios turned on their side.
+    /// Default: the --preset's, or 0.05
+    #[arg(long)]
+    panorama_vert: Option<f64>,
+
+    /// Horizontal border ratio for panoramas. Default: the --preset's, or 0.015
+    #[arg(long)]
+    panorama_horiz: Option<f64>,
+
+    /// Aspect ratio, longer side over shorter, beyond which an image is a panorama (or, if
+    /// it is taller than wide, a tall image), e.g. 2.0 for anything wider than 2:1
+    #[arg(long, value_name = "RATIO", default_value_t = 2.0, value_parser = parse_threshold)]
+    panorama_threshold: f64,
+
+    /// Fixed border width in pixels on every side, for any orientation, instead of the
+    /// ratios above: the image fits inside the canvas less twice this. Not combinable with
+    /// the ratio flags
+    #[arg(long, value_name = "PIXELS", conflicts_with_all = BORDER_RATIO_FLAGS)]
+    border_px: Option<u32>,
+
+    /// Fixed width in pixels of the top and bottom borders, overriding --border-px there.
+    /// Default: --border-px, or 0 without it
+    #[arg(long, value_name = "PIXELS", conflicts_with_all = BORDER_RATIO_FLAGS)]
+    border_px_vert: Option<u32>,
+
+    /// Fixed width in pixels of the left and right borders, overriding --border-px there.
+    /// Default: --border-px, or 0 without it
+    #[arg(long, value_name = "PIXELS", conflicts_with_all = BORDER_RATIO_FLAGS)]
+    border_px_horiz: Option<u32>,
+
+    /// Width of the top border, overriding the one from the ratios or --border-px: a ratio of
+    /// the canvas height, e.g. 0.05, or pixels, e.g. 40px. The image sits in the area the
+    /// four borders leave, as --gravity says
+    #[arg(long, value_name = "SIZE", value_parser = parse_border_size)]
+    border_top: Option<BorderSize>,
+
+    /// Width of the bottom border, like --border-top, e.g. 0.15 for room for a caption
+    #[arg(long, value_name = "SIZE", value_parser = parse_border_size)]
+    border_bottom: Option<BorderSize>,
+
+    /// Width of the left border: a ratio of the canvas width or pixels, like --border-top
+    #[arg(long, value_name = "SIZE", value_parser = parse_border_size)]
+    border_left: Option<BorderSize>,
+
+    /// Width of the right border, like --border-left
+    #[arg(long, value_name = "SIZE", value_parser = parse_border_size)]
+    border_right: Option<BorderSize>,
+
+    /// Smallest border in pixels any side may end up with: an image that would come closer
+    /// to an edge, e.g. one that nearly matches the canvas's shape, is scaled down further,
+    /// keeping its aspect ratio, until every side has at least this much
+    #[arg(long, value_name = "PIXELS")]
+    min_border_px: Option<u32>,
+
+    /// Never enlarge an image: one smaller than the area inside the borders is placed at its
+    /// own size, where --gravity puts it, with wider borders around it
+    #[arg(long, conflicts_with = "max_upscale")]
+    no_upscale: bool,
+
+    /// Enlarge images by at most this factor, e.g. 1.5; smaller ones stop short of the
+    /// borders and get wider ones. 1.0 is --no-upscale
+    #[arg(long, value_name = "FACTOR", value_parser = parse_max_upscale)]
+    max_upscale: Option<f64>,
+
+    /// How an image fills the area inside the borders: contain keeps all of it, cover
+    /// fills the area completely and crops the overflow evenly from both sides
+    #[arg(long, value_enum, default_value_t = FitMode::Contain)]
+    fit: FitMode,
+
+    /// Which part of the image --fit cover keeps: center, top, bottom, left, right, or
+    /// smart for the part with the most detail (the center where nothing stands out)
+    #[arg(long, value_enum, default_value_t = CropGravity::Center)]
+    crop_gravity: CropGravity,
+
+    /// Where an image that doesn't fill the area inside the borders sits in it: center,
+    /// north, south, east, west, northwest, northeast, southwest or southeast, e.g. north
+    /// to leave the spare room below a portrait for a caption
+    #[arg(long, value_enum, default_value_t = Gravity::Center)]
+    gravity: Gravity,
+
+    /// Move the image this many pixels right of where --gravity puts it, or left if
+    /// negative, e.g. -40. It stops at the edge of the canvas
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 0,
+        allow_hyphen_values = true
+    )]
+    offset_x: i32,
+
+    /// Move the image this many pixels down of where --gravity puts it, or up if negative,
+    /// e.g. -40 for "centered, but a little higher". It stops at the edge of the canvas
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 0,
+        allow_hyphen_values = true
+    )]
+    offset_y: i32,
+
+    /// Keep every image at its own size, untouched by any resampling, and grow the canvas
+    /// around it instead: the border ratios are fractions of the image, so 0.05 turns a
+    /// 4000x3000 photo into 4400x3300. --width and --height are ignored
+    #[arg(long, conflicts_with_all = PAD_ONLY_CONFLICTS)]
+    pad_only: bool,
+
+    /// Frame each image without forcing it into the canvas's shape: it is scaled so that it
+    /// and its borders, whose ratios are fractions of the image, just fit --width x --height
+    /// (or --longest-edge), and the canvas is only as large as they are
+    #[arg(long, conflicts_with_all = WRAP_CONFLICTS)]
+    wrap: bool,
+
+    /// Size each canvas to the image's own aspect ratio, with its longest side this many
+    /// pixels, borders included, instead of --width x --height: 2048 makes a 3:2 landscape
+    /// 2048x1365 and a 2:3 portrait 1365x2048
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = LONGEST_EDGE_CONFLICTS
+    )]
+    longest_edge: Option<u32>,
+
+    /// Draw the borders as nested bands of color, from the outside in, each COLOR:WIDTH with
+    /// a width that is a ratio of the canvas's shorter side or pixels, e.g.
+    /// "white:0.05,#222222:0.01,white:0.02" for a white mat with a dark line inside. The
+    /// photo fits inside all bands. Replaces the other border and color flags
+    #[arg(
+        long,
+        value_name = "SPEC",
+        value_delimiter = ',',
+        value_parser = parse_frame,
+        conflicts_with_all = FRAMES_CONFLICTS
+    )]
+    frames: Vec<Frame>,
+
+    /// A named preset for the borders and their color: "polaroid" gives thin equal borders
+    /// on top, left and right, a thick bottom one and an off-white color. Scaled to each
+    /// canvas; --border-top and friends, --border-px and --border-color override its parts.
+    /// Not combinable with the ratio flags
+    #[arg(long, value_enum, conflicts_with_all = BORDER_RATIO_FLAGS)]
+    style: Option<Style>,
+
+    /// Color of the borders: "#RRGGBB", "#RRGGBBAA" or white, black or gray. The alpha only
+    /// shows in outputs that keep transparency (PNG, WebP, AVIF, TIFF); JPEG and GIF outputs
+    /// get the color opaque. "transparent" leaves the borders fully transparent and needs
+    /// --format png, webp, avif or tiff. "auto" picks an opaque color from each image, see
+    /// --auto-color-mode.
+    /// Default: white
+    #[arg(long, value_name = "COLOR", value_parser = parse_border_color)]
+    border_color: Option<BorderColorChoice>,
+
+    /// How --border-color auto picks the color: the average of the whole image, its dominant
+    /// color, or the average along its edges. Default: average
+    #[arg(long, value_enum, value_name = "MODE")]
+    auto_color_mode: Option<AutoColorMode>,
+
+    /// What fills the canvas around the image: the border color, or a blurred copy of the
+    /// image zoomed to cover the canvas. Not combinable with --border-color
+    #[arg(long, value_enum, default_value_t = Background::Color)]
+    background: Background,
+
+    /// Gaussian blur radius (sigma) of --background blur, in pixels of the canvas
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 40.0,
+        value_parser = parse_blur_radius,
+        requires = "background"
+    )]
+    blur_radius: f32,
+
+    /// How much to darken --background blur, from 0.0 (not at all) to 1.0 (black), so the
+    /// image stands out
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        default_value_t = 0.0,
+        value_parser = parse_darken,
+        requires = "background"
+    )]
+    background_darken: f32,
+
+    /// Round the photo's corners with this radius: pixels, e.g. 24, or a percentage of its
+    /// shorter side, e.g. 5%. Anti-aliased onto the border; at most half the shorter side,
+    /// which gives a pill shape
+    #[arg(long, value_name = "RADIUS", value_parser = parse_corner_radius)]
+    corner_radius: Option<CornerRadius>,
+
+    /// Draw a soft drop shadow under the photo, so it seems to float above the canvas. See
+    /// --shadow-offset, --shadow-blur and --shadow-opacity
+    #[arg(long)]
+    shadow: bool,
+
+    /// How far the --shadow falls right and down of the photo, as "X,Y" in pixels; negative
+    /// for left or up
+    #[arg(
+        long,
+        value_name = "X,Y",
+        default_value = "8,8",
+        value_parser = parse_shadow_offset,
+        allow_hyphen_values = true,
+        requires = "shadow"
+    )]
+    shadow_offset: (i32, i32),
+
+    /// Gaussian blur radius (sigma) of the --shadow, in pixels
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 12.0,
+        value_parser = parse_blur_radius,
+        requires = "shadow"
+    )]
+    shadow_blur: f32,
+
+    /// Opacity of the --shadow at its darkest, from 0.0 to 1.0
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        default_value_t = 0.35,
+        value_parser = parse_darken,
+        requires = "shadow"
+    )]
+    shadow_opacity: f32,
+
+    /// Draw a thin line of this many pixels around the photo, just outside its edge, like
+    /// the keyline of a fine-art mat. Cut off where the border is narrower than the line
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    keyline: Option<u32>,
+
+    /// Color of the --keyline: "#RRGGBB" or white, black or gray. Default: black
+    #[arg(long, value_name = "COLOR", value_parser = parse_keyline_color, requires = "keyline")]
+    keyline_color: Option<BorderColor>,
+
+    /// Write a line of text in the border below the image, e.g. "{stem}, {date}": {stem} is
+    /// the input's file name without its extension and {date} the day it was taken, from
+    /// EXIF or the modification time. Text too wide for the image is shrunk, then cut short.
+    /// {camera}, {lens}, {focal}, {aperture}, {shutter} and {iso} are filled from EXIF too, and
+    /// a word whose placeholder has no value is left out
+    #[arg(long, value_name = "TEXT", value_parser = parse_caption, group = "any_caption")]
+    caption: Option<CaptionText>,
+
+    /// Write the camera settings from EXIF in the border below the image, like --caption,
+    /// by default "{camera} · {focal}mm {aperture} {shutter} ISO{iso}". Images without any
+    /// get no caption, or --caption-exif-missing
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_caption,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = caption::DEFAULT_EXIF_CAPTION,
+        group = "any_caption"
+    )]
+    caption_exif: Option<CaptionText>,
+
+    /// Caption of images without camera settings in their EXIF, e.g. "{stem}", instead of none
+    #[arg(long, value_name = "TEXT", value_parser = parse_caption, requires = "caption_exif")]
+    caption_exif_missing: Option<CaptionText>,
+
+    /// TrueType or OpenType font of the --caption. Default: DejaVu Sans, Liberation Sans or
+    /// Noto Sans, or Helvetica, Arial or Segoe UI, whichever is installed
+    #[arg(long, value_name = "PATH", value_parser = parse_caption_font, requires = "any_caption")]
+    caption_font: Option<CaptionFont>,
+
+    /// Size of the --caption: a ratio of the room below the image, e.g. 0.4, or pixels, e.g.
+    /// 24px. Default: 0.35
+    #[arg(long, value_name = "SIZE", value_parser = parse_caption_size, requires = "any_caption")]
+    caption_size: Option<BorderSize>,
+
+    /// Color of the --caption: "#RRGGBB", "#RRGGBBAA" or a name. Default: black, or white on
+    /// a dark border
+    #[arg(long, value_name = "COLOR", value_parser = parse_caption_color, requires = "any_caption")]
+    caption_color: Option<BorderColor>,
+
+    /// Where the --caption sits along the image: left or right lines it up with that edge
+    #[arg(long, value_enum, default_value_t = CaptionAlign::Center, requires = "any_caption")]
+    caption_align: CaptionAlign,
+
+    /// JPEG output quality (1–100, default 100). Only used for JPEG outputs
+    #[arg(long)]
+    jpeg_quality: Option<u8>,
+
+    /// Write progressive JPEGs, which browsers show at full size right away and sharpen as
+    /// they load. Not allowed with a --format other than jpg or auto
+    #[arg(long)]
+    jpeg_progressive: bool,
+
+    /// Chroma subsampling of JPEG outputs: 444 keeps fine coloured detail such as text, 420
+    /// gives smaller photos
+    #[arg(long, value_enum, default_value_t = Subsampling::Full)]
+    jpeg_subsampling: Subsampling,
+
+    /// JPEG encoder. mozjpeg takes the same --jpeg-quality scale (libjpeg's 1–100), so a
+    /// given quality looks about the same with either, but mozjpeg's files are smaller
+    #[arg(long = "encoder", value_enum, default_value_t = EncoderChoice::Builtin)]
+    jpeg_encoder: EncoderChoice,
+
+    /// WebP output quality (0–100); lower gives smaller, lossier files
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(0..=100))]
+    webp_quality: u8,
+
+    /// Write WebP outputs losslessly instead (--webp-quality is then ignored)
+    #[arg(long)]
+    webp_lossless: bool,
+
+    /// AVIF output quality (1–100)
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+    avif_quality: u8,
+
+    /// AVIF encoder speed, from 1 (slowest, smallest files) to 10 (fastest)
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(1..=10))]
+    avif_speed: u8,
+
+    /// Compression of TIFF outputs; all choices are lossless
+    #[arg(long, value_enum, default_value_t = TiffCompression::Lzw)]
+    tiff_compression: TiffCompression,
+
+    /// Compression effort of PNG outputs; all levels are lossless, best is slowest. The
+    /// mostly flat borders compress much better at best
+    #[arg(long, value_enum, default_value_t = PngCompression::Fast)]
+    png_compression: PngCompression,
+
+    /// Row filter of PNG outputs, applied before compressing; adaptive picks one per row
+    #[arg(long, value_enum, default_value_t = PngFilter::Adaptive)]
+    png_filter: PngFilter,
+
+    /// Run PNG outputs through oxipng for smaller files (lossless, slower). Other outputs are
+    /// left alone. Needs the `oxipng` feature
+    #[arg(long)]
+    optimize_png: bool,
+
+    /// oxipng effort for --optimize-png, from 0 (fastest) to 6 (smallest files)
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u8).range(0..=6),
+        requires = "optimize_png"
+    )]
+    optimize_level: u8,
+
+    /// Metadata of outputs: keep copies the input's EXIF (capture date, camera, lens), XMP
+    /// and ICC data, minimal only the ICC profile and the capture time, strip removes it all.
+    /// Applies to JPEG, PNG and WebP outputs; TIFF outputs keep only the ICC profile, AVIF
+    /// and GIF outputs are always stripped.
+    /// Default: keep
+    #[arg(long, value_enum)]
+    metadata: Option<MetadataPolicy>,
+
+    /// Leave location data out of copied metadata: the EXIF GPS fields, and the GPS and
+    /// place-name properties of the XMP. Camera and capture details are still kept
+    #[arg(long)]
+    strip_gps: bool,
+
+    /// Record how each output was made in its EXIF: the tool and version as Software, and the
+    /// target size, borders and encoder settings as a JSON UserComment. The rest of the
+    /// copied EXIF is kept. Applies to JPEG, PNG and WebP outputs
+    #[arg(long)]
+    stamp_metadata: bool,
+
+    /// Physical resolution to declare in JPEG, PNG and TIFF outputs, in dots per inch, for
+    /// printing. Pixel dimensions are unchanged. Default: whatever each encoder writes
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    dpi: Option<u16>,
+
+    /// Keep lossy outputs (JPEG, lossy WebP, AVIF) under this size, e.g. "2M": an output
+    /// that comes out larger is encoded again at lower qualities, searching for the highest
+    /// one that fits. Lossless outputs are never changed
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_output_size: Option<u64>,
+
+    /// Lowest quality --max-output-size may go down to. Outputs still too large there are
+    /// written at it with a warning
+    #[arg(
+        long,
+        value_name = "QUALITY",
+        default_value_t = 40,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        requires = "max_output_size"
+    )]
+    min_quality: u8,
+
+    /// Bits per channel of PNG and TIFF outputs: keep 16-bit sources at 16 bits, or force 8
+    /// or 16. JPEG, WebP, AVIF and GIF outputs are always 8-bit
+    #[arg(long, value_enum, default_value_t = BitDepth::Keep)]
+    bit_depth: BitDepth,
 
     /// Prefix for output filenames
     #[arg(long, default_value = "bordered_")]
     prefix: String,
 
+    /// Suffix inserted before the extension of output filenames, e.g. "_insta" for
+    /// "IMG_0001_insta.jpg". Combine with --prefix "" to use only the suffix
+    #[arg(long, default_value = "")]
+    suffix: String,
+
+    /// Name outputs from a template instead of --prefix/--suffix, e.g.
+    /// "{date}_{width}x{height}_{stem}". Placeholders: {stem}, {ext}, {width}, {height},
+    /// {date} (EXIF or modification date), {orientation} and {n} (position in the run).
+    /// The extension is appended unless the template contains {ext}
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = name_template::parse_name_template,
+        conflicts_with_all = ["prefix", "suffix"]
+    )]
+    name_template: Option<NameTemplate>,
+
+    /// Name outputs after when the image was taken (EXIF DateTimeOriginal, else the
+    /// modification time), e.g. "2024-06-01_14-32-11.jpg", instead of --prefix/--suffix.
+    /// Takes an optional strftime-style pattern after '=' with %Y, %y, %m, %d, %H, %M, %S
+    /// and %%. Images taken in the same second are numbered -1, -2, ...
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_DATE_PATTERN,
+        value_parser = date_name::parse_date_pattern,
+        conflicts_with_all = ["prefix", "suffix", "name_template"]
+    )]
+    rename_by_date: Option<DatePattern>,
+
+    /// Write output extensions in lower case, so "PHOTO.JPG" becomes "bordered_PHOTO.jpg";
+    /// "=canonical" also spells them one way per format (jpg, png, webp, avif, tif), so
+    /// ".jpeg" becomes ".jpg". An existing output spelled differently counts as existing
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "lower"
+    )]
+    normalize_extension: Option<NormalizeExtension>,
+
+    /// Put a sequence number before each output name in processing order, e.g.
+    /// "001_bordered_a.jpg", padded to the digits of the last number but at least three.
+    /// Numbers follow the sorted and filtered inputs, so a --dry-run shows the real run's
+    /// names, and skipped images keep theirs
+    #[arg(long, conflicts_with = "watch")]
+    number: bool,
+
+    /// Number --number starts at
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "number")]
+    number_start: usize,
+
+    /// What to do when several inputs would get the same output file (compared ignoring
+    /// case): number the later ones, stop before processing, or skip the later ones
+    #[arg(long, value_enum, default_value_t = OnCollision::Rename)]
+    on_collision: OnCollision,
+
+    /// How to handle animated GIF/WebP inputs: border only the first frame, or keep the
+    /// animation and border every frame (written as an animated GIF)
+    #[arg(long, value_enum, default_value_t = AnimatedMode::First)]
+    animated: AnimatedMode,
+
+    /// Where the outputs of a ZIP input go: a new "<name>_bordered.zip" archive, or a
+    /// "<name>" folder
+    #[arg(long, value_enum, default_value_t = ZipOutput::Archive)]
+    zip_output: ZipOutput,
+
     /// Write output into a separate subfolder "bordered_images"
-    #[arg(long, default_value_t = true)]
+    #[arg(
+        long,
+        default_value_t = true,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
     separate_folder: bool,
+
+    /// Write all outputs into this folder instead (created if missing), mirroring the
+    /// subfolders of recursive inputs. Relative paths are relative to the current directory
+    #[arg(long, value_name = "PATH", conflicts_with = "separate_folder")]
+    output_dir: Option<PathBuf>,
+
+    /// Write every output into this ZIP archive instead of loose files, under the paths it
+    /// would have below the output folder, e.g. "delivery.zip". Entries are added as images
+    /// are done, and the archive only appears under its name once complete. Not for ZIP
+    /// inputs
+    #[arg(
+        long,
+        value_name = "ZIP",
+        conflicts_with_all = [
+            "output_dir", "separate_folder", "in_place", "incremental", "watch",
+            "preserve_mtime", "mtime_from_exif",
+        ]
+    )]
+    output_zip: Option<PathBuf>,
+
+    /// Write the outputs of recursive inputs straight into the output folder instead of
+    /// mirroring their subfolders, for one flat delivery folder. Outputs that end up with
+    /// the same name are handled as --on-collision says; by default the later ones in
+    /// processing order are numbered, e.g. "bordered_photo_2.jpg"
+    #[arg(long)]
+    flatten: bool,
+
+    /// With --flatten, also write a CSV of "source,output" rows to this file, to trace the
+    /// flattened outputs back to their images. ZIP inputs aren't listed
+    #[arg(
+        long,
+        value_name = "CSV",
+        requires = "flatten",
+        conflicts_with = "dry_run"
+    )]
+    flatten_map: Option<PathBuf>,
+
+    /// Replace each image with its bordered version instead of writing a new file, keeping
+    /// the original as a backup (see --backup) so --restore can undo it. Images that already
+    /// have a backup are skipped. Not for ZIP or URL inputs
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "prefix", "suffix", "name_template", "rename_by_date", "output_dir",
+            "separate_folder", "flatten", "sizes", "format", "thumbnail", "incremental", "watch",
+            "normalize_extension", "number",
+        ]
+    )]
+    in_place: bool,
+
+    /// Where --in-place keeps the originals, and where --restore looks for them: bak renames
+    /// "photo.jpg" to "photo.jpg.bak", folder moves it to "originals/photo.jpg"
+    #[arg(long, value_enum, default_value_t = Backup::Bak)]
+    backup: Backup,
+
+    /// Show what a run would do without writing anything: for every image the output it
+    /// would write, its orientation, the size and offset it would be scaled to, or why it
+    /// would be skipped. Exits with an error if any image would fail, e.g. by overwriting
+    /// its input. ZIP inputs aren't looked into
+    #[arg(long, conflicts_with_all = ["watch", "restore", "pipe"])]
+    dry_run: bool,
+
+    /// Put the originals kept by --in-place back over their bordered versions, then exit.
+    /// Takes the same inputs and --backup as the --in-place run
+    #[arg(long, conflicts_with = "in_place")]
+    restore: bool,
+
+    /// Overwrite outputs that already exist. By default an image whose output is already
+    /// there is skipped
+    #[arg(long, visible_alias = "overwrite")]
+    force: bool,
+
+    /// Only reprocess images modified since their output was written, or whose output was
+    /// written with other settings. The first incremental run into a folder rebuilds it
+    #[arg(long, conflicts_with = "force")]
+    incremental: bool,
+
+    /// With --incremental, keep outputs written with other settings, comparing only
+    /// modification times
+    #[arg(long, requires = "incremental")]
+    incremental_ignore_settings: bool,
+
+    /// Give outputs the modification time of their image instead of the time they were
+    /// written, so galleries sorting by file time keep the order. Not for URL or ZIP inputs
+    #[arg(long)]
+    preserve_mtime: bool,
+
+    /// Like --preserve-mtime, but with the EXIF capture time, falling back to the image's
+    /// modification time. Can't be combined with --incremental, which compares the times
+    #[arg(long, conflicts_with = "incremental")]
+    mtime_from_exif: bool,
+
+    /// Also process files that look like outputs of a previous run (names with the prefix and
+    /// suffix)
+    #[arg(long)]
+    reprocess_outputs: bool,
+
+    /// Process the image paths or URLs listed in this file (one per line) instead of scanning
+    /// a folder
+    #[arg(long, value_name = "LIST")]
+    files_from: Option<PathBuf>,
+
+    /// Read image paths from standard input, one per line
+    #[arg(long)]
+    stdin: bool,
+
+    /// Read NUL-delimited image paths from standard input (implies --stdin)
+    #[arg(short = '0', long)]
+    null: bool,
+
+    /// Descend into subfolders, mirroring their structure in the output folder
+    #[arg(short = 'r', long)]
+    recursive: bool,
+
+    /// Comma-separated input extensions to scan for, replacing the defaults
+    /// (jpg,jpeg,jpe,jfif,png,webp,tif,tiff,bmp,gif,heic,heif,avif,cr2,nef,arw,dng,raf,orf,
+    /// rw2,pef,svg,svgz).
+    /// Prefix with '+' to extend them instead (e.g. "+qoi")
+    #[arg(long, value_name = "LIST")]
+    extensions: Option<String>,
+
+    /// Also process hidden files and folders (names starting with '.', e.g. macOS "._" files)
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Follow symlinked files and folders inside the input folder (skipped by default)
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Only process files matching this glob (repeatable). Matched against the file name,
+    /// or against the path relative to the input folder if the pattern contains a '/'.
+    /// Listed paths are matched relative to the list's folder, or the current one for --stdin
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    pattern: Vec<glob::Pattern>,
+
+    /// Read one image from standard input and write the bordered result to standard output,
+    /// logging to standard error. Requires a --format other than auto
+    #[arg(
+        long,
+        requires = "format",
+        conflicts_with_all = ["input", "input_flag", "files_from", "stdin", "null", "watch"]
+    )]
+    pipe: bool,
+
+    /// Output encoding: follow each input (PNG stays PNG, everything else becomes JPEG), or
+    /// write every image as JPEG, PNG, WebP, AVIF (needs the avif-encode feature) or TIFF.
+    /// Images with transparency are blended onto the border for JPEG
+    #[arg(long, value_enum, default_value_t = FormatChoice::Auto)]
+    format: FormatChoice,
+
+    /// Keep the decoded part of truncated JPEGs, filling the missing area with the border
+    /// color instead of the decoder's grey
+    #[arg(long)]
+    salvage: bool,
+
+    /// After the initial pass, keep watching the input folders and process new or changed
+    /// images as they appear, until Ctrl-C
+    #[arg(long)]
+    watch: bool,
+
+    /// Only process images of this orientation (read from the file header only)
+    #[arg(long, value_enum, value_name = "ORIENTATION")]
+    only: Option<Orientation>,
+
+    /// Order in which the images of a folder are processed. Files from --files-from or
+    /// --stdin keep their listed order
+    #[arg(long, value_enum, default_value_t = SortKey::Name)]
+    sort_by: SortKey,
+
+    /// Reverse the --sort-by order (e.g. largest files first)
+    #[arg(long)]
+    reverse: bool,
+
+    /// Give up on downloading a URL input after this many seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    timeout: u64,
+
+    /// Fail URL inputs whose download is larger than this, e.g. "100M"
+    #[arg(long, value_name = "SIZE", default_value = "100M", value_parser = parse_size)]
+    max_download_size: u64,
+
+    /// Skip files larger than this on disk, e.g. "50M" or "1.5G" (K/M/G/T are powers of
+    /// 1024). The size is checked before decoding. Unlimited by default
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Skip images with more than this many megapixels (read from the file header only), so
+    /// absurdly large files can't exhaust memory. 0 disables the limit
+    #[arg(long, value_name = "MP", default_value_t = 250.0, value_parser = parse_megapixels)]
+    max_megapixels: f64,
+
+    /// Skip images narrower than this many pixels (read from the file header only)
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 0,
+        conflicts_with = "min_dimensions"
+    )]
+    min_width: u32,
+
+    /// Skip images shorter than this many pixels (read from the file header only)
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 0,
+        conflicts_with = "min_dimensions"
+    )]
+    min_height: u32,
+
+    /// Skip images smaller than WIDTHxHEIGHT, e.g. "400x400" (shorthand for --min-width
+    /// and --min-height)
+    #[arg(long, value_name = "WxH", value_parser = parse_dimensions)]
+    min_dimensions: Option<(u32, u32)>,
+
+    /// Only process images taken on or after this date ("2024-06-01") or within this long
+    /// before now ("12h", "7d", "2w"). Uses the EXIF capture date, falling back to the
+    /// file's modification time
+    #[arg(long, value_name = "DATE", value_parser = capture_date::parse_since)]
+    since: Option<i64>,
+
+    /// Only process images taken on or before this date (inclusive) or at least this long
+    /// before now; same formats as --since
+    #[arg(long, value_name = "DATE", value_parser = capture_date::parse_until)]
+    until: Option<i64>,
+
+    /// Only process the N most recently modified images, after all other filters. Older
+    /// ones are reported as skipped
+    #[arg(long, value_name = "N")]
+    newest: Option<usize>,
+
+    /// Only process images modified within this long before now, e.g. "30m", "2h" or "7d"
+    #[arg(long, value_name = "DURATION", value_parser = capture_date::parse_age)]
+    modified_within: Option<i64>,
+
+    /// Whether --since/--until keep images that have no date at all
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = MissingDate::Include)]
+    missing_date: MissingDate,
+
+    /// Skip files matching this glob (repeatable, same matching rules as --pattern).
+    /// Excludes win over --pattern
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    exclude: Vec<glob::Pattern>,
 }
 
-#[derive(Clone, Copy)]
+fn parse_glob(s: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(s).map_err(|e| format!("invalid glob pattern '{}': {}", s, e))
+}
+
+/// Parses a `--size` value, which unlike the minimum dimensions can't be zero.
+fn parse_canvas_size(s: &str) -> Result<(u32, u32), String> {
+    match parse_dimensions(s)? {
+        (0, _) | (_, 0) => Err(format!(
+            "invalid size '{}' (width and height can't be 0)",
+            s
+        )),
+        size => Ok(size),
+    }
+}
+
+/// Parses a "WIDTHxHEIGHT" pair such as "400x300".
+fn parse_dimensions(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || {
+        format!(
+            "invalid dimensions '{}' (expected WIDTHxHEIGHT, e.g. 400x400)",
+            s
+        )
+    };
+    let (w, h) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let w = w.trim().parse().map_err(|_| invalid())?;
+    let h = h.trim().parse().map_err(|_| invalid())?;
+    Ok((w, h))
+}
+
+const SIZE_UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+/// Parses a byte count with an optional K/M/G/T suffix (and optional trailing "B" or "iB").
+fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size '{}' (expected e.g. 500K, 50M or 1.5G)", s);
+    let upper = s.trim().to_uppercase();
+    let upper = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let (number, exponent) = match upper.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let exponent = "KMGT".find(c).ok_or_else(invalid)? + 1;
+            (&upper[..i], exponent as i32)
+        }
+        _ => (upper, 0),
+    };
+    let value: f64 = number.trim().parse().map_err(|_| invalid())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid());
+    }
+    Ok((value * 1024f64.powi(exponent)).round() as u64)
+}
+
+/// Parses a `--blur-radius`: a positive number of pixels.
+fn parse_blur_radius(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(radius) if radius.is_finite() && radius > 0.0 => Ok(radius),
+        _ => Err(format!(
+            "invalid blur radius '{}' (expected a positive number, e.g. 40)",
+            s
+        )),
+    }
+}
+
+/// Parses a `--background-darken` amount between 0.0 and 1.0.
+fn parse_darken(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(amount) if (0.0..=1.0).contains(&amount) => Ok(amount),
+        _ => Err(format!(
+            "invalid amount '{}' (expected 0.0 to 1.0, e.g. 0.3)",
+            s
+        )),
+    }
+}
+
+/// Parses a `--square-tolerance` between 0.0 and 1.0.
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(tolerance) if (0.0..=1.0).contains(&tolerance) => Ok(tolerance),
+        _ => Err(format!(
+            "invalid tolerance '{}' (expected 0.0 to 1.0, e.g. 0.02)",
+            s
+        )),
+    }
+}
+
+/// Parses a `--panorama-threshold`: an aspect ratio above 1.0.
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(threshold) if threshold.is_finite() && threshold > 1.0 => Ok(threshold),
+        _ => Err(format!(
+            "invalid aspect ratio '{}' (expected more than 1.0, e.g. 2.0)",
+            s
+        )),
+    }
+}
+
+/// Parses a `--max-upscale` factor: 1.0 (no enlargement) or more.
+fn parse_max_upscale(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor >= 1.0 => Ok(factor),
+        _ => Err(format!(
+            "invalid upscale factor '{}' (expected 1.0 or more, e.g. 1.5)",
+            s
+        )),
+    }
+}
+
+/// Parses a `--max-megapixels` limit: 0 (no limit) or more.
+fn parse_megapixels(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(megapixels) if megapixels.is_finite() && megapixels >= 0.0 => Ok(megapixels),
+        _ => Err(format!(
+            "invalid megapixels '{}' (expected 0 or more, e.g. 100)",
+            s
+        )),
+    }
+}
+
+/// Formats a byte count for humans, in the same powers of 1024 `parse_size` accepts.
+pub fn format_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < SIZE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, SIZE_UNITS[unit])
+    }
+}
+
+#[derive(Clone)]
 struct Config {
     target_width: u32,
     target_height: u32,
+    /// `--preset`, whose size and ratios are the defaults of the flags above and below.
+    preset: Option<&'static Preset>,
+    /// Canvas sizes from `--size`, without repeats; empty for the single target size.
+    sizes: Vec<(u32, u32)>,
+    /// Canvas sizes of the orientation classes given one, e.g. with `--portrait-size`.
+    class_sizes: Vec<(Orientation, (u32, u32))>,
+    /// Longest edge of the `--thumbnail` written with each output.
+    thumbnail: Option<u32>,
+    /// `--rotate`, applied to every image as it is decoded.
+    rotation: Rotation,
+    /// Write a JSON report next to each output (`--write-sidecar`).
+    write_sidecar: bool,
     landscape_vert_border: f64,
     landscape_horiz_border: f64,
     portrait_vert_border: f64,
     portrait_horiz_border: f64,
+    square_vert_border: f64,
+    square_horiz_border: f64,
+    /// `--square-tolerance`.
+    square_tolerance: f64,
+    panorama_vert_border: f64,
+    panorama_horiz_border: f64,
+    panorama_threshold: f64,
+    /// (vertical, horizontal) border widths in pixels from `--border-px`, which replace the
+    /// ratios.
+    border_px: Option<(u32, u32)>,
+    /// Per-side overrides from `--border-top` and friends.
+    border_top: Option<BorderSize>,
+    border_bottom: Option<BorderSize>,
+    border_left: Option<BorderSize>,
+    border_right: Option<BorderSize>,
+    /// `--min-border-px`: the image is scaled down further if any side would get less.
+    min_border_px: Option<u32>,
+    /// Most an image is enlarged by: 1.0 with `--no-upscale`, or `--max-upscale`.
+    max_upscale: Option<f64>,
+    fit_mode: FitMode,
+    crop_gravity: CropGravity,
+    gravity: Gravity,
+    /// `--offset-x` and `--offset-y`: how far the image is moved from where `gravity` puts it.
+    offset: (i32, i32),
+    /// `--pad-only`: each canvas is the image's own size plus its borders.
+    pad_only: bool,
+    /// `--wrap`: each canvas is the scaled image's size plus its borders.
+    wrap: bool,
+    /// `--longest-edge`: each canvas has the image's aspect ratio and this longest side.
+    longest_edge: Option<u32>,
+    /// Where `--crop-gravity smart` put the crop window on the current image, as
+    /// `CropGravity::position` does; `None` to keep it centered.
+    crop_focus: Option<(f64, f64)>,
+    /// `--frames`, outermost first; the outermost's color is also `border_color`.
+    frames: Vec<Frame>,
+    /// `--style`, whose borders apply unless `--border-px` is given.
+    style: Option<Style>,
+    /// The fixed `--border-color`, or the one picked for the image being processed.
+    border_color: BorderColor,
+    /// `--border-color auto`, until a color is picked for an image.
+    auto_color: Option<AutoColorMode>,
+    background: Background,
+    blur_radius: f32,
+    background_darken: f32,
+    corner_radius: Option<CornerRadius>,
+    shadow: Option<Shadow>,
+    /// Width of the `--keyline` around the photo, if one is drawn.
+    keyline: Option<u32>,
+    keyline_color: BorderColor,
+    caption: Option<Caption>,
+    /// The `--caption` of the current image, placeholders filled in.
+    caption_text: Option<String>,
     jpeg_quality: u8,
+    jpeg_progressive: bool,
+    jpeg_subsampling: Subsampling,
+    jpeg_encoder: EncoderChoice,
+    webp_quality: u8,
+    webp_lossless: bool,
+    avif_quality: u8,
+    avif_speed: u8,
+    tiff_compression: TiffCompression,
+    png_compression: PngCompression,
+    png_filter: PngFilter,
+    optimize_png: bool,
+    optimize_level: u8,
+    /// `--max-output-size` of lossy outputs, and the `--min-quality` it may lower them to.
+    max_output_size: Option<u64>,
+    min_quality: u8,
+    metadata: MetadataPolicy,
+    strip_gps: bool,
+    stamp_metadata: bool,
+    dpi: Option<u16>,
+    bit_depth: BitDepth,
+    animated: AnimatedMode,
+    zip_output: ZipOutput,
+    prefix: String,
+    suffix: String,
+    name_template: Option<NameTemplate>,
+    rename_by_date: Option<DatePattern>,
+    normalize_extension: Option<NormalizeExtension>,
+    /// `--number`, padded for the run's inputs once they are known.
+    number: Option<Sequence>,
+    on_collision: OnCollision,
     separate_folder: bool,
+    /// Destination from `--output-dir`; replaces the `separate_folder` layout when set.
+    output_dir: Option<PathBuf>,
+    /// Archive all outputs are written into (`--output-zip`); output paths are then entry
+    /// names, relative to the archive.
+    output_zip: Option<PathBuf>,
+    /// Drop the subfolders of recursive inputs from output paths (`--flatten`).
+    flatten: bool,
+    /// Where originals are kept when images are replaced (`--in-place`), or restored from
+    /// (`--restore`).
+    in_place: Option<Backup>,
+    /// Report the planned outputs instead of writing them (`--dry-run`).
+    dry_run: bool,
+    /// Overwrite existing outputs (`--force`) instead of skipping their inputs.
+    force: bool,
+    incremental: bool,
+    incremental_ignore_settings: bool,
+    preserve_mtime: bool,
+    mtime_from_exif: bool,
+    reprocess_outputs: bool,
+    recursive: bool,
+    follow_symlinks: bool,
+    include_hidden: bool,
+    extensions: Vec<String>,
+    include_patterns: Vec<glob::Pattern>,
+    exclude_patterns: Vec<glob::Pattern>,
+    max_file_size: Option<u64>,
+    /// Pixel count limit from `--max-megapixels`, `None` when disabled.
+    max_pixels: Option<u64>,
+    min_width: u32,
+    min_height: u32,
+    /// Capture time bounds from `--since`/`--until`, in seconds since the Unix epoch.
+    since: Option<i64>,
+    until: Option<i64>,
+    missing_date: MissingDate,
+    newest: Option<usize>,
+    /// Cutoff from `--modified-within`, in seconds since the Unix epoch.
+    modified_after: Option<i64>,
+    only: Option<Orientation>,
+    sort_by: SortKey,
+    reverse: bool,
+    watch: bool,
+    salvage: bool,
+    /// Output encoding forced by `--format` or a sidecar file, instead of following the input.
+    format: Option<OutputFormat>,
+    download_timeout: Duration,
+    max_download_size: u64,
 }
 
 impl Config {
     fn from_args(args: &Args) -> Self {
+        let mut sizes = Vec::new();
+        for &size in &args.sizes {
+            if !sizes.contains(&size) {
+                sizes.push(size);
+            }
+        }
+        let preset = match args.preset {
+            Some(PresetChoice::Preset(preset)) => Some(preset),
+            _ => None,
+        };
+        let vert = preset.map(|preset| preset.vertical);
+        let horiz = preset.map(|preset| preset.horizontal);
         Self {
-            target_width: args.width,
-            target_height: args.height,
-            landscape_vert_border: args.landscape_vert,
-            landscape_horiz_border: args.landscape_horiz,
-            portrait_vert_border: args.portrait_vert,
-            portrait_horiz_border: args.portrait_horiz,
-            jpeg_quality: args.jpeg_quality,
+            target_width: args
+                .width
+                .or(preset.map(|preset| preset.width))
+                .unwrap_or(1080),
+            target_height: args
+                .height
+                .or(preset.map(|preset| preset.height))
+                .unwrap_or(1080),
+            preset,
+            sizes,
+            class_sizes: [
+                (Orientation::Landscape, args.landscape_size),
+                (Orientation::Portrait, args.portrait_size),
+                (Orientation::Square, args.square_size),
+                (Orientation::Panorama, args.panorama_size),
+                (Orientation::Tall, args.tall_size),
+            ]
+            .into_iter()
+            .filter_map(|(orientation, size)| Some((orientation, size?)))
+            .collect(),
+            thumbnail: args.thumbnail,
+            rotation: args.rotate,
+            write_sidecar: args.write_sidecar,
+            landscape_vert_border: args.landscape_vert.or(vert).unwrap_or(0.05),
+            landscape_horiz_border: args.landscape_horiz.or(horiz).unwrap_or(0.03),
+            portrait_vert_border: args.portrait_vert.or(vert).unwrap_or(0.005),
+            portrait_horiz_border: args.portrait_horiz.or(horiz).unwrap_or(0.18),
+            square_vert_border: args.square_vert.or(vert).unwrap_or(0.05),
+            square_horiz_border: args.square_horiz.or(horiz).unwrap_or(0.05),
+            square_tolerance: args.square_tolerance,
+            panorama_vert_border: args.panorama_vert.or(vert).unwrap_or(0.05),
+            panorama_horiz_border: args.panorama_horiz.or(horiz).unwrap_or(0.015),
+            panorama_threshold: args.panorama_threshold,
+            border_px: match (args.border_px, args.border_px_vert, args.border_px_horiz) {
+                (None, None, None) => None,
+                (all, vert, horiz) => Some((vert.or(all).unwrap_or(0), horiz.or(all).unwrap_or(0))),
+            },
+            border_top: args.border_top,
+            border_bottom: args.border_bottom,
+            border_left: args.border_left,
+            border_right: args.border_right,
+            min_border_px: args.min_border_px,
+            max_upscale: args.no_upscale.then_some(1.0).or(args.max_upscale),
+            fit_mode: args.fit,
+            crop_gravity: args.crop_gravity,
+            gravity: args.gravity,
+            offset: (args.offset_x, args.offset_y),
+            pad_only: args.pad_only,
+            wrap: args.wrap,
+            longest_edge: args.longest_edge,
+            crop_focus: None,
+            frames: args.frames.clone(),
+            style: args.style,
+            border_color: match args.border_color {
+                Some(BorderColorChoice::Fixed(color)) => color,
+                Some(BorderColorChoice::Auto) => BorderColor::WHITE,
+                None => match args.frames.first() {
+                    Some(frame) => frame.color,
+                    None => args.style.map_or(BorderColor::WHITE, Style::border_color),
+                },
+            },
+            auto_color: (args.border_color == Some(BorderColorChoice::Auto))
+                .then(|| args.auto_color_mode.unwrap_or(AutoColorMode::Average)),
+            background: args.background,
+            blur_radius: args.blur_radius,
+            background_darken: args.background_darken,
+            corner_radius: args.corner_radius,
+            shadow: args.shadow.then_some(Shadow {
+                offset: args.shadow_offset,
+                blur: args.shadow_blur,
+                opacity: args.shadow_opacity,
+            }),
+            keyline: args.keyline,
+            keyline_color: args.keyline_color.unwrap_or(BorderColor::BLACK),
+            caption: args
+                .caption
+                .clone()
+                .or_else(|| args.caption_exif.clone())
+                .map(|text| Caption {
+                    text,
+                    from_exif: args.caption_exif.is_some(),
+                    missing: args.caption_exif_missing.clone(),
+                    font: args.caption_font.clone().or_else(caption::default_font),
+                    size: args.caption_size.unwrap_or(caption::DEFAULT_SIZE),
+                    color: args.caption_color,
+                    align: args.caption_align,
+                }),
+            caption_text: None,
+            jpeg_quality: args.jpeg_quality.unwrap_or(100),
+            jpeg_progressive: args.jpeg_progressive,
+            jpeg_subsampling: args.jpeg_subsampling,
+            jpeg_encoder: args.jpeg_encoder,
+            webp_quality: args.webp_quality,
+            webp_lossless: args.webp_lossless,
+            avif_quality: args.avif_quality,
+            avif_speed: args.avif_speed,
+            tiff_compression: args.tiff_compression,
+            png_compression: args.png_compression,
+            png_filter: args.png_filter,
+            optimize_png: args.optimize_png,
+            optimize_level: args.optimize_level,
+            max_output_size: args.max_output_size,
+            min_quality: args.min_quality,
+            metadata: args.metadata.unwrap_or(MetadataPolicy::Keep),
+            strip_gps: args.strip_gps,
+            stamp_metadata: args.stamp_metadata,
+            dpi: args.dpi,
+            bit_depth: args.bit_depth,
+            animated: args.animated,
+            zip_output: args.zip_output,
+            prefix: args.prefix.clone(),
+            suffix: args.suffix.clone(),
+            name_template: args.name_template.clone(),
+            rename_by_date: args.rename_by_date.clone(),
+            normalize_extension: args.normalize_extension,
+            number: args.number.then(|| Sequence::new(args.number_start)),
+            on_collision: args.on_collision,
             separate_folder: args.separate_folder,
+            output_dir: args.output_dir.clone(),
+            output_zip: args.output_zip.clone(),
+            flatten: args.flatten,
+            in_place: (args.in_place || args.restore).then_some(args.backup),
+            dry_run: args.dry_run,
+            force: args.force,
+            incremental: args.incremental,
+            incremental_ignore_settings: args.incremental_ignore_settings,
+            preserve_mtime: args.preserve_mtime,
+            mtime_from_exif: args.mtime_from_exif,
+            reprocess_outputs: args.reprocess_outputs,
+            recursive: args.recursive,
+            follow_symlinks: args.follow_symlinks,
+            include_hidden: args.include_hidden,
+            extensions: parse_extensions(args.extensions.as_deref()),
+            include_patterns: args.pattern.clone(),
+            exclude_patterns: args.exclude.clone(),
+            max_file_size: args.max_file_size,
+            max_pixels: (args.max_megapixels > 0.0).then_some((args.max_megapixels * 1e6) as u64),
+            min_width: args.min_dimensions.map_or(args.min_width, |(w, _)| w),
+            min_height: args.min_dimensions.map_or(args.min_height, |(_, h)| h),
+            since: args.since,
+            until: args.until,
+            missing_date: args.missing_date,
+            newest: args.newest,
+            modified_after: args.modified_within,
+            only: args.only,
+            sort_by: args.sort_by,
+            reverse: args.reverse,
+            watch: args.watch,
+            salvage: args.salvage,
+            format: args.format.forced(),
+            download_timeout: Duration::from_secs(args.timeout),
+            max_download_size: args.max_download_size,
         }
     }
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    /// The canvas size of each output of an image: the `--size` list, or `None` for the
+    /// single output at `--width`x`--height`.
+    fn output_sizes(&self) -> Vec<Option<(u32, u32)>> {
+        if self.sizes.is_empty() {
+            vec![None]
+        } else {
+            self.sizes.iter().copied().map(Some).collect()
+        }
+    }
 
-    let config = Config::from_args(&args);
-    let input_folder = args
-        .input
-        .as_ref()
-        .or(args.input_flag.as_ref())
-        .cloned()
-        .ok_or("Error: Input folder is required (pass as argument or use -i/--input)")?;
-    let using_defaults = std::env::args().len() == 2
-        && std::env::args().nth(1).map(|a| !a.starts_with('-')).unwrap_or(false);
+    /// Returns the settings for the `--thumbnail` of an output. Thumbnails are small anyway;
+    /// only the full output is held to `--max-output-size`.
+    fn for_thumbnail(&self) -> Config {
+        Config {
+            max_output_size: None,
+            ..self.clone()
+        }
+    }
 
-    print_config(&config, using_defaults);
+    /// With `--border-color auto`, picks the border color for `img` and reports it under
+    /// `label`. `None` with a fixed color.
+    fn pick_border_color(&self, img: &DynamicImage, label: &str) -> Option<BorderColor> {
+        let color = border_color::pick(img, self.auto_color?);
+        match color {
+            Some(color) => println!("🎨 Border color for {}: {}", label, color),
+            None => println!(
+                "🎨 Border color for {}: {} (nothing opaque to pick from)",
+                label, self.border_color
+            ),
+        }
+        color
+    }
 
-    let main_start = Instant::now();
+    /// Returns the settings with `picked` as the border color, if one was picked.
+    fn with_border_color(&self, picked: Option<BorderColor>) -> Cow<'_, Config> {
+        match picked {
+            Some(color) => Cow::Owned(Config {
+                border_color: color,
+                auto_color: None,
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
 
-    let output_folder: PathBuf = if config.separate_folder {
-        input_folder.join("bordered_images")
-    } else {
-        input_folder.clone()
-    };
+    /// With `--crop-gravity smart`, returns the settings with the crop window picked for
+    /// `img`; unchanged if nothing is cropped off it or no part of it stands out.
+    fn with_crop_focus(&self, img: &DynamicImage) -> Cow<'_, Config> {
+        if self.crop_gravity != CropGravity::Smart {
+            return Cow::Borrowed(self);
+        }
+        let placement = Placement::of(img.width(), img.height(), self);
+        match gravity::smart_position(img, placement) {
+            Some(focus) => Cow::Owned(Config {
+                crop_focus: Some(focus),
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
 
-    if config.separate_folder {
-        std::fs::create_dir_all(&output_folder)?;
+    /// With `--caption`, returns the settings with its text for the image whose file name
+    /// has `stem`, its date and EXIF read from `source`.
+    fn with_caption(&self, stem: &str, source: CaptionSource) -> Cow<'_, Config> {
+        let Some(caption) = &self.caption else {
+            return Cow::Borrowed(self);
+        };
+        Cow::Owned(Config {
+            caption_text: caption.text_for(stem, source),
+            ..self.clone()
+        })
     }
 
-    let entries = std::fs::read_dir(&input_folder)?;
-    let mut total_ok = 0usize;
-    let mut total_fail = 0usize;
-    let mut total_duration = std::time::Duration::ZERO;
-    let mut fastest: Option<(String, std::time::Duration)> = None;
-    let mut slowest: Option<(String, std::time::Duration)> = None;
+    /// Returns the settings for the output at `size`.
+    fn at_size(&self, size: Option<(u32, u32)>) -> Cow<'_, Config> {
+        match size {
+            Some((width, height)) => Cow::Owned(Config {
+                target_width: width,
+                target_height: height,
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
 
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
+    /// The canvas size of the single output of an image of `width` x `height`, if its
+    /// orientation class has one of its own (`--portrait-size` and friends), `--pad-only` or
+    /// `--wrap` grows one around it or `--longest-edge` gives it one of its shape.
+    fn class_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if let Some(((width, height), borders)) = self.wrapping(width, height) {
+            let canvas_width = width + (borders.left + borders.right) as u32;
+            let canvas_height = height + (borders.top + borders.bottom) as u32;
+            return Some(match self.wrap {
+                // Rounding may have taken a side a pixel past the bounds.
+                true => {
+                    let (max_width, max_height) = self.wrap_bounds();
+                    (canvas_width.min(max_width), canvas_height.min(max_height))
+                }
+                false => (canvas_width, canvas_height),
+            });
         }
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
-        if ext != "jpg" && ext != "jpeg" && ext != "png" {
-            continue;
+        if let Some(edge) = self.longest_edge {
+            let scale = edge as f64 / width.max(height).max(1) as f64;
+            let side = |length: u32| ((length as f64 * scale).round() as u32).clamp(1, edge);
+            return Some((side(width), side(height)));
         }
+        let orientation = Orientation::of(width, height, self);
+        self.class_sizes
+            .iter()
+            .find(|&&(class, _)| class == orientation)
+            .map(|&(_, size)| size)
+    }
 
-        let filename = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-        let output_path = output_folder.join(format!("{}{}", args.prefix, filename));
-
-        let start = Instant::now();
-        match process_image(&path, &output_path, &config) {
-            Ok(()) => {
-                total_ok += 1;
-                let elapsed = start.elapsed();
-                total_duration += elapsed;
-                println!("✅ Successfully processed {} in {:.2} seconds", filename, elapsed.as_secs_f64());
-                if fastest.as_ref().map(|(_, d)| elapsed < *d).unwrap_or(true) {
-                    fastest = Some((filename.clone(), elapsed));
-                }
-                if slowest.as_ref().map(|(_, d)| elapsed > *d).unwrap_or(true) {
-                    slowest = Some((filename, elapsed));
-                }
+    /// With `--pad-only` or `--wrap`, the size an image of `width` x `height` is scaled to
+    /// and its borders in whole pixels: those its orientation gets on a canvas of the scaled
+    /// image's size, so ratios are fractions of the image, and at least `--min-border-px`.
+    /// `--pad-only` keeps the image's own size; `--wrap` scales it so that it and its borders
+    /// just fit the `wrap_bounds`.
+    fn wrapping(&self, width: u32, height: u32) -> Option<((u32, u32), Borders)> {
+        if !self.pad_only && !self.wrap {
+            return None;
+        }
+        let min = self.min_border_px.unwrap_or(0) as f64;
+        let borders = |(width, height): (u32, u32)| {
+            let config = self.at_size(Some((width, height)));
+            let borders = Borders::of(Orientation::of(width, height, self), &config);
+            let side = |border: f64| border.round().max(min);
+            Borders {
+                top: side(borders.top),
+                bottom: side(borders.bottom),
+                left: side(borders.left),
+                right: side(borders.right),
             }
-            Err(e) => {
-                total_fail += 1;
-                eprintln!("❌ Error processing {}: {}", filename, e);
+        };
+        let mut size = (width, height);
+        if self.wrap {
+            let (max_width, max_height) = self.wrap_bounds();
+            let cap = self.max_upscale.unwrap_or(f64::INFINITY);
+            // Ratios grow with the image but pixel borders don't, so the scale is refined
+            // until it settles; with ratios alone, the first pass is exact.
+            let mut scale = 1.0;
+            for _ in 0..WRAP_PASSES {
+                let around = borders(size);
+                let canvas_width = size.0 as f64 + around.left + around.right;
+                let canvas_height = size.1 as f64 + around.top + around.bottom;
+                scale *= (max_width as f64 / canvas_width).min(max_height as f64 / canvas_height);
+                scale = scale.min(cap);
+                let scaled = |length: u32| ((length as f64 * scale).round() as u32).max(1);
+                size = (scaled(width), scaled(height));
             }
         }
+        Some((size, borders(size)))
     }
 
-    let main_elapsed = main_start.elapsed();
-    println!("\nTotal execution time: {:.2} seconds", main_elapsed.as_secs_f64());
-    println!("\n📊 === Processing Summary ===");
-    println!("✅ Total images processed: {}", total_ok);
-    println!("❌ Failed images: {}", total_fail);
-    if total_ok > 0 {
-        let avg = total_duration.as_secs_f64() / total_ok as f64;
-        println!("⏱️  Average processing time: {:.2} seconds", avg);
-        if let Some((name, d)) = &fastest {
-            println!("🚀 Fastest image: {} ({:.2} seconds)", name, d.as_secs_f64());
-        }
-        if let Some((name, d)) = &slowest {
-            println!("🐢 Slowest image: {} ({:.2} seconds)", name, d.as_secs_f64());
+    /// The box `--wrap` fits each image and its borders into: `--width` x `--height`, or a
+    /// square of `--longest-edge`.
+    fn wrap_bounds(&self) -> (u32, u32) {
+        match self.longest_edge {
+            Some(edge) => (edge, edge),
+            None => (self.target_width, self.target_height),
         }
     }
-    println!();
 
-    Ok(())
-}
+    /// With `--pad-only` or `--wrap`, returns the settings for an image of `dimensions`: its
+    /// borders from `wrapping` as fixed ones around a canvas just large enough, so it fills
+    /// the area inside them. `--wrap` fits it into the settings' own size, so this comes
+    /// before `at_size`.
+    fn padded(&self, dimensions: Option<(u32, u32)>) -> Cow<'_, Config> {
+        let Some((width, height)) = dimensions else {
+            return Cow::Borrowed(self);
+        };
+        let Some((_, padding)) = self.wrapping(width, height) else {
+            return Cow::Borrowed(self);
+        };
+        let (target_width, target_height) = self.class_size(width, height).unwrap_or_default();
+        let pixels = |border: f64| Some(BorderSize::Pixels(border as u32));
+        Cow::Owned(Config {
+            target_width,
+            target_height,
+            border_top: pixels(padding.top),
+            border_bottom: pixels(padding.bottom),
+            border_left: pixels(padding.left),
+            border_right: pixels(padding.right),
+            ..self.clone()
+        })
+    }
 
-fn print_config(config: &Config, using_defaults: bool) {
-    println!("\n=== Configuration ===");
-    if using_defaults {
-        println!("Using default configuration (no flags provided)");
+    /// The colors the borders are drawn in: the border color and those of the `--frames`.
+    fn border_colors(&self) -> impl Iterator<Item = BorderColor> + '_ {
+        std::iter::once(self.border_color).chain(self.frames.iter().map(|frame| frame.color))
     }
-    println!(
-        "Target dimensions: {}x{}",
-        config.target_width, config.target_height
-    );
-    println!(
-        "Landscape borders: Vertical={:.1}%, Horizontal={:.1}%",
-        config.landscape_vert_border * 100.0,
-        config.landscape_horiz_border * 100.0
-    );
-    println!(
-        "Portrait borders: Vertical={:.1}%, Horizontal={:.1}%",
-        config.portrait_vert_border * 100.0,
-        config.portrait_horiz_border * 100.0
-    );
-    println!("JPEG quality: {}", config.jpeg_quality);
-    println!("Separate output folder: {}", config.separate_folder);
-    println!("==================\n");
-}
 
-fn process_image(
-    input_path: &Path,
-    output_path: &Path,
-    config: &Config,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let img = image::open(input_path)?.to_rgba8();
-    let (orig_width, orig_height) = img.dimensions();
-    let is_landscape = orig_width > orig_height;
+    /// Checks that the borders leave room for the image on every canvas size, for every
+    /// orientation.
+    fn check_borders(&self) -> Result<(), String> {
+        // The canvas grows to whatever the borders need.
+        if self.pad_only || self.wrap {
+            return Ok(());
+        }
+        let class_sizes = self.class_sizes.iter().map(|&(_, size)| Some(size));
+        // Every shape of canvas can't be checked; a square one catches ratios that leave
+        // no room on any.
+        let longest_edge = self.longest_edge.map(|edge| Some((edge, edge)));
+        for size in self
+            .output_sizes()
+            .into_iter()
+            .chain(class_sizes)
+            .chain(longest_edge)
+        {
+            let config = self.at_size(size);
+            for &orientation in Orientation::value_variants() {
+                let borders = Borders::of(orientation, &config);
+                let (max_width, max_height) = borders.min_border_limits(&config);
+                if borders.available_width(&config).min(max_width) < 1.0
+                    || borders.available_height(&config).min(max_height) < 1.0
+                {
+                    let orientation = orientation.to_possible_value();
+                    return Err(format!(
+                        "the borders leave no room for {} images on a {}x{} canvas (top {:.0}, \
+                         bottom {:.0}, left {:.0}, right {:.0} px{})",
+                        orientation
+                            .map(|v| v.get_name().to_string())
+                            .unwrap_or_default(),
+                        config.target_width,
+                        config.target_height,
+                        borders.top,
+                        borders.bottom,
+                        borders.left,
+                        borders.right,
+                        match config.min_border_px {
+                            Some(min) => format!(", at least {} px each", min),
+                            None => String::new(),
+                        }
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
-    let (vert_ratio, horiz_ratio) = if is_landscape {
-        (config.landscape_vert_border, config.landscape_horiz_border)
-    } else {
-        (config.portrait_vert_border, config.portrait_horiz_border)
+/// Resolves the `--extensions` value into a lowercase extension list.
+fn parse_extensions(value: Option<&str>) -> Vec<String> {
+    let defaults = DEFAULT_EXTENSIONS.iter().map(|e| e.to_string());
+    let Some(value) = value else {
+        return defaults.collect();
     };
+    let (mut extensions, list): (Vec<String>, &str) = match value.strip_prefix('+') {
+        Some(rest) => (defaults.collect(), rest),
+        None => (Vec::new(), value),
+    };
+    for ext in list.split(',') {
+        let ext = ext.trim().trim_start_matches('.').to_lowercase();
+        if !ext.is_empty() && !extensions.contains(&ext) {
+            extensions.push(ext);
+        }
+    }
+    extensions
+}
 
-    let available_width = config.target_width as f64 * (1.0 - 2.0 * horiz_ratio);
-    let available_height = config.target_height as f64 * (1.0 - 2.0 * vert_ratio);
-
-    let scale = (available_width / orig_width as f64).min(available_height / orig_height as f64);
-
-    let scaled_width = (orig_width as f64 * scale).round() as u32;
-    let scaled_height = (orig_height as f64 * scale).round() as u32;
-
-    // White canvas
-    let mut canvas: RgbaImage =
-        ImageBuffer::from_pixel(config.target_width, config.target_height, WHITE);
-
-    // Resize source image (bilinear-like filter)
-    let resized = imageops::resize(
-        &img,
-        scaled_width,
-        scaled_height,
-        FilterType::Triangle,
-    );
-
-    let offset_x = (config.target_width - scaled_width) / 2;
-    let offset_y = (config.target_height - scaled_height) / 2;
-
-    canvas.copy_from(&resized, offset_x, offset_y)?;
-
-    let out_ext = output_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|s| s.to_lowercase())
-        .unwrap_or_default();
-
-    if out_ext == "png" {
-        canvas.save(output_path)?;
-    } else {
-        let mut out_file = std::fs::File::create(output_path)?;
-        let mut encoder =
-            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out_file, config.jpeg_quality);
-        encoder.encode_image(&canvas)?;
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    if args.preset == Some(PresetChoice::List) {
+        presets::print_list();
+        return Ok(());
     }
 
-    Ok(())
+    let config = Config::from_args(&args);
+    if let Some(message) = config.format.and_then(OutputFormat::unavailable) {
+        return Err(format!("Error: {}", message).into());
+    }
+    if let Some(message) = config.jpeg_encoder.unavailable() {
+        return Err(format!("Error: {}", message).into());
+    }
+    if let Some(message) = config.caption.as_ref().and_then(Caption::unavailable) {
+        return Err(format!("Error: {}", message).into());
+    }
+    if config.optimize_png {
+        if let Some(message) = optimize::unavailable() {
+            return Err(format!("Error: {}", message).into());
+        }
+        if config
+            .format
+            .is_some_and(|format| format != OutputFormat::Png)
+        {
+            eprintln!("⚠️  --optimize-png has no effect unless outputs are PNG");
+        }
+    }
+    if config.pad_only && (args.width.is_some() || args.height.is_some()) {
+        eprintln!("⚠️  --width and --height are ignored with --pad-only");
+    }
+    if config.crop_gravity != CropGravity::Center && config.fit_mode != FitMode::Cover {
+        eprintln!("⚠️  --crop-gravity has no effect without --fit cover");
+    }
+    let fills = config.fit_mode == FitMode::Cover || config.pad_only || config.wrap;
+    if config.gravity != Gravity::Center && fills {
+        eprintln!("⚠️  --gravity has no effect with --fit cover, --pad-only or --wrap");
+    }
+    if config.offset != (0, 0) && fills {
+        eprintln!(
+            "⚠️  --offset-x and --offset-y have no effect with --fit cover, --pad-only or --wrap"
+        );
+    }
+    if args
+        .metadata
+        .is_some_and(|policy| policy != MetadataPolicy::Strip)
+    {
+        match config.format {
+            Some(OutputFormat::Tiff) => {
+                eprintln!("⚠️  TIFF outputs only keep the ICC profile, not EXIF or XMP")
+            }
+            Some(OutputFormat::Avif) => {
+                eprintln!("⚠️  --metadata has no effect on avif outputs, they are always stripped")
+            }
+            _ => {}
+        }
+    }
+    if config
+        .border_colors()
+        .any(|color| color == BorderColor::TRANSPARENT)
+    {
+        let flag = match config.frames.is_empty() {
+            true => "--border-color",
+            false => "--frames",
+        };
+        match config.format {
+            Some(format) if format.keeps_alpha() => {}
+            Some(format) => {
+                return Err(format!(
+                    "Error: {} transparent needs an output format with transparency, {} has \
+                     none; use e.g. --format png",
+                    flag,
+                    format
+                        .to_possible_value()
+                        .map(|v| v.get_name().to_string())
+                        .unwrap_or_default()
+                )
+                .into())
+            }
+            None => {
+                return Err(format!(
+                    "Error: {} transparent needs an output format with transparency, and \
+                     outputs keep their input's format by default; add e.g. --format png",
+                    flag
+                )
+                .into())
+            }
+        }
+    }
+    if let Err(message) = config.check_borders() {
+        return Err(format!("Error: {}", message).into());
+    }
+    if config.background == Background::Blur && args.border_color.is_some() {
+        return Err(
+            "Error: --background blur fills the borders with the image itself, so it \
+                    can't be combined with --border-color"
+                .into(),
+        );
+    }
+    if args.auto_color_mode.is_some() && config.auto_color.is_none() {
+        eprintln!("⚠️  --auto-color-mode has no effect unless --border-color is auto");
+    }
+    if config.strip_gps && config.metadata != MetadataPolicy::Keep {
+        let metadata = config.metadata.to_possible_value();
+        eprintln!(
+            "⚠️  --strip-gps has no effect with --metadata {}, which copies no location data",
+            metadata
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        );
+    }
+    if config.stamp_metadata {
+        if let Some(format @ (OutputFormat::Tiff | OutputFormat::Avif | OutputFormat::Gif)) =
+            config.format
+        {
+            eprintln!(
+                "⚠️  --stamp-metadata has no effect on {} outputs",
+                format
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default()
+            );
+        }
+    }
+    if config.dpi.is_some() {
+        if let Some(format @ (OutputFormat::Webp | OutputFormat::Avif)) = config.format {
+            eprintln!(
+                "⚠️  --dpi has no effect on {} outputs",
+                format
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default()
+            );
+        }
+    }
+    if config.max_output_size.is_some() {
+        if let Some(format) = config.format.filter(|&f| !size_limit::applies(f, &config)) {
+            eprintln!(
+                "⚠️  --max-output-size has no effect on {}{} outputs",
+                if format == OutputFormat::Webp {
+                    "lossless "
+                } else {
+                    ""
+                },
+                format
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default()
+            );
+        }
+    }
+    if let Some(quality) = args.jpeg_quality {
+        match config.format {
+            Some(format) if format != OutputFormat::Jpeg => eprintln!(
+                "⚠️  --jpeg-quality has no effect on {} outputs",
+                format
+                    .to_possible_value()
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default()
+            ),
+            _ if !(1..=100).contains(&quality) => {
+                return Err("Error: --jpeg-quality must be between 1 and 100".into())
+            }
+            _ => {}
+        }
+    }
+    if config.jpeg_progressive && config.format.is_some_and(|f| f != OutputFormat::Jpeg) {
+        return Err("Error: --jpeg-progressive needs JPEG outputs (--format jpg or auto)".into());
+    }
+    if args.pipe {
+        let Some(format) = config.format else {
+            return Err("Error: --pipe needs a --format other than auto".into());
+        };
+        if !config.sizes.is_empty() {
+            return Err("Error: --pipe writes a single image, it can't be used with --size".into());
+        }
+        print_config(&config, false, &mut std::io::stderr())?;
+        pipe::run(format, &config);
+    }
+
+    let inputs = resolve_inputs(&args, &config)?;
+    let replaceable = |input: &Input| !matches!(input.source, Source::Zip(_) | Source::Url(_));
+    if config.in_place.is_some() && !inputs.iter().all(replaceable) {
+        return Err("Error: ZIP and URL inputs can't be replaced in place".into());
+    }
+    if config.output_zip.is_some() && inputs.iter().any(|i| matches!(i.source, Source::Zip(_))) {
+        return Err("Error: ZIP inputs can't be written into --output-zip".into());
+    }
+    if args.restore {
+        let (restored, failed) = in_place::restore_all(&discover(&inputs, &config)?, args.backup);
+        println!("\n↩️  Restored originals: {}", restored);
+        if failed > 0 {
+            return Err(format!("Error: {} originals could not be restored", failed).into());
+        }
+        return Ok(());
+    }
+    let using_defaults = std::env::args().len() == 2
+        && std::env::args()
+            .nth(1)
+            .map(|a| !a.starts_with('-'))
+            .unwrap_or(false);
+
+    print_config(&config, using_defaults, &mut std::io::stdout())?;
+
+    let main_start = Instant::now();
+    let mut stats = Stats::new(inputs.iter().map(|input| input.label.as_str()));
+    let worklist = discover(&inputs, &config)?;
+    let config = Config {
+        number: config
+            .number
+            .map(|sequence| sequence.for_count(worklist.jobs.len())),
+        ..config
+    };
+    let plan = Plan::new(worklist, &config)?;
+    plan.print(&config, &mut std::io::stdout())?;
+    if let Some(map) = &args.flatten_map {
+        if let Err(e) = plan.write_map(map) {
+            return Err(format!("Error: could not write {}: {}", map.display(), e).into());
+        }
+    }
+
+    let mut zip = match config.output_zip.as_deref().filter(|_| !config.dry_run) {
+        Some(path) => match OutputZip::create(path) {
+            Ok(zip) => Some(zip),
+            Err(e) => {
+                return Err(format!("Error: could not create {}: {}", path.display(), e).into())
+            }
+        },
+        None => None,
+    };
+    run_plan(&plan, &config, &mut stats, &mut zip)?;
+    if let Some(zip) = zip {
+        match zip.finish() {
+            Ok((path, size)) => stats.record_archive(path, size),
+            Err(e) => {
+                return Err(format!("Error: could not finish the output archive: {}", e).into())
+            }
+        }
+    }
+    for (index, input) in inputs.iter().enumerate() {
+        if let Source::Zip(path) = &input.source {
+            if config.dry_run {
+                println!(
+                    "📝 {}: ZIP inputs aren't looked into by --dry-run",
+                    input.label
+                );
+                continue;
+            }
+            if let Err(e) = archive::process_zip(index, path, &config, &mut stats) {
+                stats.record_failure(index);
+                if atomic::is_disk_full(e.as_ref()) {
+                    return Err(disk_full(path));
+                }
+                eprintln!("❌ Error processing {}: {}", input.label, e);
+            }
+        }
+    }
+
+    if config.watch {
+        watch::watch(&inputs, &plan, &config, &mut stats)?;
+    }
+
+    let main_elapsed = main_start.elapsed();
+    println!(
+        "\nTotal execution time: {:.2} seconds",
+        main_elapsed.as_secs_f64()
+    );
+    stats.print_summary();
+
+    if config.dry_run && stats.failures() > 0 {
+        return Err(format!("Error: {} images would fail", stats.failures()).into());
+    }
+    Ok(())
+}
+
+/// Reports the skipped and rejected entries of a plan, then processes its jobs.
+///
+/// Only a full disk stops the run early: every later image would fail the same way.
+fn run_plan(
+    plan: &Plan,
+    config: &Config,
+    stats: &mut Stats,
+    zip: &mut Option<OutputZip>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for skipped in &plan.skipped {
+        report_skip(skipped, stats);
+    }
+    for rejected in &plan.rejected {
+        stats.record_failure(rejected.input);
+        eprintln!(
+            "❌ Error processing {}: {}",
+            rejected.label, rejected.reason
+        );
+    }
+    let mut settings = SettingsCache::default();
+    for item in &plan.items {
+        process_job(item, config, stats, &mut settings, zip)?;
+    }
+    Ok(())
+}
+
+/// The error a run stops with when writing `path` found the disk full.
+fn disk_full(path: &Path) -> Box<dyn std::error::Error> {
+    format!(
+        "Error: disk full while writing the output of {}, stopping",
+        path.display()
+    )
+    .into()
+}
+
+/// Prints a skipped file and records it into `stats`.
+fn report_skip(skipped: &Skipped, stats: &mut Stats) {
+    stats.record_skip(skipped);
+    match &skipped.detail {
+        Some(detail) => println!(
+            "⏭️  Skipped {} ({}, {})",
+            skipped.path.display(),
+            skipped.reason.describe(),
+            detail
+        ),
+        None => println!(
+            "⏭️  Skipped {} ({})",
+            skipped.path.display(),
+            skipped.reason.describe()
+        ),
+    }
+}
+
+/// Describes an output `--dry-run` found would be written, e.g.
+/// `photo.jpg → out/bordered_photo.jpg (landscape 6000x4000, scaled to 1728x1152 at 36,24)`.
+fn planned_output(item: &WorkItem, label: &str, path: &Path, config: &Config) -> String {
+    let geometry = match item.dimensions {
+        Some((width, height)) => {
+            let placement = Placement::of(width, height, config);
+            let orientation = Orientation::of(width, height, config).to_possible_value();
+            let (scaled_width, scaled_height) = placement.scaled_size();
+            let cropped = match placement.crop == Crop::default() {
+                true => String::new(),
+                false => format!(", cropped to {}x{}", placement.width, placement.height),
+            };
+            format!(
+                "{} {}x{}, scaled to {}x{}{} at {},{}{}",
+                orientation
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default(),
+                width,
+                height,
+                scaled_width,
+                scaled_height,
+                cropped,
+                placement.x,
+                placement.y,
+                fit_limit(placement, config)
+                    .into_iter()
+                    .chain(placement.offset_clamped.then(|| OFFSET_CLAMPED.to_string()))
+                    .map(|note| format!(", {}", note))
+                    .collect::<String>()
+            )
+        }
+        None => "size unknown until decoded".to_string(),
+    };
+    format!("{} → {} ({})", label, path.display(), geometry)
+}
+
+/// Tells what kept an image of `width` x `height` from filling the area inside its borders,
+/// e.g. `📐 photo.jpg: scaled to 600x400, upscaling prevented by --no-upscale`, and where it
+/// went if the canvas's edge stopped `--offset-x` or `--offset-y`; `None` if neither happened.
+fn fit_note(label: &str, width: u32, height: u32, config: &Config) -> Option<String> {
+    let placement = Placement::of(width, height, config);
+    let mut notes = Vec::new();
+    if let Some(limit) = fit_limit(placement, config) {
+        notes.push(format!(
+            "scaled to {}x{}, {}",
+            placement.width, placement.height, limit
+        ));
+    }
+    if placement.offset_clamped {
+        notes.push(format!(
+            "placed at {},{}, {}",
+            placement.x, placement.y, OFFSET_CLAMPED
+        ));
+    }
+    match notes.is_empty() {
+        true => None,
+        false => Some(format!("📐 {}: {}", label, notes.join("; "))),
+    }
+}
+
+/// What the notes on an image say when the canvas's edge stopped `--offset-x` or
+/// `--offset-y`.
+const OFFSET_CLAMPED: &str = "offset stopped at the canvas edge";
+
+/// What kept `placement` from filling the area inside the borders, e.g. "shrunk for the 32
+/// px --min-border-px"; `None` if nothing did.
+fn fit_limit(placement: Placement, config: &Config) -> Option<String> {
+    if placement.shrunk_for_min_border {
+        return Some(format!(
+            "shrunk for the {} px --min-border-px",
+            config.min_border_px?
+        ));
+    }
+    match config.max_upscale.filter(|_| placement.upscale_capped)? {
+        1.0 => Some("upscaling prevented by --no-upscale".to_string()),
+        factor => Some(format!("upscaling capped at {}x by --max-upscale", factor)),
+    }
+}
+
+/// Returns true if `a` and `b` both exist and are the same file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Processes one image of the worklist, recording the result into `stats`. Outputs go into
+/// `zip` when one is given (`--output-zip`).
+///
+/// Errors are reported and recorded; only a full disk is returned.
+fn process_job(
+    item: &WorkItem,
+    config: &Config,
+    stats: &mut Stats,
+    settings: &mut SettingsCache,
+    zip: &mut Option<OutputZip>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let job = &item.job;
+    let path = &job.path;
+    let filename = match &job.url {
+        Some(url) => url.clone(),
+        None => path
+            .strip_prefix(&job.root)
+            .unwrap_or(path)
+            .display()
+            .to_string(),
+    };
+    let overridden = match job.url {
+        Some(_) => None,
+        None => match sidecar::apply_sidecar(path, config) {
+            Ok(overridden) => overridden,
+            Err(e) => {
+                stats.record_failure(job.input);
+                eprintln!("❌ Error processing {}: {}", filename, e);
+                return Ok(());
+            }
+        },
+    };
+    let run_config = config;
+    let config = overridden.as_ref().unwrap_or(config);
+    let format = output_format(path, config);
+    let output_root = output::output_root(&job.root, config);
+    if config.in_place.is_some() && job.url.is_some() {
+        stats.record_failure(job.input);
+        eprintln!(
+            "❌ Error processing {}: URL inputs can't be replaced in place",
+            filename
+        );
+        return Ok(());
+    }
+    // The outputs still to write, with the name each is reported under.
+    let mut pending = Vec::new();
+    for output in &item.outputs {
+        let output_path = &output.path;
+        let label = match output.size {
+            Some((width, height)) => format!("{} at {}x{}", filename, width, height),
+            None => filename.clone(),
+        };
+        if let Some(planned) = &output.renamed_from {
+            eprintln!(
+                "⚠️  {} would also be written as {}, using {}",
+                label,
+                planned.display(),
+                output_path.display()
+            );
+        }
+        // Relative entry names of `--output-zip` have nothing on disk to compare with.
+        let to_files = config.output_zip.is_none();
+        let checks_input = to_files && config.in_place.is_none() && job.url.is_none();
+        if checks_input && same_file(path, output_path) {
+            stats.record_failure(job.input);
+            eprintln!(
+                "❌ Error processing {}: output would overwrite the input, set a --prefix, \
+                 --suffix or another output folder",
+                label
+            );
+            continue;
+        }
+        let existing = match config.force || config.in_place.is_some() || !to_files {
+            true => None,
+            false => output::existing_output(output_path, format, config),
+        };
+        if let Some(existing) = existing {
+            let reason = match (config.incremental, &job.url) {
+                (true, None) => settings
+                    .is_up_to_date(path, &existing, &output_root, run_config)
+                    .then_some(SkipReason::UpToDate),
+                _ => Some(SkipReason::OutputExists),
+            };
+            if let Some(reason) = reason {
+                let skipped = Skipped {
+                    input: job.input,
+                    path: path.clone(),
+                    reason,
+                    detail: output
+                        .size
+                        .map(|(width, height)| format!("{}x{}", width, height)),
+                };
+                report_skip(&skipped, stats);
+                continue;
+            }
+        }
+        if let Some(output_dir) = output_path.parent().filter(|_| to_files && !config.dry_run) {
+            if let Err(e) = std::fs::create_dir_all(output_dir) {
+                stats.record_failure(job.input);
+                eprintln!("❌ Error processing {}: {}", label, e);
+                continue;
+            }
+        }
+        pending.push((output, label));
+    }
+    if config.dry_run {
+        for (output, label) in &pending {
+            let config = config.padded(item.dimensions);
+            let config = config.at_size(output.size);
+            println!("📝 {}", planned_output(item, label, &output.path, &config));
+            stats.record_planned(job.input);
+        }
+        return Ok(());
+    }
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // Decode once; the first output's time includes it.
+    let mut start = Instant::now();
+    let mut stages = Stages::default();
+    let decoded = match &job.url {
+        Some(url) => download_input(url, config, &mut stages),
+        None => decode_input(path, format, config, &mut stages),
+    };
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            stats.record_failure(job.input);
+            eprintln!("❌ Error processing {}: {}", filename, e);
+            return Ok(());
+        }
+    };
+    let picked = match &decoded {
+        Decoded::Image { img, .. } => config.pick_border_color(img, &filename),
+        // Animations get the color of their first frame.
+        Decoded::Animation if config.auto_color.is_some() => decode_image(path, config)
+            .ok()
+            .and_then(|first| config.pick_border_color(&first, &filename)),
+        Decoded::Animation => None,
+    };
+    let config = config.with_border_color(picked);
+    let config = config.as_ref();
+    // From here until the bordered version takes its place, the original of an in-place
+    // image only exists under its backup name.
+    let source = match config.in_place {
+        Some(backup) => match backup.back_up(path) {
+            Ok(source) => source,
+            Err(e) => {
+                stats.record_failure(job.input);
+                eprintln!(
+                    "❌ Error processing {}: could not back up the original: {}",
+                    filename, e
+                );
+                return Ok(());
+            }
+        },
+        None => path.clone(),
+    };
+    // SVGs are drawn again at the size of each output rather than resampled from the first.
+    // In place, the one output is the SVG itself and the original only left as its backup.
+    let vector = job.url.is_none()
+        && config.in_place.is_none()
+        && OptionalCodec::for_path(path) == Some(OptionalCodec::Svg);
+    for (output, label) in pending {
+        let output_path = &output.path;
+        // An SVG is laid out by the size it declares, not the size it was first drawn at.
+        let dimensions = match vector {
+            true => item.dimensions.or(decoded.size()),
+            false => decoded.size().or(item.dimensions),
+        };
+        // Images whose header couldn't be read before only have their class now.
+        let size = output.size.or_else(|| {
+            let (width, height) = dimensions?;
+            config.class_size(width, height)
+        });
+        let config = config.padded(dimensions);
+        let config = config.at_size(size);
+        let redrawn = match (&decoded, dimensions) {
+            (
+                Decoded::Image {
+                    img,
+                    metadata,
+                    processed,
+                },
+                Some(upright),
+            ) if vector && svg_render_size(upright, &config) != (img.width(), img.height()) => {
+                match Stages::time(&mut stages.decode, || decode_image(path, &config)) {
+                    Ok(img) => Some(Decoded::Image {
+                        img,
+                        metadata: metadata.clone(),
+                        processed: *processed,
+                    }),
+                    Err(e) => {
+                        stats.record_failure(job.input);
+                        eprintln!("❌ Error processing {}: {}", label, e);
+                        continue;
+                    }
+                }
+            }
+            _ => None,
+        };
+        let decoded = redrawn.as_ref().unwrap_or(&decoded);
+        // Animations keep the crop centered.
+        let config = match decoded {
+            Decoded::Image { img, .. } => config.with_crop_focus(img),
+            Decoded::Animation => Cow::Borrowed(config.as_ref()),
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let caption_source = match job.url {
+            Some(_) => CaptionSource::Unknown,
+            None => CaptionSource::File(path),
+        };
+        let config = config.with_caption(&stem, caption_source);
+        let config = config.as_ref();
+        let result = write_decoded(
+            decoded,
+            &source,
+            output_path,
+            format,
+            config,
+            &mut stages,
+            zip.as_mut(),
+        );
+        if let (Err(_), Some(backup)) = (&result, config.in_place) {
+            if let Err(e) = backup.restore(path) {
+                eprintln!(
+                    "⚠️  Could not put the original of {} back, it is kept as {}: {}",
+                    label,
+                    source.display(),
+                    e
+                );
+            }
+        }
+        if config.incremental && result.is_ok() {
+            if let Err(e) = settings.record_written(&output_root, run_config) {
+                eprintln!(
+                    "⚠️  Could not save the settings into {}: {}",
+                    output_root.display(),
+                    e
+                );
+            }
+        }
+        if (config.preserve_mtime || config.mtime_from_exif) && job.url.is_none() && result.is_ok()
+        {
+            if let Err(e) = mtime::copy_time(&source, output_path, config.mtime_from_exif) {
+                eprintln!(
+                    "⚠️  Could not set the modification time of {}: {}",
+                    output_path.display(),
+                    e
+                );
+            }
+        }
+        let size = match zip {
+            Some(zip) => zip.entry_size(output_path).unwrap_or_default(),
+            None => std::fs::metadata(output_path)
+                .map(|m| m.len())
+                .unwrap_or_default(),
+        };
+        let elapsed = start.elapsed();
+        if let (Ok(_), Some(fit)) = (&result, &stages.fit) {
+            size_limit::warn_if_over(&label, size, config, &stages);
+            stats.record_fit(fit);
+        }
+        if config.write_sidecar && result.is_ok() {
+            let fitted = stages
+                .fit
+                .map(|fit| size_limit::with_quality(config, format, fit.quality));
+            let mut report = ImageReport::new(
+                item,
+                decoded.size(),
+                output_path,
+                size,
+                format,
+                fitted.as_ref().unwrap_or(config),
+                elapsed,
+            );
+            report.quality_fit = stages.fit;
+            if let Err(e) = report.write(zip.as_mut()) {
+                eprintln!(
+                    "⚠️  Could not write the sidecar of {}: {}",
+                    output_path.display(),
+                    e
+                );
+            }
+        }
+        let note = match (&result, decoded.size()) {
+            (Ok(_), Some((width, height))) => fit_note(&label, width, height, config),
+            _ => None,
+        };
+        match result {
+            Ok(Processed::Clean) => {
+                println!(
+                    "✅ Successfully processed {} in {:.2} seconds ({}; {}{})",
+                    label,
+                    elapsed.as_secs_f64(),
+                    stages,
+                    format_size(size),
+                    stages.fitted_quality()
+                );
+                stats.record_success(job.input, &label, elapsed, size);
+                stats.record_dimensions(config.target_width, config.target_height);
+            }
+            Ok(Processed::Salvaged) => {
+                println!(
+                    "🩹 Salvaged truncated {} in {:.2} seconds (missing area filled; {}; {}{})",
+                    label,
+                    elapsed.as_secs_f64(),
+                    stages,
+                    format_size(size),
+                    stages.fitted_quality()
+                );
+                stats.record_success(job.input, &label, elapsed, size);
+                stats.record_dimensions(config.target_width, config.target_height);
+                stats.record_salvaged();
+            }
+            Err(e) => {
+                stats.record_failure(job.input);
+                if atomic::is_disk_full(e.as_ref()) {
+                    return Err(disk_full(path));
+                }
+                eprintln!("❌ Error processing {}: {}", label, e);
+            }
+        }
+        if let Some(note) = note {
+            println!("{}", note);
+        }
+        start = Instant::now();
+        stages = Stages::default();
+    }
+    Ok(())
+}
+
+fn print_config(config: &Config, using_defaults: bool, out: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(out, "\n=== Configuration ===")?;
+    if using_defaults {
+        writeln!(out, "Using default configuration (no flags provided)")?;
+    }
+    if let Some(preset) = config.preset {
+        writeln!(out, "Preset: {} ({})", preset.name, preset.description)?;
+    }
+    if config.pad_only {
+        writeln!(
+            out,
+            "Target dimensions: each image's own, plus its borders (--pad-only)"
+        )?;
+    } else if config.wrap {
+        let (width, height) = config.wrap_bounds();
+        writeln!(
+            out,
+            "Target dimensions: each image's aspect ratio, framed to fit {}x{} (--wrap)",
+            width, height
+        )?;
+    } else if let Some(edge) = config.longest_edge {
+        writeln!(
+            out,
+            "Target dimensions: longest edge {} px, in each image's aspect ratio",
+            edge
+        )?;
+    } else if config.sizes.is_empty() {
+        writeln!(
+            out,
+            "Target dimensions: {}x{}",
+            config.target_width, config.target_height
+        )?;
+    } else {
+        let sizes: Vec<String> = config
+            .sizes
+            .iter()
+            .map(|(width, height)| format!("{}x{}", width, height))
+            .collect();
+        writeln!(out, "Target dimensions: {}", sizes.join(", "))?;
+    }
+    if !config.class_sizes.is_empty() {
+        let sizes: Vec<String> = Orientation::value_variants()
+            .iter()
+            .map(|&orientation| {
+                let name = orientation.to_possible_value();
+                let name = name.map(|v| v.get_name().to_string()).unwrap_or_default();
+                let own = config
+                    .class_sizes
+                    .iter()
+                    .find(|&&(class, _)| class == orientation);
+                match own {
+                    Some(&(_, (width, height))) => format!("{} {}x{}", name, width, height),
+                    None => format!(
+                        "{} {}x{} (default)",
+                        name, config.target_width, config.target_height
+                    ),
+                }
+            })
+            .collect();
+        writeln!(out, "Canvas per orientation: {}", sizes.join(", "))?;
+    }
+    if config.rotation != Rotation::None {
+        let degrees = config.rotation.to_possible_value();
+        writeln!(
+            out,
+            "Rotation: {}° clockwise, after the EXIF orientation",
+            degrees
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    if let Some(size) = config.thumbnail {
+        writeln!(out, "Thumbnails: {} px longest edge", size)?;
+    }
+    if config.write_sidecar {
+        writeln!(out, "JSON sidecars: yes")?;
+    }
+    if !config.frames.is_empty() {
+        let frames: Vec<_> = config
+            .frames
+            .iter()
+            .map(|frame| format!("{} {}", frame.color, frame.width))
+            .collect();
+        writeln!(out, "Frames (outside in): {}", frames.join(", "))?;
+    } else if let Some(style) = config.style.filter(|_| config.border_px.is_none()) {
+        let style = style.to_possible_value();
+        writeln!(
+            out,
+            "Style: {}",
+            style.map(|v| v.get_name().to_string()).unwrap_or_default()
+        )?;
+        for size in config.output_sizes() {
+            let config = config.at_size(size);
+            let borders = Borders::of(Orientation::Landscape, &config);
+            writeln!(
+                out,
+                "Borders at {}x{}: Top={:.0} px, Bottom={:.0} px, Left={:.0} px, Right={:.0} px",
+                config.target_width,
+                config.target_height,
+                borders.top,
+                borders.bottom,
+                borders.left,
+                borders.right
+            )?;
+        }
+    } else if let Some((vert, horiz)) = config.border_px {
+        writeln!(
+            out,
+            "Borders: fixed pixels, Vertical={} px, Horizontal={} px",
+            vert, horiz
+        )?;
+    } else {
+        writeln!(
+            out,
+            "Landscape borders: Vertical={:.1}%, Horizontal={:.1}%",
+            config.landscape_vert_border * 100.0,
+            config.landscape_horiz_border * 100.0
+        )?;
+        writeln!(
+            out,
+            "Portrait borders: Vertical={:.1}%, Horizontal={:.1}%",
+            config.portrait_vert_border * 100.0,
+            config.portrait_horiz_border * 100.0
+        )?;
+        writeln!(
+            out,
+            "Square borders: Vertical={:.1}%, Horizontal={:.1}% (within {:.1}% of 1:1)",
+            config.square_vert_border * 100.0,
+            config.square_horiz_border * 100.0,
+            config.square_tolerance * 100.0
+        )?;
+        writeln!(
+            out,
+            "Panorama borders: Vertical={:.1}%, Horizontal={:.1}% (wider than {}:1, turned for \
+             tall images)",
+            config.panorama_vert_border * 100.0,
+            config.panorama_horiz_border * 100.0,
+            config.panorama_threshold
+        )?;
+    }
+    let sides = [
+        ("Top", config.border_top),
+        ("Bottom", config.border_bottom),
+        ("Left", config.border_left),
+        ("Right", config.border_right),
+    ];
+    let sides: Vec<_> = sides
+        .iter()
+        .filter_map(|(name, size)| size.map(|size| format!("{}={}", name, size)))
+        .collect();
+    if !sides.is_empty() {
+        writeln!(out, "Border overrides: {}", sides.join(", "))?;
+    }
+    if let Some(min) = config.min_border_px {
+        writeln!(out, "Minimum border: {} px on every side", min)?;
+    }
+    if config.gravity != Gravity::Center {
+        let gravity = config.gravity.to_possible_value();
+        writeln!(
+            out,
+            "Gravity: {} (inside the borders)",
+            gravity
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    if config.offset != (0, 0) {
+        let (x, y) = config.offset;
+        writeln!(
+            out,
+            "Offset: {},{} px from the --gravity position (stopping at the canvas edge)",
+            x, y
+        )?;
+    }
+    if config.fit_mode == FitMode::Cover {
+        let gravity = config.crop_gravity.to_possible_value();
+        writeln!(
+            out,
+            "Fit: cover (the overflow is cropped, gravity {})",
+            gravity
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    match config.max_upscale {
+        Some(1.0) => writeln!(out, "Upscaling: never")?,
+        Some(factor) => writeln!(out, "Upscaling: at most {}x", factor)?,
+        None => {}
+    }
+    match (config.background, config.auto_color) {
+        (Background::Blur, _) => writeln!(
+            out,
+            "Background: blurred image (radius {} px, darkened {:.0}%)",
+            config.blur_radius,
+            config.background_darken * 100.0
+        )?,
+        (Background::Color, Some(mode)) => writeln!(
+            out,
+            "Border color: auto ({})",
+            mode.to_possible_value()
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?,
+        (Background::Color, None) => writeln!(out, "Border color: {}", config.border_color)?,
+    }
+    if let Some(radius) = config.corner_radius {
+        writeln!(out, "Corner radius: {}", radius)?;
+    }
+    if let Some(shadow) = config.shadow {
+        writeln!(
+            out,
+            "Shadow: offset {},{} px, blur {} px, opacity {:.0}%",
+            shadow.offset.0,
+            shadow.offset.1,
+            shadow.blur,
+            shadow.opacity * 100.0
+        )?;
+    }
+    if let Some(width) = config.keyline {
+        writeln!(out, "Keyline: {} px, {}", width, config.keyline_color)?;
+    }
+    if let Some(caption) = &config.caption {
+        let font = caption.font.as_ref().and_then(|font| font.path.file_name());
+        let color = match caption.color {
+            Some(color) => color.to_string(),
+            None => "black or white to suit the border".to_string(),
+        };
+        let align = caption.align.to_possible_value();
+        writeln!(
+            out,
+            "Caption: \"{}\" in {}, {} of the room below the image, {}, aligned {}",
+            caption.text,
+            font.map(|name| name.to_string_lossy()).unwrap_or_default(),
+            caption.size,
+            color,
+            align.map(|v| v.get_name().to_string()).unwrap_or_default()
+        )?;
+        if caption.from_exif {
+            let missing = caption.missing.as_ref().map(|text| format!("\"{}\"", text));
+            writeln!(
+                out,
+                "Caption of images without EXIF camera settings: {}",
+                missing.as_deref().unwrap_or("none")
+            )?;
+        }
+    }
+    match config.format {
+        Some(format) => writeln!(
+            out,
+            "Output format: {}",
+            format
+                .to_possible_value()
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?,
+        None => writeln!(out, "Output format: auto")?,
+    }
+    if config
+        .format
+        .is_none_or(|format| format == OutputFormat::Jpeg)
+    {
+        writeln!(out, "JPEG quality: {}", config.jpeg_quality)?;
+        if config.jpeg_encoder == EncoderChoice::Mozjpeg {
+            writeln!(out, "JPEG encoder: mozjpeg")?;
+        }
+        if config.jpeg_progressive {
+            writeln!(out, "JPEG encoding: progressive")?;
+        }
+        let subsampling = config.jpeg_subsampling.to_possible_value();
+        writeln!(
+            out,
+            "JPEG chroma subsampling: {}",
+            subsampling
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    if config
+        .format
+        .is_none_or(|format| format == OutputFormat::Png)
+    {
+        let compression = config.png_compression.to_possible_value();
+        let filter = config.png_filter.to_possible_value();
+        writeln!(
+            out,
+            "PNG compression: {}, filter: {}",
+            compression
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default(),
+            filter.map(|v| v.get_name().to_string()).unwrap_or_default()
+        )?;
+        if config.optimize_png {
+            writeln!(
+                out,
+                "PNG optimization: oxipng level {}",
+                config.optimize_level
+            )?;
+        }
+    }
+    if config.metadata != MetadataPolicy::Keep {
+        let metadata = config.metadata.to_possible_value();
+        writeln!(
+            out,
+            "Metadata: {}",
+            metadata
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    if config.strip_gps {
+        writeln!(out, "Location data: stripped")?;
+    }
+    if config.stamp_metadata {
+        writeln!(out, "Settings stamp: EXIF Software and UserComment")?;
+    }
+    if let Some(dpi) = config.dpi {
+        writeln!(out, "Resolution: {} dpi", dpi)?;
+    }
+    if let Some(limit) = config.max_output_size {
+        writeln!(
+            out,
+            "Maximum output size: {} (quality down to {})",
+            format_size(limit),
+            config.min_quality
+        )?;
+    }
+    if config.format == Some(OutputFormat::Tiff) {
+        let compression = config.tiff_compression.to_possible_value();
+        writeln!(
+            out,
+            "TIFF compression: {}",
+            compression
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    if config.format == Some(OutputFormat::Avif) {
+        writeln!(
+            out,
+            "AVIF quality: {}, speed: {}",
+            config.avif_quality, config.avif_speed
+        )?;
+    }
+    if config.format == Some(OutputFormat::Webp) {
+        match config.webp_lossless {
+            true => writeln!(out, "WebP: lossless")?,
+            false => writeln!(out, "WebP quality: {}", config.webp_quality)?,
+        }
+    }
+    writeln!(
+        out,
+        "Bit depth: {}",
+        config
+            .bit_depth
+            .to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    )?;
+    writeln!(
+        out,
+        "Animated inputs: {}",
+        config
+            .animated
+            .to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    )?;
+    if let Some(zip) = &config.output_zip {
+        writeln!(out, "Output archive: {}", zip.display())?;
+    }
+    match (config.in_place, &config.output_dir) {
+        (Some(Backup::Bak), _) => writeln!(out, "In place: yes, originals kept as .bak files")?,
+        (Some(Backup::Folder), _) => writeln!(
+            out,
+            "In place: yes, originals kept in \"originals\" folders"
+        )?,
+        (None, Some(dir)) => writeln!(out, "Output folder: {}", dir.display())?,
+        (None, None) => writeln!(out, "Separate output folder: {}", config.separate_folder)?,
+    }
+    if config.flatten {
+        writeln!(out, "Subfolders: flattened")?;
+    }
+    if let Some(template) = &config.name_template {
+        writeln!(out, "Output names: {}", template)?;
+    }
+    if let Some(pattern) = &config.rename_by_date {
+        writeln!(out, "Output names: capture date as {}", pattern)?;
+    }
+    if let Some(sequence) = config.number {
+        writeln!(out, "Numbered outputs: from {}", sequence.start)?;
+    }
+    if let Some(normalize) = config.normalize_extension {
+        writeln!(
+            out,
+            "Output extensions: {}",
+            normalize
+                .to_possible_value()
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    writeln!(
+        out,
+        "On output name collision: {}",
+        config
+            .on_collision
+            .to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    )?;
+    writeln!(out, "Overwrite existing outputs: {}", config.force)?;
+    if config.incremental {
+        let settings = if config.incremental_ignore_settings {
+            "ignored"
+        } else {
+            "compared"
+        };
+        writeln!(out, "Incremental: yes (settings {})", settings)?;
+    }
+    if config.mtime_from_exif {
+        writeln!(out, "Output modification times: capture time of the images")?;
+    } else if config.preserve_mtime {
+        writeln!(out, "Output modification times: those of the images")?;
+    }
+    writeln!(out, "Recursive: {}", config.recursive)?;
+    writeln!(out, "Follow symlinks: {}", config.follow_symlinks)?;
+    writeln!(out, "Include hidden files: {}", config.include_hidden)?;
+    writeln!(out, "Input extensions: {}", config.extensions.join(", "))?;
+    writeln!(
+        out,
+        "Sort order: {}{}",
+        config
+            .sort_by
+            .to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default(),
+        if config.reverse { " (reversed)" } else { "" }
+    )?;
+    if !config.include_patterns.is_empty() {
+        let patterns: Vec<&str> = config.include_patterns.iter().map(|p| p.as_str()).collect();
+        writeln!(out, "Include patterns: {}", patterns.join(", "))?;
+    }
+    if !config.exclude_patterns.is_empty() {
+        let patterns: Vec<&str> = config.exclude_patterns.iter().map(|p| p.as_str()).collect();
+        writeln!(out, "Exclude patterns: {}", patterns.join(", "))?;
+    }
+    if config.watch {
+        writeln!(out, "Watch mode: on")?;
+    }
+    if config.dry_run {
+        writeln!(out, "Dry run: nothing is written")?;
+    }
+    if cfg!(feature = "http") {
+        writeln!(
+            out,
+            "URL downloads: {}s timeout, up to {}",
+            config.download_timeout.as_secs(),
+            format_size(config.max_download_size)
+        )?;
+    }
+    if let Some(limit) = config.max_file_size {
+        writeln!(out, "Max file size: {}", format_size(limit))?;
+    }
+    match config.max_pixels {
+        Some(max) => writeln!(out, "Max megapixels: {}", max as f64 / 1e6)?,
+        None => writeln!(out, "Max megapixels: unlimited")?,
+    }
+    if config.min_width > 0 || config.min_height > 0 {
+        writeln!(
+            out,
+            "Minimum dimensions: {}x{}",
+            config.min_width, config.min_height
+        )?;
+    }
+    if let Some(only) = config.only.and_then(|o| o.to_possible_value()) {
+        writeln!(out, "Only: {} images", only.get_name())?;
+    }
+    if let Some(n) = config.newest {
+        writeln!(out, "Newest only: {} images", n)?;
+    }
+    if let Some(after) = config.modified_after {
+        writeln!(out, "Modified after: {}", format_date_time(after))?;
+    }
+    if config.since.is_some() || config.until.is_some() {
+        let bound = |b: Option<i64>| b.map(format_date).unwrap_or_else(|| "any".to_string());
+        let missing = config.missing_date.to_possible_value();
+        writeln!(
+            out,
+            "Taken between: {} and {} (undated images: {})",
+            bound(config.since),
+            bound(config.until),
+            missing
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    writeln!(out, "==================\n")?;
+    Ok(())
+}
+
+/// How an image that was written made it through decoding.
+#[derive(Clone, Copy)]
+enum Processed {
+    Clean,
+    /// Truncated, with the missing part filled in (`--salvage`).
+    Salvaged,
+}
+
+/// An input decoded once for all of its outputs.
+enum Decoded {
+    Image {
+        img: DynamicImage,
+        metadata: Metadata,
+        processed: Processed,
+    },
+    /// An animation kept as one (`--animated keep`), decoded again for each output.
+    Animation,
+}
+
+impl Decoded {
+    /// The size the image was decoded at; `None` for animations.
+    fn size(&self) -> Option<(u32, u32)> {
+        match self {
+            Decoded::Image { img, .. } => Some((img.width(), img.height())),
+            Decoded::Animation => None,
+        }
+    }
+}
+
+fn decode_input(
+    input_path: &Path,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Decoded, Box<dyn std::error::Error>> {
+    if format == OutputFormat::Gif {
+        return Ok(Decoded::Animation);
+    }
+
+    let decoded = Stages::time(&mut stages.decode, || {
+        decode_image_as_stored(input_path, config)
+    });
+    let (mut img, orientation) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) if salvage::is_truncated_jpeg(input_path) => {
+            return Err(format!("truncated JPEG: {}", e).into());
+        }
+        Err(e) => return Err(e),
+    };
+    let metadata = Stages::time(&mut stages.decode, || {
+        Metadata::from_path(input_path, config.metadata)
+    });
+    let mut processed = Processed::Clean;
+    // A truncated JPEG decodes without an error, its missing part at mid-grey, so the file
+    // is only checked for one when there is something to do about it.
+    if config.salvage
+        && salvage::is_truncated_jpeg(input_path)
+        && salvage::fill_missing(&mut img, config.border_color)
+    {
+        processed = Processed::Salvaged;
+    }
+    // Only now, as salvaging finds the missing blocks in the order they were stored.
+    turn_upright(&mut img, orientation, config);
+    Ok(Decoded::Image {
+        img,
+        metadata,
+        processed,
+    })
+}
+
+/// Downloads a URL input and decodes it from memory.
+fn download_input(
+    url: &str,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Decoded, Box<dyn std::error::Error>> {
+    let data = Stages::time(&mut stages.download, || fetch::download(url, config))?;
+    let img = Stages::time(&mut stages.decode, || decode_bytes(&data, config))?;
+    let metadata = Metadata::from_bytes(&data, url, config.metadata);
+    Ok(Decoded::Image {
+        img,
+        metadata,
+        processed: Processed::Clean,
+    })
+}
+
+/// Borders a decoded input at `config`'s size and writes it to `output_path`, or into `zip`
+/// at that path.
+fn write_decoded(
+    decoded: &Decoded,
+    input_path: &Path,
+    output_path: &Path,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+    mut zip: Option<&mut OutputZip>,
+) -> Result<Processed, Box<dyn std::error::Error>> {
+    match decoded {
+        Decoded::Animation => {
+            match zip {
+                Some(zip) => {
+                    let mut encoded = Vec::new();
+                    animation::process_animation(input_path, &mut encoded, config, stages)?;
+                    Stages::time(&mut stages.encode, || zip.add(output_path, &encoded))?;
+                }
+                None => {
+                    let mut out_file =
+                        Stages::time(&mut stages.encode, || AtomicFile::create(output_path))?;
+                    animation::process_animation(input_path, &mut out_file, config, stages)?;
+                    Stages::time(&mut stages.encode, || out_file.commit())?;
+                }
+            }
+            Ok(Processed::Clean)
+        }
+        Decoded::Image {
+            img,
+            metadata,
+            processed,
+        } => {
+            let canvas = Stages::time(&mut stages.border, || compose_image(img, format, config))?;
+            let zip_entry = zip.as_deref_mut();
+            write_output(
+                &canvas,
+                metadata,
+                output_path,
+                format,
+                config,
+                stages,
+                zip_entry,
+            )?;
+            if let Some(size) = config.thumbnail {
+                let thumbnail = Stages::time(&mut stages.border, || thumbnail(&canvas, size));
+                let thumbnail_path = output::thumbnail_path(output_path);
+                let config = config.for_thumbnail();
+                write_output(
+                    &thumbnail,
+                    metadata,
+                    &thumbnail_path,
+                    format,
+                    &config,
+                    stages,
+                    zip,
+                )?;
+            }
+            Ok(*processed)
+        }
+    }
+}
+
+/// Encodes a canvas into `output_path`, which only appears once it's complete, or into
+/// `zip` at that path.
+///
+/// Outputs that get `--optimize-png` or `--max-output-size`, and entries of `zip`, are
+/// encoded in memory first, so the optimized or fitted data is the only thing written.
+fn write_output(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    output_path: &Path,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+    zip: Option<&mut OutputZip>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(zip) = zip {
+        let encoded = encode_output(canvas, metadata, format, config, stages)?;
+        Stages::time(&mut stages.encode, || zip.add(output_path, &encoded))?;
+        return Ok(());
+    }
+    let mut out_file = Stages::time(&mut stages.encode, || AtomicFile::create(output_path))?;
+    if size_limit::applies(format, config) || optimize::applies(format, config) {
+        let encoded = encode_output(canvas, metadata, format, config, stages)?;
+        Stages::time(&mut stages.encode, || out_file.write_all(&encoded))?;
+    } else {
+        Stages::time(&mut stages.encode, || {
+            write_canvas(canvas, metadata, format, config, &mut out_file)
+        })?;
+    }
+    Stages::time(&mut stages.encode, || out_file.commit())?;
+    Ok(())
+}
+
+/// Encodes a canvas in memory, fitted to `--max-output-size` or run through
+/// `--optimize-png` where those apply.
+fn encode_output(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if size_limit::applies(format, config) {
+        return size_limit::encode_to_fit(canvas, metadata, format, config, stages);
+    }
+    let mut encoded = Vec::new();
+    Stages::time(&mut stages.encode, || {
+        write_canvas(canvas, metadata, format, config, &mut encoded)
+    })?;
+    optimize::optimize_output(encoded, format, config, stages)
+}
+
+/// Encodes a finished canvas in the output `format`, with the input's `metadata` where the
+/// format can hold it.
+fn write_canvas(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    format: OutputFormat,
+    config: &Config,
+    mut writer: impl Write,
+) -> Result<(), image::ImageError> {
+    let mut metadata = metadata.for_canvas(canvas);
+    if config.strip_gps {
+        metadata = metadata.without_location();
+    }
+    if config.stamp_metadata {
+        let settings = report::SettingsStamp::of(format, config).to_json();
+        metadata.exif = metadata::stamp_exif(metadata.exif, &settings);
+    }
+    match format {
+        OutputFormat::Png => Ok(writer.write_all(&encode_png(canvas, &metadata, config)?)?),
+        OutputFormat::Webp => {
+            let encoded = metadata::embed_in_webp(encode_webp(canvas, config)?, &metadata, canvas);
+            Ok(writer.write_all(&encoded)?)
+        }
+        OutputFormat::Tiff => Ok(writer.write_all(&encode_tiff(canvas, &metadata, config)?)?),
+        #[cfg(feature = "avif-encode")]
+        OutputFormat::Avif => {
+            canvas.write_with_encoder(image::codecs::avif::AvifEncoder::new_with_speed_quality(
+                writer,
+                config.avif_speed,
+                config.avif_quality,
+            ))
+        }
+        #[cfg(not(feature = "avif-encode"))]
+        OutputFormat::Avif => Err(image::ImageError::Encoding(
+            image::error::EncodingError::new(
+                image::ImageFormat::Avif.into(),
+                OutputFormat::Avif.unavailable().unwrap_or_default(),
+            ),
+        )),
+        _ if metadata.is_empty() => encode_jpeg(canvas, config, writer),
+        _ => {
+            let mut encoded = Vec::new();
+            encode_jpeg(canvas, config, &mut encoded)?;
+            Ok(writer.write_all(&metadata::embed_in_jpeg(encoded, &metadata))?)
+        }
+    }
+}
+
+/// Encodes a canvas as PNG with `--png-compression` and `--png-filter`.
+fn encode_png(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    config: &Config,
+) -> Result<Vec<u8>, image::ImageError> {
+    use image::ImageEncoder;
+
+    let mut encoded = Vec::new();
+    let mut encoder = image::codecs::png::PngEncoder::new_with_quality(
+        &mut encoded,
+        config.png_compression.into(),
+        config.png_filter.into(),
+    );
+    if let Some(icc) = &metadata.icc {
+        encoder
+            .set_icc_profile(icc.clone())
+            .map_err(image::ImageError::Unsupported)?;
+    }
+    if let Some(exif) = &metadata.exif {
+        encoder
+            .set_exif_metadata(exif.clone())
+            .map_err(image::ImageError::Unsupported)?;
+    }
+    canvas.write_with_encoder(encoder)?;
+    if let Some(xmp) = &metadata.xmp {
+        encoded = metadata::embed_xmp_in_png(encoded, xmp);
+    }
+    if let Some(dpi) = config.dpi {
+        encoded = metadata::embed_dpi_in_png(encoded, dpi);
+    }
+    Ok(encoded)
+}
+
+/// Encodes a canvas as WebP, lossy at `--webp-quality` unless `--webp-lossless`.
+///
+/// libwebp only takes 8-bit RGB or RGBA, so grayscale canvases are expanded; alpha is kept.
+fn encode_webp(canvas: &DynamicImage, config: &Config) -> Result<Vec<u8>, image::ImageError> {
+    let rgba;
+    let rgb;
+    let encoder = if canvas.color().has_alpha() {
+        rgba = canvas.to_rgba8();
+        webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+    } else {
+        rgb = canvas.to_rgb8();
+        webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height())
+    };
+    let encoded = encoder
+        .encode_simple(config.webp_lossless, config.webp_quality as f32)
+        .map_err(|e| {
+            image::ImageError::Encoding(image::error::EncodingError::new(
+                image::ImageFormat::WebP.into(),
+                format!("libwebp failed: {:?}", e),
+            ))
+        })?;
+    Ok(encoded.to_vec())
+}
+
+/// Encodes a canvas as TIFF with `--tiff-compression`, tagged with the ICC profile if the
+/// metadata has one and with `--dpi`.
+///
+/// The TIFF writer seeks, so the file is assembled in memory. Gray+alpha canvases are
+/// written as RGBA, which more readers understand.
+fn encode_tiff(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    config: &Config,
+) -> Result<Vec<u8>, image::ImageError> {
+    use tiff::encoder::colortype::{Gray16, Gray8, RGB16, RGB8, RGBA16, RGBA8};
+    use tiff::encoder::{colortype::ColorType, Rational, TiffEncoder, TiffValue};
+
+    fn write<C: ColorType>(
+        encoder: &mut TiffEncoder<&mut std::io::Cursor<Vec<u8>>>,
+        (width, height): (u32, u32),
+        data: &[C::Inner],
+        icc: Option<&[u8]>,
+        dpi: Option<u16>,
+    ) -> tiff::TiffResult<()>
+    where
+        [C::Inner]: TiffValue,
+    {
+        let mut image = encoder.new_image::<C>(width, height)?;
+        if let Some(icc) = icc {
+            image
+                .encoder()
+                .write_tag(tiff::tags::Tag::IccProfile, icc)?;
+        }
+        if let Some(dpi) = dpi {
+            let dpi = Rational {
+                n: dpi.into(),
+                d: 1,
+            };
+            image.resolution(tiff::tags::ResolutionUnit::Inch, dpi);
+        }
+        image.write_data(data)
+    }
+
+    let tiff_error = |e: tiff::TiffError| {
+        image::ImageError::Encoding(image::error::EncodingError::new(
+            image::ImageFormat::Tiff.into(),
+            e,
+        ))
+    };
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    let mut encoder = tiff::encoder::TiffEncoder::new(&mut encoded)
+        .map_err(tiff_error)?
+        .with_compression(config.tiff_compression.into());
+    let size = (canvas.width(), canvas.height());
+    let icc = metadata.icc.as_deref();
+    let dpi = config.dpi;
+    let e = &mut encoder;
+    match canvas {
+        DynamicImage::ImageLuma8(img) => write::<Gray8>(e, size, img, icc, dpi),
+        DynamicImage::ImageRgb8(img) => write::<RGB8>(e, size, img, icc, dpi),
+        DynamicImage::ImageRgba8(img) => write::<RGBA8>(e, size, img, icc, dpi),
+        DynamicImage::ImageLuma16(img) => write::<Gray16>(e, size, img, icc, dpi),
+        DynamicImage::ImageRgb16(img) => write::<RGB16>(e, size, img, icc, dpi),
+        DynamicImage::ImageRgba16(img) => write::<RGBA16>(e, size, img, icc, dpi),
+        DynamicImage::ImageLumaA16(_) => write::<RGBA16>(e, size, &canvas.to_rgba16(), icc, dpi),
+        _ => write::<RGBA8>(e, size, &canvas.to_rgba8(), icc, dpi),
+    }
+    .map_err(tiff_error)?;
+    Ok(encoded.into_inner())
+}
+
+/// Scales a finished canvas down so its longest edge is `size`, for `--thumbnail`. A canvas
+/// that is already that small is used as it is.
+fn thumbnail(canvas: &DynamicImage, size: u32) -> Cow<'_, DynamicImage> {
+    if canvas.width().max(canvas.height()) <= size {
+        Cow::Borrowed(canvas)
+    } else {
+        Cow::Owned(canvas.resize(size, size, FilterType::Triangle))
+    }
+}
+
+/// Lays out a decoded image in the pixel format best suited to it and the output `format`.
+///
+/// Grayscale sources stay grayscale and 16-bit sources stay 16-bit (see `--bit-depth`), so
+/// neither pays for an RGBA8 copy it doesn't need.
+fn compose_image(
+    img: &DynamicImage,
+    format: OutputFormat,
+    config: &Config,
+) -> Result<DynamicImage, image::ImageError> {
+    let color = img.color();
+    // A colored border, keyline or caption needs a color canvas, even around a grayscale
+    // image.
+    let keyline_has_color = config.keyline.is_some() && !config.keyline_color.is_gray();
+    let caption_has_color = config
+        .caption
+        .as_ref()
+        .is_some_and(|caption| !caption.color_on(config.border_color).is_gray());
+    let has_color = color.has_color()
+        || !config.border_colors().all(BorderColor::is_gray)
+        || keyline_has_color
+        || caption_has_color;
+    if config.bit_depth.wants_16bit(color, format) {
+        // Only PNG and TIFF are written at 16 bits, and both keep alpha, so nothing is
+        // flattened and a translucent border keeps its alpha.
+        let has_alpha = color.has_alpha() || !config.border_colors().all(BorderColor::is_opaque);
+        return Ok(match (has_color, has_alpha) {
+            (false, false) => compose(&img.to_luma16(), config, false)?.into(),
+            (false, true) => compose(&img.to_luma_alpha16(), config, false)?.into(),
+            (true, _) => compose(&img.to_rgba16(), config, false)?.into(),
+        });
+    }
+
+    // JPEG has no alpha: blend translucent pixels onto the border instead of letting the
+    // encoder drop the alpha channel and expose whatever color sits underneath. The
+    // flattened canvas is opaque, so it is handed on without an alpha channel.
+    let flatten = !format.keeps_alpha();
+    let has_alpha =
+        color.has_alpha() || !(flatten || config.border_colors().all(BorderColor::is_opaque));
+    Ok(match (has_color, has_alpha) {
+        (false, false) => compose(&img.to_luma8(), config, false)?.into(),
+        (false, true) if flatten => {
+            DynamicImage::from(compose(&img.to_luma_alpha8(), config, true)?)
+                .into_luma8()
+                .into()
+        }
+        (false, true) => compose(&img.to_luma_alpha8(), config, false)?.into(),
+        (true, false) if flatten => compose(&img.to_rgb8(), config, false)?.into(),
+        (true, true) if flatten => DynamicImage::from(compose(&img.to_rgba8(), config, true)?)
+            .into_rgb8()
+            .into(),
+        (true, _) => compose(&img.to_rgba8(), config, false)?.into(),
+    })
+}
+
+/// Orientation class of an image, which decides the border ratios it gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+    /// Wider than `--panorama-threshold`.
+    Panorama,
+    /// Taller than `--panorama-threshold`, a panorama on its side.
+    Tall,
+}
+
+impl Orientation {
+    /// The orientation of an image of `width` x `height`, with `--square-tolerance` and
+    /// `--panorama-threshold`.
+    fn of(width: u32, height: u32, config: &Config) -> Self {
+        Self::classify(
+            width,
+            height,
+            config.square_tolerance,
+            config.panorama_threshold,
+        )
+    }
+
+    /// Classifies an image as square if its longer side is at most `tolerance` (a fraction
+    /// of the shorter side) longer, as a panorama or tall if it is more than `threshold`
+    /// times longer, and otherwise by which side is longer.
+    fn classify(width: u32, height: u32, tolerance: f64, threshold: f64) -> Self {
+        let (long, short) = (width.max(height), width.min(height));
+        let wide = width > height;
+        if (long - short) as f64 <= short as f64 * tolerance {
+            Orientation::Square
+        } else if long as f64 > short as f64 * threshold {
+            match wide {
+                true => Orientation::Panorama,
+                false => Orientation::Tall,
+            }
+        } else if wide {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        }
+    }
+
+    /// Returns the (vertical, horizontal) border ratios for this orientation; with
+    /// `--border-px`, all get the ratios of its fixed widths.
+    fn border_ratios(self, config: &Config) -> (f64, f64) {
+        if let Some((vert, horiz)) = config.border_px {
+            return (
+                vert as f64 / config.target_height as f64,
+                horiz as f64 / config.target_width as f64,
+            );
+        }
+        match self {
+            Orientation::Landscape => (config.landscape_vert_border, config.landscape_horiz_border),
+            Orientation::Portrait => (config.portrait_vert_border, config.portrait_horiz_border),
+            Orientation::Square => (config.square_vert_border, config.square_horiz_border),
+            Orientation::Panorama => (config.panorama_vert_border, config.panorama_horiz_border),
+            Orientation::Tall => (config.panorama_horiz_border, config.panorama_vert_border),
+        }
+    }
+}
+
+/// A border width from `--border-top` and friends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BorderSize {
+    /// Fraction of the canvas side the border is measured along.
+    Ratio(f64),
+    Pixels(u32),
+}
+
+impl BorderSize {
+    /// The width in pixels on a canvas side of `length`.
+    fn resolve(self, length: u32) -> f64 {
+        match self {
+            BorderSize::Ratio(ratio) => length as f64 * ratio,
+            BorderSize::Pixels(pixels) => pixels as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for BorderSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BorderSize::Ratio(ratio) => write!(f, "{:.1}%", ratio * 100.0),
+            BorderSize::Pixels(pixels) => write!(f, "{} px", pixels),
+        }
+    }
+}
+
+/// Parses a per-side border width: a ratio from 0.0 to below 1.0, or pixels with a "px"
+/// suffix.
+fn parse_border_size(s: &str) -> Result<BorderSize, String> {
+    let invalid = || {
+        format!(
+            "invalid border size '{}' (expected a ratio or pixels, e.g. 0.1 or 40px)",
+            s
+        )
+    };
+    let s = s.trim();
+    if let Some(pixels) = s.strip_suffix("px") {
+        return pixels
+            .trim()
+            .parse()
+            .map(BorderSize::Pixels)
+            .map_err(|_| invalid());
+    }
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..1.0).contains(&ratio) => Ok(BorderSize::Ratio(ratio)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The resolved width of each border of an image, in pixels of the canvas.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Borders {
+    pub top: f64,
+    pub bottom: f64,
+    pub left: f64,
+    pub right: f64,
+}
+
+impl Borders {
+    /// The borders an image of `orientation` gets: the symmetric ones from its ratios (or
+    /// `--border-px`), those of the `--style` or those all `--frames` add up to, with the
+    /// per-side overrides applied.
+    fn of(orientation: Orientation, config: &Config) -> Self {
+        let (width, height) = (config.target_width, config.target_height);
+        let defaults = match (config.style, config.border_px) {
+            _ if !config.frames.is_empty() => {
+                let total = frames::total_width(&config.frames, width.min(height));
+                Self {
+                    top: total,
+                    bottom: total,
+                    left: total,
+                    right: total,
+                }
+            }
+            (Some(style), None) => style.borders(width.min(height)),
+            _ => {
+                let (vert_ratio, horiz_ratio) = orientation.border_ratios(config);
+                Self {
+                    top: height as f64 * vert_ratio,
+                    bottom: height as f64 * vert_ratio,
+                    left: width as f64 * horiz_ratio,
+                    right: width as f64 * horiz_ratio,
+                }
+            }
+        };
+        let side = |size: Option<BorderSize>, default: f64, length: u32| {
+            size.map_or(default, |size| size.resolve(length))
+        };
+        Self {
+            top: side(config.border_top, defaults.top, height),
+            bottom: side(config.border_bottom, defaults.bottom, height),
+            left: side(config.border_left, defaults.left, width),
+            right: side(config.border_right, defaults.right, width),
+        }
+    }
+
+    fn available_width(&self, config: &Config) -> f64 {
+        config.target_width as f64 - self.left - self.right
+    }
+
+    fn available_height(&self, config: &Config) -> f64 {
+        config.target_height as f64 - self.top - self.bottom
+    }
+
+    /// Widest and tallest the image may get with a gap of at least `--min-border-px` on
+    /// every side (unlimited without it). The image sits midway between the borders, so
+    /// shrinking it only moves each edge in by half: where the borders differ, the gap on
+    /// the side of the narrower one needs the difference on top of twice the minimum.
+    fn min_border_limits(&self, config: &Config) -> (f64, f64) {
+        let Some(min) = config.min_border_px else {
+            return (f64::INFINITY, f64::INFINITY);
+        };
+        let limit = |target: u32, before: f64, after: f64| {
+            (target as f64 - 2.0 * min as f64 - (before - after).abs()).floor()
+        };
+        (
+            limit(config.target_width, self.left, self.right),
+            limit(config.target_height, self.top, self.bottom),
+        )
+    }
+}
+
+/// `--fit`: how an image fills the area inside the borders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum FitMode {
+    /// Scale it to fit inside, keeping all of it; the borders take up the difference.
+    Contain,
+    /// Scale it to cover the whole area and crop what overflows, evenly from both sides.
+    Cover,
+}
+
+/// The size an image is scaled to, and what kept it from filling the area inside the borders.
+struct Fit {
+    /// Size of the scaled image, before `--fit cover` crops it.
+    scaled_width: u32,
+    scaled_height: u32,
+    /// Size of what is placed on the canvas.
+    width: u32,
+    height: u32,
+    /// `--min-border-px` made it smaller.
+    shrunk_for_min_border: bool,
+    /// `--no-upscale` or `--max-upscale` made it smaller.
+    upscale_capped: bool,
+}
+
+/// Returns the size an image of `width` x `height` is scaled to so it fits inside the
+/// borders.
+fn fit(width: u32, height: u32, config: &Config) -> Fit {
+    let borders = Borders::of(Orientation::of(width, height, config), config);
+
+    let available_width = borders.available_width(config);
+    let available_height = borders.available_height(config);
+
+    let (max_width, max_height) = borders.min_border_limits(config);
+    let cap = config.max_upscale.unwrap_or(f64::INFINITY);
+    if config.fit_mode == FitMode::Cover {
+        // The image fills this area and is cropped to it; --min-border-px only makes it
+        // smaller.
+        let area_width = available_width.min(max_width).round().max(1.0);
+        let area_height = available_height.min(max_height).round().max(1.0);
+        let covering = (area_width / width as f64).max(area_height / height as f64);
+        let scale = covering.min(cap);
+        let scaled_width = ((width as f64 * scale).round() as u32).max(1);
+        let scaled_height = ((height as f64 * scale).round() as u32).max(1);
+        return Fit {
+            scaled_width,
+            scaled_height,
+            width: scaled_width.min(area_width as u32),
+            height: scaled_height.min(area_height as u32),
+            shrunk_for_min_border: max_width < available_width || max_height < available_height,
+            upscale_capped: cap < covering,
+        };
+    }
+    let filling = (available_width / width as f64).min(available_height / height as f64);
+    let limited = (max_width / width as f64).min(max_height / height as f64);
+    let scale = filling.min(limited).min(cap);
+
+    let scaled_width = (width as f64 * scale).round() as u32;
+    let scaled_height = (height as f64 * scale).round() as u32;
+    Fit {
+        scaled_width,
+        scaled_height,
+        width: scaled_width,
+        height: scaled_height,
+        shrunk_for_min_border: limited < filling && limited <= cap,
+        upscale_capped: cap < filling.min(limited),
+    }
+}
+
+/// Pixels `--fit cover` cut off each edge of the scaled image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Crop {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// Where a scaled image sits on the canvas.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Placement {
+    /// Offset of the image's top left corner.
+    pub x: u32,
+    pub y: u32,
+    /// Size of the image on the canvas: the size it is scaled to, less the `crop`.
+    pub width: u32,
+    pub height: u32,
+    pub crop: Crop,
+    /// True if `--min-border-px` scaled it down further than the borders did.
+    pub shrunk_for_min_border: bool,
+    /// True if `--no-upscale` or `--max-upscale` kept it from being enlarged to fill the
+    /// borders.
+    pub upscale_capped: bool,
+    /// True if `--offset-x` or `--offset-y` would have moved it past an edge of the canvas,
+    /// so it was stopped there.
+    pub offset_clamped: bool,
+}
+
+impl Placement {
+    /// Size the image is scaled to, before `--fit cover` crops it.
+    pub fn scaled_size(&self) -> (u32, u32) {
+        let crop = self.crop;
+        (
+            self.width + crop.left + crop.right,
+            self.height + crop.top + crop.bottom,
+        )
+    }
+
+    /// Places an image of `width` x `height` in the area the borders leave, as `--gravity`
+    /// says.
+    fn of(width: u32, height: u32, config: &Config) -> Self {
+        let fit = fit(width, height, config);
+        // Of the pixels cropped along an axis, the share taken before the window; centered,
+        // any odd pixel goes from the right or bottom.
+        let (position_x, position_y) = config
+            .crop_focus
+            .unwrap_or_else(|| config.crop_gravity.position());
+        let before =
+            |cropped: u32, position: f64| ((cropped as f64 * position).floor() as u32).min(cropped);
+        let after = |cropped: u32, position: f64| cropped - before(cropped, position);
+        let borders = Borders::of(Orientation::of(width, height, config), config);
+        // Along the room the image leaves between the borders: centered, midway between them,
+        // so with equal ones in the middle of the canvas. Clamped so rounding, or borders
+        // wider than the canvas, can't push the image off it.
+        // Then moved by `--offset-x` and `--offset-y`, and clamped again.
+        let (gravity_x, gravity_y) = config.gravity.position();
+        let (nudge_x, nudge_y) = config.offset;
+        // Worked out from the centered offset, so the default layout comes out exactly as
+        // `(free + before - after) / 2`: other gravities add their share of the room to it.
+        let offset = |target: u32, scaled: u32, (before, after): (f64, f64), gravity: f64| {
+            let free = target.saturating_sub(scaled) as f64;
+            let centered = free + before - after;
+            let room = free - before - after;
+            ((centered + room * (2.0 * gravity - 1.0)) / 2.0)
+                .floor()
+                .clamp(0.0, free)
+        };
+        let nudged = |offset: f64, nudge: i32, target: u32, scaled: u32| {
+            let free = target.saturating_sub(scaled) as f64;
+            let nudged = offset + nudge as f64;
+            (
+                nudged.clamp(0.0, free) as u32,
+                !(0.0..=free).contains(&nudged),
+            )
+        };
+        let x = offset(
+            config.target_width,
+            fit.width,
+            (borders.left, borders.right),
+            gravity_x,
+        );
+        let y = offset(
+            config.target_height,
+            fit.height,
+            (borders.top, borders.bottom),
+            gravity_y,
+        );
+        let (x, clamped_x) = nudged(x, nudge_x, config.target_width, fit.width);
+        let (y, clamped_y) = nudged(y, nudge_y, config.target_height, fit.height);
+        Self {
+            x,
+            y,
+            width: fit.width,
+            height: fit.height,
+            crop: Crop {
+                top: before(fit.scaled_height - fit.height, position_y),
+                bottom: after(fit.scaled_height - fit.height, position_y),
+                left: before(fit.scaled_width - fit.width, position_x),
+                right: after(fit.scaled_width - fit.width, position_x),
+            },
+            shrunk_for_min_border: fit.shrunk_for_min_border,
+            upscale_capped: fit.upscale_capped,
+            offset_clamped: clamped_x || clamped_y,
+        }
+    }
+}
+
+/// Scales `img` to fit inside the borders and places it on a canvas of the border color, as
+/// `--gravity` says.
+///
+/// With `flatten`, the border color is made opaque and translucent pixels are blended onto
+/// it before scaling, so the filter doesn't mix the color of fully transparent pixels
+/// (often black) into soft edges; otherwise they are copied as-is, alpha included. Works
+/// on any pixel type, so grayscale and 16-bit images keep their layout.
+fn compose<P>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    config: &Config,
+    flatten: bool,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, image::ImageError>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    let (orig_width, orig_height) = img.dimensions();
+    let placement = Placement::of(orig_width, orig_height, config);
+
+    let border = match flatten {
+        true => config.border_color.opaque(),
+        false => config.border_color,
+    };
+    let flattened = flatten.then(|| {
+        let mut flattened = ImageBuffer::from_pixel(orig_width, orig_height, border.pixel());
+        imageops::overlay(&mut flattened, img, 0, 0);
+        flattened
+    });
+    let source = flattened.as_ref().unwrap_or(img);
+
+    let mut canvas = match config.background {
+        Background::Color => {
+            ImageBuffer::from_pixel(config.target_width, config.target_height, border.pixel())
+        }
+        Background::Blur => background::blurred(source, config),
+    };
+    frames::draw(&mut canvas, placement, &config.frames, flatten);
+    if let Some(shadow) = config.shadow {
+        shadow.draw(&mut canvas, placement, config.corner_radius);
+    }
+    let corners = config
+        .corner_radius
+        .map(|radius| (radius, corners::backgrounds(&canvas, placement, radius)));
+
+    // Resize source image (bilinear-like filter); at its own size (--pad-only) it is
+    // copied untouched.
+    let (scaled_width, scaled_height) = placement.scaled_size();
+    let mut resized = match (scaled_width, scaled_height) == (orig_width, orig_height) {
+        true => Cow::Borrowed(source),
+        false => Cow::Owned(imageops::resize(
+            source,
+            scaled_width,
+            scaled_height,
+            FilterType::Triangle,
+        )),
+    };
+    if placement.crop != Crop::default() {
+        let Crop { top, left, .. } = placement.crop;
+        resized = Cow::Owned(
+            imageops::crop_imm(
+                resized.as_ref(),
+                left,
+                top,
+                placement.width,
+                placement.height,
+            )
+            .to_image(),
+        );
+    }
+
+    match config.background {
+        Background::Color => canvas.copy_from(resized.as_ref(), placement.x, placement.y)?,
+        // Translucent pixels show the blurred copy through rather than a hole in it.
+        Background::Blur => imageops::overlay(
+            &mut canvas,
+            resized.as_ref(),
+            placement.x as i64,
+            placement.y as i64,
+        ),
+    }
+    if let Some((radius, backgrounds)) = &corners {
+        corners::round(&mut canvas, backgrounds, placement, *radius);
+    }
+    if let Some(width) = config.keyline {
+        draw_keyline(&mut canvas, placement, width, config.keyline_color.pixel());
+    }
+    if let (Some(caption), Some(text)) = (&config.caption, &config.caption_text) {
+        caption.draw(
+            &mut canvas,
+            placement,
+            text,
+            caption.color_on(border).pixel(),
+        );
+    }
+
+    Ok(canvas)
+}
+
+/// Draws a `width` px outline in `color` around the image at `placement`, touching its edge
+/// from outside and cut off at the canvas edge. Whole pixels only, so it stays crisp.
+fn draw_keyline<P: Pixel>(
+    canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    placement: Placement,
+    width: u32,
+    color: P,
+) {
+    let (canvas_width, canvas_height) = canvas.dimensions();
+    let (image_right, image_bottom) = (
+        placement.x + placement.width,
+        placement.y + placement.height,
+    );
+    let left = placement.x.saturating_sub(width);
+    let top = placement.y.saturating_sub(width);
+    let right = image_right.saturating_add(width).min(canvas_width);
+    let bottom = image_bottom.saturating_add(width).min(canvas_height);
+    for y in top..bottom {
+        let spans = match (placement.y..image_bottom).contains(&y) {
+            // Beside the image: only the parts left and right of it.
+            true => [left..placement.x, image_right..right],
+            false => [left..right, 0..0],
+        };
+        for x in spans.into_iter().flatten() {
+            canvas.put_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The settings the command line `args` gives, for an input that is never read.
+    pub(crate) fn config(args: &[&str]) -> Config {
+        let args = ["white_border_adder", "photo.jpg"].iter().chain(args);
+        Config::from_args(&Args::parse_from(args))
+    }
+
+    /// An empty folder of its own for the test `name`.
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("white_border_adder-{}", std::process::id()))
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// An EXIF block, as `Metadata` holds it, with `fields` in its 0th IFD.
+    pub(crate) fn exif(fields: &[exif::Field]) -> Vec<u8> {
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut out = std::io::Cursor::new(Vec::new());
+        writer.write(&mut out, false).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn orientation_classes_meet_at_their_ratios() {
+        use Orientation::*;
+
+        let defaults = config(&[]);
+        let of = |width, height| Orientation::of(width, height, &defaults);
+        // Within 2% of 1:1 is square, either way round.
+        assert_eq!(of(1000, 1000), Square);
+        assert_eq!(of(1000, 1020), Square);
+        assert_eq!(of(1020, 1000), Square);
+        assert_eq!(of(1000, 1021), Portrait);
+        assert_eq!(of(1021, 1000), Landscape);
+        // Exactly 2:1 is still landscape or portrait; only wider is a panorama.
+        assert_eq!(of(2000, 1000), Landscape);
+        assert_eq!(of(2001, 1000), Panorama);
+        assert_eq!(of(1000, 2000), Portrait);
+        assert_eq!(of(1000, 2001), Tall);
+
+        let strict = config(&["--square-tolerance", "0", "--panorama-threshold", "3"]);
+        assert_eq!(Orientation::of(1000, 1000, &strict), Square);
+        assert_eq!(Orientation::of(1001, 1000, &strict), Landscape);
+        assert_eq!(Orientation::of(2001, 1000, &strict), Landscape);
+        assert_eq!(Orientation::of(3001, 1000, &strict), Panorama);
+        assert_eq!(Orientation::classify(1, 3001, 0.0, 3000.0), Tall);
+    }
+
+    #[test]
+    fn sixteen_bit_gradients_keep_their_steps() {
+        // 1000 steps of 65 from black to white; at 8 bits there is room for only 256.
+        let gradient: ImageBuffer<image::Rgb<u16>, _> =
+            ImageBuffer::from_fn(1000, 10, |x, _| image::Rgb([x as u16 * 65; 3]));
+        let config = config(&["--width", "1200", "--height", "1200"]);
+        let canvas = compose(&gradient, &config, false).unwrap();
+        // Across the middle of the canvas, through the image and its side borders.
+        let mut levels: Vec<u16> = (0..canvas.width())
+            .map(|x| canvas.get_pixel(x, canvas.height() / 2).0[0])
+            .collect();
+        levels.dedup();
+        assert!(levels.len() > 900, "{} levels", levels.len());
+        assert!(levels.iter().any(|&level| level % 257 != 0));
+    }
+
+    #[test]
+    fn best_png_compression_is_smaller() {
+        let photo = image::RgbImage::from_fn(300, 200, |x, y| {
+            image::Rgb([
+                (x * 255 / 300) as u8,
+                (y * 255 / 200) as u8,
+                ((x * y) % 7 * 30) as u8,
+            ])
+        });
+        let encoded = |args: &[&str]| {
+            let config = config(&[&["--width", "400", "--height", "400"], args].concat());
+            let canvas = DynamicImage::from(compose(&photo, &config, true).unwrap());
+            let mut png = Vec::new();
+            write_canvas(
+                &canvas,
+                &Metadata::default(),
+                OutputFormat::Png,
+                &config,
+                &mut png,
+            )
+            .unwrap();
+            png
+        };
+        let default = encoded(&[]);
+        let best = encoded(&["--png-compression", "best"]);
+        assert!(
+            best.len() < default.len(),
+            "best {} vs default {}",
+            best.len(),
+            default.len()
+        );
+        let decoded = |png: &[u8]| image::load_from_memory(png).unwrap().to_rgb8();
+        assert_eq!(decoded(&best), decoded(&default));
+    }
+
+    #[test]
+    fn centered_placement_is_exact() {
+        // A portrait that fills the area between its side borders exactly; any error in
+        // working out the middle shows up as the odd pixel going to the wrong side.
+        let placement = Placement::of(691, 1038, &config(&["--width", "1080", "--height", "1080"]));
+        assert_eq!((placement.x, placement.y), (194, 21));
+        assert_eq!((placement.width, placement.height), (691, 1038));
+    }
+
+    #[test]
+    fn gravity_places_against_the_borders() {
+        let at = |gravity: &str| {
+            let config = config(&["--width", "1080", "--height", "1080", "--gravity", gravity]);
+            let placement = Placement::of(691, 1038, &config);
+            (placement.x, placement.y)
+        };
+        // 5.4 px top and bottom borders, which the image stops at.
+        assert_eq!(at("north"), (194, 5));
+        assert_eq!(at("south"), (194, 36));
+        assert_eq!(at("southeast"), (194, 36));
+        assert_eq!(at("center"), (194, 21));
+    }
+
+    #[test]
+    fn offsets_stop_at_the_canvas_edges() {
+        // Centered at 194,21 with 389 px to spare across and 42 down.
+        let at = |x: &str, y: &str| {
+            let config = config(&[
+                "--width",
+                "1080",
+                "--height",
+                "1080",
+                "--offset-x",
+                x,
+                "--offset-y",
+                y,
+            ]);
+            let placement = Placement::of(691, 1038, &config);
+            ((placement.x, placement.y), placement.offset_clamped)
+        };
+        assert_eq!(at("-40", "-10"), ((154, 11), false));
+        assert_eq!(at("-194", "21"), ((0, 42), false));
+        assert_eq!(at("195", "-21"), ((389, 0), false));
+        assert_eq!(at("-10000", "0"), ((0, 21), true));
+        assert_eq!(at("0", "10000"), ((194, 42), true));
+        assert_eq!(at("10000", "-10000"), ((389, 0), true));
+    }
+
+    #[test]
+    fn placement_stays_on_a_canvas_its_borders_overfill() {
+        for gravity in ["north", "south", "west", "east", "center"] {
+            let config = config(&[
+                "--width",
+                "400",
+                "--height",
+                "300",
+                "--border-px",
+                "2000",
+                "--gravity",
+                gravity,
+            ]);
+            let placement = Placement::of(800, 600, &config);
+            assert!(
+                placement.x + placement.width <= 400,
+                "{}: {:?}",
+                gravity,
+                placement
+            );
+            assert!(
+                placement.y + placement.height <= 300,
+                "{}: {:?}",
+                gravity,
+                placement
+            );
+        }
+    }
 }
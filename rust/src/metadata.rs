@@ -0,0 +1,1195 @@
+//! `--metadata`: which of an input's EXIF, XMP and ICC data reaches its output.
+//!
+//! Outputs are re-encoded from pixels, so nothing is carried over unless it is read from the
+//! input here and written back into the new container. JPEG, PNG and WebP outputs can hold
+//! all three, TIFF outputs the ICC profile; AVIF and GIF outputs are always stripped.
+//!
+//! The ICC profile matters most: without it, Adobe RGB or Display P3 photos are shown as if
+//! they were sRGB and look washed out or oversaturated. It is copied across format changes
+//! too, so a PNG written as JPEG keeps its profile.
+//!
+//! Copied EXIF gets its Orientation reset to 1: the canvas is written the way it is laid out,
+//! so a viewer must not rotate it again.
+
+use crate::decode::OptionalCodec;
+use clap::ValueEnum;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::io::{BufRead, Cursor, Seek};
+use std::path::Path;
+
+/// `--metadata`: what to copy from an input to its output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MetadataPolicy {
+    /// No EXIF, XMP or ICC data in outputs.
+    Strip,
+    /// Copy EXIF, XMP and the ICC profile.
+    Keep,
+    /// Only the ICC profile and the capture time (EXIF DateTimeOriginal).
+    Minimal,
+}
+
+/// Metadata read from an input, raw as it is stored: EXIF as a TIFF structure without the
+/// JPEG `Exif\0\0` header, XMP as its XML packet.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    pub icc: Option<Vec<u8>>,
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Reads the metadata of the image at `path` that `policy` keeps. Formats that can't be
+    /// read give none; corrupt EXIF is left out with a warning.
+    pub fn from_path(path: &Path, policy: MetadataPolicy) -> Self {
+        if policy == MetadataPolicy::Strip {
+            return Self::default();
+        }
+        let label = path.display().to_string();
+        if OptionalCodec::for_path(path) == Some(OptionalCodec::Heif) {
+            return read_heif(path).for_policy(policy, &label);
+        }
+        ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map(|reader| read(reader).for_policy(policy, &label))
+            .unwrap_or_default()
+    }
+
+    /// Like `from_path`, for an image held in memory; `label` names it in warnings.
+    pub fn from_bytes(data: &[u8], label: &str, policy: MetadataPolicy) -> Self {
+        if policy == MetadataPolicy::Strip {
+            return Self::default();
+        }
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .map(|reader| read(reader).for_policy(policy, label))
+            .unwrap_or_default()
+    }
+
+    /// Narrows everything read from an input to what `policy` keeps, and checks the EXIF.
+    /// What is kept records the orientation as upright, as decoding turns the pixels so.
+    fn for_policy(self, policy: MetadataPolicy, label: &str) -> Self {
+        let exif = self.exif.and_then(|exif| match checked_exif(exif) {
+            Ok(exif) => Some(exif),
+            Err(e) => {
+                eprintln!("⚠️  Corrupt EXIF data in {}, not copied: {}", label, e);
+                None
+            }
+        });
+        match policy {
+            MetadataPolicy::Strip => Self::default(),
+            MetadataPolicy::Keep => Self {
+                exif,
+                xmp: self.xmp.map(upright_xmp),
+                ..self
+            },
+            MetadataPolicy::Minimal => Self {
+                icc: self.icc,
+                exif: exif.and_then(capture_time_only),
+                xmp: None,
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.icc.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+
+    /// Drops the GPS fields from the EXIF and the location properties from the XMP
+    /// (`--strip-gps`).
+    pub fn without_location(self) -> Self {
+        Self {
+            exif: self.exif.and_then(without_gps),
+            xmp: self.xmp.and_then(without_xmp_location),
+            ..self
+        }
+    }
+
+    /// Returns the metadata to write with `canvas`: the image size in the EXIF becomes the
+    /// canvas's, and an ICC profile for another colour space than the canvas (a CMYK input's,
+    /// which decoding already applied) is dropped.
+    pub fn for_canvas(&self, canvas: &DynamicImage) -> Self {
+        let space: &[u8] = if canvas.color().has_color() {
+            b"RGB "
+        } else {
+            b"GRAY"
+        };
+        Self {
+            icc: self
+                .icc
+                .clone()
+                .filter(|icc| icc.get(16..20) == Some(space)),
+            exif: self
+                .exif
+                .clone()
+                .map(|exif| with_dimensions(exif, (canvas.width(), canvas.height()))),
+            xmp: self.xmp.clone(),
+        }
+    }
+}
+
+fn read<R: BufRead + Seek>(reader: ImageReader<R>) -> Metadata {
+    let Ok(mut decoder) = reader.into_decoder() else {
+        return Metadata::default();
+    };
+    Metadata {
+        icc: decoder.icc_profile().ok().flatten(),
+        exif: decoder.exif_metadata().ok().flatten(),
+        xmp: decoder.xmp_metadata().ok().flatten(),
+    }
+}
+
+/// Reads a HEIF image's EXIF and ICC profile through libheif. libheif hands over pixels in
+/// the file's own colour space, so its profile still applies to the output.
+#[cfg(feature = "heif")]
+fn read_heif(path: &Path) -> Metadata {
+    use libheif_rs::HeifContext;
+
+    let Some(handle) = path
+        .to_str()
+        .and_then(|path| HeifContext::read_from_file(path).ok())
+        .and_then(|context| context.primary_image_handle().ok())
+    else {
+        return Metadata::default();
+    };
+    let mut ids = [0; 1];
+    let exif = match handle.metadata_block_ids(&mut ids, b"Exif") {
+        // The block starts with the offset of the TIFF header after these 4 bytes.
+        1 => handle.metadata(ids[0]).ok().and_then(|block| {
+            let offset = u32::from_be_bytes(block.get(..4)?.try_into().ok()?) as usize;
+            block.get(4 + offset..).map(<[u8]>::to_vec)
+        }),
+        _ => None,
+    };
+    Metadata {
+        icc: handle.color_profile_raw().map(|profile| profile.data),
+        exif,
+        xmp: None,
+    }
+}
+
+#[cfg(not(feature = "heif"))]
+fn read_heif(_path: &Path) -> Metadata {
+    Metadata::default()
+}
+
+/// Checks that EXIF data parses, sets its Orientation to 1 (upright) and drops its
+/// thumbnail, a picture of the input without the border, in place, so everything else,
+/// maker notes included, stays byte for byte as it was.
+fn checked_exif(mut exif: Vec<u8>) -> Result<Vec<u8>, exif::Error> {
+    exif::Reader::new().read_raw(exif.clone())?;
+    let order = ByteOrder::of(&exif);
+    // Parsing succeeded, so the header and the 0th IFD are in bounds.
+    let ifd = order.u32(&exif, 4).unwrap_or_default() as usize;
+    for entry in ifd_entries(&exif, ifd, order) {
+        // Orientation is one SHORT, stored in the entry itself.
+        if order.u16(&exif, entry) == Some(0x0112) && order.u16(&exif, entry + 2) == Some(3) {
+            exif[entry + 8..entry + 10].copy_from_slice(&order.u16_bytes(1));
+        }
+    }
+    // The thumbnail is the 1st IFD, linked from the end of the 0th. Its JPEG is blanked and
+    // the link cut, so readers find neither.
+    let next = ifd + 2 + ifd_entries(&exif, ifd, order).count() * 12;
+    if let Some(thumbnail_ifd) = order.u32(&exif, next).filter(|&at| at != 0) {
+        let entries: Vec<usize> = ifd_entries(&exif, thumbnail_ifd as usize, order).collect();
+        let value = |tag| {
+            let entry = entries
+                .iter()
+                .find(|&&entry| order.u16(&exif, entry) == Some(tag))?;
+            order.u32(&exif, entry + 8).map(|value| value as usize)
+        };
+        // JPEGInterchangeFormat and JPEGInterchangeFormatLength
+        if let (Some(start), Some(len)) = (value(0x0201), value(0x0202)) {
+            if let Some(jpeg) = exif.get_mut(start..start.saturating_add(len)) {
+                jpeg.fill(0);
+            }
+        }
+        exif[next..next + 4].fill(0);
+    }
+    Ok(exif)
+}
+
+/// Sets the image size EXIF records, ImageWidth and ImageLength in the 0th IFD and
+/// PixelXDimension and PixelYDimension in the Exif IFD, to `width` x `height` in place.
+fn with_dimensions(mut exif: Vec<u8>, (width, height): (u32, u32)) -> Vec<u8> {
+    let order = ByteOrder::of(&exif);
+    let ifd = order.u32(&exif, 4).unwrap_or_default() as usize;
+    let exif_ifd = ifd_entries(&exif, ifd, order)
+        .find(|&entry| order.u16(&exif, entry) == Some(0x8769))
+        .and_then(|entry| order.u32(&exif, entry + 8));
+    let entries: Vec<usize> = ifd_entries(&exif, ifd, order)
+        .chain(
+            exif_ifd
+                .into_iter()
+                .flat_map(|at| ifd_entries(&exif, at as usize, order)),
+        )
+        .collect();
+    for entry in entries {
+        let value = match order.u16(&exif, entry) {
+            Some(0x0100 | 0xA002) => width,
+            Some(0x0101 | 0xA003) => height,
+            _ => continue,
+        };
+        // One SHORT or LONG, stored in the entry itself; a SHORT too small becomes a LONG.
+        match (order.u16(&exif, entry + 2), order.u32(&exif, entry + 4)) {
+            (Some(3), Some(1)) if value <= u16::MAX as u32 => {
+                exif[entry + 8..entry + 10].copy_from_slice(&order.u16_bytes(value as u16));
+            }
+            (Some(3 | 4), Some(1)) => {
+                exif[entry + 2..entry + 4].copy_from_slice(&order.u16_bytes(4));
+                exif[entry + 8..entry + 12].copy_from_slice(&order.u32_bytes(value));
+            }
+            _ => {}
+        }
+    }
+    exif
+}
+
+/// The byte order of a TIFF structure, as its first two bytes give it.
+#[derive(Clone, Copy)]
+struct ByteOrder {
+    big_endian: bool,
+}
+
+impl ByteOrder {
+    fn of(tiff: &[u8]) -> Self {
+        Self {
+            big_endian: tiff.starts_with(b"MM"),
+        }
+    }
+
+    fn u16(self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes = data.get(at..at.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes = data.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u16_bytes(self, value: u16) -> [u8; 2] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn u32_bytes(self, value: u32) -> [u8; 4] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+}
+
+/// The offsets of the 12-byte entries of the IFD at `ifd`, as far as they are in bounds.
+fn ifd_entries(tiff: &[u8], ifd: usize, order: ByteOrder) -> impl Iterator<Item = usize> {
+    let count = order.u16(tiff, ifd).unwrap_or_default() as usize;
+    let len = tiff.len();
+    (0..count)
+        .map(move |i| ifd + 2 + i * 12)
+        .take_while(move |&entry| entry + 12 <= len)
+}
+
+/// Rewrites EXIF data with DateTimeOriginal as its only field, or `None` if it has none.
+fn capture_time_only(exif: Vec<u8>) -> Option<Vec<u8>> {
+    use exif::{In, Reader, Tag};
+
+    let exif = Reader::new().read_raw(exif).ok()?;
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    let mut writer = exif::experimental::Writer::new();
+    writer.push_field(field);
+    let mut out = Cursor::new(Vec::new());
+    writer.write(&mut out, exif.little_endian()).ok()?;
+    Some(out.into_inner())
+}
+
+/// EXIF Software of `--stamp-metadata` outputs.
+const SOFTWARE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// Character code UserComment text starts with, marking it as ASCII.
+const USER_COMMENT_ASCII: &[u8] = b"ASCII\0\0\0";
+
+/// Rewrites EXIF data with Software set to this tool and UserComment to `settings`
+/// (`--stamp-metadata`), replacing whatever the input had there. Without EXIF, a new block
+/// with just those two is made.
+///
+/// Unlike `checked_exif`, this lays the EXIF out anew: every field and the thumbnail are
+/// kept, but maker notes that point at absolute offsets may not survive the move. If the
+/// input's EXIF can't be rewritten, it is returned as it was.
+pub fn stamp_exif(exif: Option<Vec<u8>>, settings: &str) -> Option<Vec<u8>> {
+    use exif::{Field, In, Reader, Tag, Value};
+
+    let stamp = [
+        Field {
+            tag: Tag::Software,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![SOFTWARE.as_bytes().to_vec()]),
+        },
+        Field {
+            tag: Tag::UserComment,
+            ifd_num: In::PRIMARY,
+            value: Value::Undefined([USER_COMMENT_ASCII, settings.as_bytes()].concat(), 0),
+        },
+    ];
+    let parsed = match exif {
+        Some(exif) => match Reader::new().read_raw(exif.clone()) {
+            Ok(parsed) => Some(parsed),
+            Err(_) => return Some(exif),
+        },
+        None => None,
+    };
+    let replaced = |field: &Field| stamp.iter().any(|s| s.tag == field.tag);
+    rewrite_exif(parsed.as_ref(), replaced, &stamp)
+        .or_else(|| parsed.map(|parsed| parsed.buf().to_vec()))
+}
+
+/// Rewrites EXIF data without its GPS fields (`--strip-gps`). EXIF without any is returned
+/// untouched; EXIF that can't be read or rewritten is dropped, so no location gets through.
+fn without_gps(exif: Vec<u8>) -> Option<Vec<u8>> {
+    let is_gps = |field: &exif::Field| field.tag.context() == exif::Context::Gps;
+    let parsed = exif::Reader::new().read_raw(exif).ok()?;
+    if !parsed.fields().any(is_gps) {
+        return Some(parsed.buf().to_vec());
+    }
+    rewrite_exif(Some(&parsed), is_gps, &[])
+}
+
+/// Lays out `exif` anew, leaving out the fields `drop` matches and adding `add`, or `None`
+/// if it can't be written. Without `exif`, the block holds just `add`.
+fn rewrite_exif(
+    exif: Option<&exif::Exif>,
+    drop: impl Fn(&exif::Field) -> bool,
+    add: &[exif::Field],
+) -> Option<Vec<u8>> {
+    let mut writer = exif::experimental::Writer::new();
+    if let Some(exif) = exif {
+        for field in exif.fields().filter(|&field| !drop(field)) {
+            writer.push_field(field);
+        }
+        if let Some(thumbnail) = thumbnail(exif) {
+            writer.set_jpeg(thumbnail, exif::In::THUMBNAIL);
+        }
+    }
+    for field in add {
+        writer.push_field(field);
+    }
+    let mut out = Cursor::new(Vec::new());
+    writer
+        .write(&mut out, exif.is_some_and(|exif| exif.little_endian()))
+        .ok()?;
+    Some(out.into_inner())
+}
+
+/// Local names of the XMP properties `--strip-gps` removes besides the GPS ones: the place
+/// names of the IPTC and Photoshop schemas.
+const XMP_LOCATION_PROPERTIES: [&str; 11] = [
+    "City",
+    "State",
+    "Country",
+    "CountryCode",
+    "CountryName",
+    "ProvinceState",
+    "Location",
+    "LocationCreated",
+    "LocationShown",
+    "Sublocation",
+    "WorldRegion",
+];
+
+fn is_xmp_location(name: &str) -> bool {
+    let local = name.rsplit(':').next().unwrap_or(name);
+    local.starts_with("GPS") || XMP_LOCATION_PROPERTIES.contains(&local)
+}
+
+/// Removes the location properties from an XMP packet (`--strip-gps`), whether written as
+/// attributes or as elements. A packet that isn't UTF-8 is dropped.
+fn without_xmp_location(xmp: Vec<u8>) -> Option<Vec<u8>> {
+    let xmp = String::from_utf8(xmp).ok()?;
+    let mut out = String::with_capacity(xmp.len());
+    let mut rest = xmp.as_str();
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let (tag, after) = rest[start..].split_at(xml_tag_len(&rest[start..]));
+        rest = after;
+        if tag.starts_with("</") || tag.starts_with("<?") || tag.starts_with("<!") {
+            out.push_str(tag);
+        } else if is_xmp_location(xml_tag_name(tag)) {
+            if !tag.ends_with("/>") {
+                rest = after_closing_tag(rest, xml_tag_name(tag));
+            }
+        } else {
+            out.push_str(&without_location_attributes(tag));
+        }
+    }
+    out.push_str(rest);
+    Some(out.into_bytes())
+}
+
+/// The XMP property recording the orientation, as EXIF Orientation does.
+const XMP_ORIENTATION: &str = "tiff:Orientation";
+
+/// Sets `tiff:Orientation` in an XMP packet to 1 (upright), whether written as an attribute
+/// or as an element. A packet that isn't UTF-8 is left as it is.
+fn upright_xmp(xmp: Vec<u8>) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(&xmp) else {
+        return xmp;
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let (tag, after) = rest[start..].split_at(xml_tag_len(&rest[start..]));
+        rest = after;
+        if tag.starts_with("</") || tag.starts_with("<?") || tag.starts_with("<!") {
+            out.push_str(tag);
+            continue;
+        }
+        out.push_str(&edit_attributes(tag, |name, attribute| {
+            match name == XMP_ORIENTATION {
+                true => Some(format!(" {}=\"1\"", name)),
+                false => Some(attribute.to_string()),
+            }
+        }));
+        if xml_tag_name(tag) == XMP_ORIENTATION && !tag.ends_with("/>") {
+            // The value runs up to the closing tag.
+            out.push('1');
+            rest = &rest[rest.find('<').unwrap_or(rest.len())..];
+        }
+    }
+    out.push_str(rest);
+    out.into_bytes()
+}
+
+/// Length of the tag `xml` starts with, up to its `>`; quoted attribute values may hold one.
+fn xml_tag_len(xml: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in xml.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    xml.len()
+}
+
+/// The element name of a tag, e.g. `exif:GPSLatitude` for `</exif:GPSLatitude>`.
+fn xml_tag_name(tag: &str) -> &str {
+    let tag = tag.trim_start_matches(['<', '/']);
+    let end = tag.find(|c: char| c.is_whitespace() || c == '/' || c == '>');
+    &tag[..end.unwrap_or(tag.len())]
+}
+
+/// Returns what follows the tag closing the element `name` whose start tag `xml` followed,
+/// skipping elements of the same name nested in it.
+fn after_closing_tag<'a>(mut xml: &'a str, name: &str) -> &'a str {
+    let mut depth = 1;
+    while let Some(start) = xml.find('<') {
+        let (tag, after) = xml[start..].split_at(xml_tag_len(&xml[start..]));
+        xml = after;
+        if xml_tag_name(tag) != name {
+            continue;
+        }
+        if tag.starts_with("</") {
+            depth -= 1;
+            if depth == 0 {
+                return xml;
+            }
+        } else if !tag.ends_with("/>") {
+            depth += 1;
+        }
+    }
+    ""
+}
+
+/// Returns a start tag without its location attributes.
+fn without_location_attributes(tag: &str) -> String {
+    edit_attributes(tag, |name, attribute| {
+        (!is_xmp_location(name)).then(|| attribute.to_string())
+    })
+}
+
+/// Returns a start tag with each attribute replaced by what `edit` makes of its name and its
+/// text, leading whitespace included; `None` drops it.
+fn edit_attributes(tag: &str, edit: impl Fn(&str, &str) -> Option<String>) -> String {
+    let name_end = tag.find(|c: char| c.is_whitespace() || c == '/' || c == '>');
+    let (name, mut rest) = tag.split_at(name_end.unwrap_or(tag.len()));
+    let mut out = name.to_string();
+    loop {
+        let attribute = rest.trim_start();
+        let value = attribute
+            .find('=')
+            .map(|eq| (eq, attribute[eq + 1..].trim_start()));
+        let Some((eq, value)) = value else { break };
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(close) = value[1..].find(quote) else {
+            break;
+        };
+        // Whitespace, name, `=` and value, both quotes included.
+        let len = rest.len() - value.len() + close + 2;
+        if let Some(edited) = edit(attribute[..eq].trim(), &rest[..len]) {
+            out.push_str(&edited);
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The JPEG thumbnail an EXIF block carries, if any.
+fn thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    use exif::{In, Tag};
+
+    let uint = |tag| exif.get_field(tag, In::THUMBNAIL)?.value.get_uint(0);
+    let offset = uint(Tag::JPEGInterchangeFormat)? as usize;
+    let length = uint(Tag::JPEGInterchangeFormatLength)? as usize;
+    exif.buf().get(offset..offset.checked_add(length)?)
+}
+
+/// Largest payload of one JPEG marker segment, after its 2-byte length.
+const JPEG_SEGMENT_MAX: usize = 65533;
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+/// Inserts `metadata` into an encoded JPEG, after its JFIF header.
+///
+/// The ICC profile is split across APP2 segments as the ICC spec describes. EXIF and XMP
+/// that don't fit one segment are left out; no reader would find their continuation.
+pub fn embed_in_jpeg(encoded: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    let mut segments = Vec::new();
+    let mut push_segment = |marker: u8, parts: &[&[u8]]| {
+        let len: usize = parts.iter().map(|p| p.len()).sum();
+        segments.extend_from_slice(&[0xFF, marker]);
+        segments.extend_from_slice(&(len as u16 + 2).to_be_bytes());
+        for part in parts {
+            segments.extend_from_slice(part);
+        }
+    };
+    if let Some(exif) = &metadata.exif {
+        if JPEG_EXIF_HEADER.len() + exif.len() <= JPEG_SEGMENT_MAX {
+            push_segment(0xE1, &[JPEG_EXIF_HEADER, exif]);
+        }
+    }
+    if let Some(xmp) = &metadata.xmp {
+        if JPEG_XMP_HEADER.len() + xmp.len() <= JPEG_SEGMENT_MAX {
+            push_segment(0xE1, &[JPEG_XMP_HEADER, xmp]);
+        }
+    }
+    if let Some(icc) = &metadata.icc {
+        // Each segment holds the header and a 1-based sequence number and count.
+        let chunk_size = JPEG_SEGMENT_MAX - JPEG_ICC_HEADER.len() - 2;
+        let chunks: Vec<&[u8]> = icc.chunks(chunk_size).collect();
+        if chunks.len() <= 255 {
+            for (i, chunk) in chunks.iter().enumerate() {
+                let numbering = [i as u8 + 1, chunks.len() as u8];
+                push_segment(0xE2, &[JPEG_ICC_HEADER, &numbering, chunk]);
+            }
+        }
+    }
+
+    // Keep the JFIF APP0 segment first, where readers expect it.
+    let mut at = 2;
+    if let [0xFF, 0xE0, high, low] = encoded.get(2..6).unwrap_or_default() {
+        at += 2 + u16::from_be_bytes([*high, *low]) as usize;
+    }
+    let at = at.min(encoded.len());
+    let mut out = Vec::with_capacity(encoded.len() + segments.len());
+    out.extend_from_slice(&encoded[..at]);
+    out.extend_from_slice(&segments);
+    out.extend_from_slice(&encoded[at..]);
+    out
+}
+
+/// Inserts XMP into an encoded PNG as an iTXt chunk before the image data, where readers that
+/// stop at the pixels still find it. The `image` PNG encoder writes the ICC profile and EXIF
+/// itself.
+pub fn embed_xmp_in_png(encoded: Vec<u8>, xmp: &[u8]) -> Vec<u8> {
+    // keyword, null, compression flag and method, empty language and translated keyword
+    let mut data = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+    data.extend_from_slice(xmp);
+    insert_png_chunk(encoded, b"iTXt", &data)
+}
+
+/// Adds a pHYs chunk declaring `dpi` to an encoded PNG; the `image` PNG encoder has no
+/// setting for it.
+pub fn embed_dpi_in_png(encoded: Vec<u8>, dpi: u16) -> Vec<u8> {
+    // pHYs counts pixels per metre, in both directions, followed by unit 1 (the metre).
+    let per_metre = (f64::from(dpi) / 0.0254).round() as u32;
+    let mut data = per_metre.to_be_bytes().repeat(2);
+    data.push(1);
+    insert_png_chunk(encoded, b"pHYs", &data)
+}
+
+/// Inserts a chunk into an encoded PNG before the first IDAT, where both XMP and pHYs must go.
+fn insert_png_chunk(mut encoded: Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32fast::hash(&chunk[4..]).to_be_bytes());
+    // Walk the chunks after the 8-byte signature up to the first IDAT.
+    let mut at = 8;
+    while at + 8 <= encoded.len() && &encoded[at + 4..at + 8] != b"IDAT" {
+        let len = u32::from_be_bytes([
+            encoded[at],
+            encoded[at + 1],
+            encoded[at + 2],
+            encoded[at + 3],
+        ]);
+        at += 12 + len as usize;
+    }
+    let at = at.min(encoded.len());
+    encoded.splice(at..at, chunk);
+    encoded
+}
+
+/// Rewrites `canvas` encoded as WebP in the extended format, with `metadata`'s ICCP, EXIF and
+/// XMP chunks.
+pub fn embed_in_webp(encoded: Vec<u8>, metadata: &Metadata, canvas: &DynamicImage) -> Vec<u8> {
+    if metadata.is_empty() || encoded.get(..4) != Some(b"RIFF") {
+        return encoded;
+    }
+    // The image chunks libwebp wrote: VP8 or VP8L, or VP8X, ALPH and VP8 for lossy alpha.
+    // libwebp leaves alpha out of fully opaque images, so whether there is any comes from
+    // its chunks rather than the canvas.
+    let mut image_chunks = Vec::new();
+    let mut has_alpha = false;
+    let mut at = 12;
+    while at + 8 <= encoded.len() {
+        let size = u32::from_le_bytes([
+            encoded[at + 4],
+            encoded[at + 5],
+            encoded[at + 6],
+            encoded[at + 7],
+        ]) as usize;
+        let end = (at + 8 + size + size % 2).min(encoded.len());
+        let data = &encoded[at + 8..end];
+        match &encoded[at..at + 4] {
+            b"VP8X" => has_alpha |= data.first().is_some_and(|flags| flags & 0x10 != 0),
+            chunk => {
+                // The lossless header's alpha bit follows the signature and 28 bits of size.
+                if chunk == b"VP8L" {
+                    has_alpha |= data.get(4).is_some_and(|b| b & 0x10 != 0);
+                }
+                image_chunks.extend_from_slice(&encoded[at..end]);
+            }
+        }
+        at = end;
+    }
+
+    let mut flags = 0u8;
+    let mut chunks_before = Vec::new();
+    let mut chunks_after = Vec::new();
+    if let Some(icc) = &metadata.icc {
+        flags |= 0x20;
+        push_webp_chunk(&mut chunks_before, b"ICCP", icc);
+    }
+    if has_alpha {
+        flags |= 0x10;
+    }
+    if let Some(exif) = &metadata.exif {
+        flags |= 0x08;
+        push_webp_chunk(&mut chunks_after, b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        flags |= 0x04;
+        push_webp_chunk(&mut chunks_after, b"XMP ", xmp);
+    }
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(canvas.width() - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(canvas.height() - 1).to_le_bytes()[..3]);
+
+    let mut body = b"WEBP".to_vec();
+    push_webp_chunk(&mut body, b"VP8X", &vp8x);
+    body.extend_from_slice(&chunks_before);
+    body.extend_from_slice(&image_chunks);
+    body.extend_from_slice(&chunks_after);
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+
+/// Appends a RIFF chunk, padded to an even length.
+fn push_webp_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputFormat;
+    use crate::tests::{config, exif};
+    use exif::{Field, In, Reader, Tag, Value};
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    fn ascii(text: &str) -> Value {
+        Value::Ascii(vec![text.as_bytes().to_vec()])
+    }
+
+    /// Metadata with a bit of everything: a real sRGB profile, a camera make, and an XMP
+    /// packet of odd length, which RIFF chunks pad.
+    fn sample() -> Metadata {
+        Metadata {
+            icc: Some(moxcms::ColorProfile::new_srgb().encode().unwrap()),
+            exif: Some(exif(&[field(Tag::Make, ascii("Canon"))])),
+            xmp: Some(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec()),
+        }
+    }
+
+    /// Reads back what an encoded image carries, and checks its pixels still decode.
+    fn read_back(encoded: &[u8]) -> Metadata {
+        image::load_from_memory(encoded).expect("still decodes");
+        let reader = ImageReader::new(Cursor::new(encoded))
+            .with_guessed_format()
+            .unwrap();
+        read(reader)
+    }
+
+    fn assert_same(read: &Metadata, written: &Metadata) {
+        assert_eq!(read.icc, written.icc);
+        assert_eq!(read.exif, written.exif);
+        assert_eq!(read.xmp, written.xmp);
+    }
+
+    /// The chunk types of a PNG, checking each chunk's CRC on the way.
+    fn png_chunks(png: &[u8]) -> Vec<[u8; 4]> {
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc32fast::hash(body), crc);
+            chunks.push(body[..4].try_into().unwrap());
+            at += 12 + len;
+        }
+        chunks
+    }
+
+    /// The markers of a JPEG's segments up to the start of its scan.
+    fn jpeg_markers(jpeg: &[u8]) -> Vec<u8> {
+        let mut markers = Vec::new();
+        let mut at = 2;
+        while jpeg[at + 1] != 0xDA {
+            markers.push(jpeg[at + 1]);
+            at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        }
+        markers
+    }
+
+    /// The chunk types of a WebP.
+    fn webp_chunks(webp: &[u8]) -> Vec<[u8; 4]> {
+        let mut chunks = Vec::new();
+        let mut at = 12;
+        while at + 8 <= webp.len() {
+            let size = u32::from_le_bytes(webp[at + 4..at + 8].try_into().unwrap()) as usize;
+            chunks.push(webp[at..at + 4].try_into().unwrap());
+            at += 8 + size + size % 2;
+        }
+        chunks
+    }
+
+    /// `canvas` written as `format` with `metadata`, the way outputs are.
+    fn written(canvas: &DynamicImage, metadata: &Metadata, format: OutputFormat) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        crate::write_canvas(canvas, metadata, format, &config(&[]), &mut encoded).unwrap();
+        encoded
+    }
+
+    #[test]
+    fn jpegs_carry_what_is_embedded() {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut encoded = Vec::new();
+        canvas
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        let metadata = sample();
+        assert_same(&read_back(&embed_in_jpeg(encoded, &metadata)), &metadata);
+    }
+
+    /// The payloads of a JPEG's APP2 ICC segments, after their header, in file order.
+    fn icc_segments(jpeg: &[u8]) -> Vec<&[u8]> {
+        let mut segments = Vec::new();
+        let mut at = 2;
+        // Up to the start of scan, where marker segments end.
+        while jpeg[at + 1] != 0xDA {
+            let len = u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+            let payload = &jpeg[at + 4..at + 2 + len];
+            if jpeg[at + 1] == 0xE2 {
+                segments.push(payload.strip_prefix(JPEG_ICC_HEADER).expect("ICC segment"));
+            }
+            at += 2 + len;
+        }
+        segments
+    }
+
+    fn jpeg_with_icc(icc: &[u8]) -> Vec<u8> {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut encoded = Vec::new();
+        canvas
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        let metadata = Metadata {
+            icc: Some(icc.to_vec()),
+            ..Metadata::default()
+        };
+        embed_in_jpeg(encoded, &metadata)
+    }
+
+    #[test]
+    fn display_p3_profiles_go_into_one_app2_segment() {
+        let p3 = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        let jpeg = jpeg_with_icc(&p3);
+        let segments = icc_segments(&jpeg);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0][..2], [1, 1]);
+        assert_eq!(segments[0][2..], p3[..]);
+        let read = read_back(&jpeg);
+        assert_eq!(read.icc.as_ref(), Some(&p3));
+        let canvas = DynamicImage::from(image::RgbImage::new(1, 1));
+        assert_eq!(read.for_canvas(&canvas).icc, Some(p3));
+    }
+
+    #[test]
+    fn large_profiles_are_split_across_numbered_segments() {
+        // A P3 profile padded with a large table, as print and camera profiles have.
+        let mut icc = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        icc.extend((0..150_000).map(|i| (i % 251) as u8));
+        let jpeg = jpeg_with_icc(&icc);
+        let segments = icc_segments(&jpeg);
+        assert_eq!(segments.len(), 3);
+        for (i, segment) in segments.iter().enumerate() {
+            assert_eq!(segment[..2], [i as u8 + 1, 3]);
+            assert!(JPEG_ICC_HEADER.len() + segment.len() <= JPEG_SEGMENT_MAX);
+        }
+        let joined: Vec<u8> = segments
+            .iter()
+            .flat_map(|segment| &segment[2..])
+            .copied()
+            .collect();
+        assert_eq!(joined, icc);
+        assert_eq!(read_back(&jpeg).icc, Some(icc));
+    }
+
+    #[test]
+    fn png_chunks_go_before_the_image_data() {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut encoded = Vec::new();
+        canvas
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Png)
+            .unwrap();
+        let xmp = sample().xmp.unwrap();
+        let encoded = embed_dpi_in_png(embed_xmp_in_png(encoded, &xmp), 300);
+        assert_eq!(read_back(&encoded).xmp, Some(xmp));
+        let chunks = png_chunks(&encoded);
+        let position = |kind: &[u8; 4]| chunks.iter().position(|chunk| chunk == kind).unwrap();
+        assert!(position(b"iTXt") < position(b"IDAT"));
+        assert!(position(b"pHYs") < position(b"IDAT"));
+        let phys = encoded.windows(4).position(|w| w == b"pHYs").unwrap() + 4;
+        // 300 dpi is 11811 pixels per metre.
+        assert_eq!(&encoded[phys..phys + 9], &[0, 0, 46, 35, 0, 0, 46, 35, 1]);
+    }
+
+    #[test]
+    fn webps_carry_what_is_embedded() {
+        let opaque = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut translucent = image::RgbaImage::new(32, 16);
+        translucent.put_pixel(0, 0, image::Rgba([255, 0, 0, 128]));
+        let translucent = DynamicImage::from(translucent);
+        let metadata = sample();
+        for (canvas, lossless) in [(&opaque, false), (&opaque, true), (&translucent, true)] {
+            let encoded = match canvas.as_rgba8() {
+                Some(rgba) => webp::Encoder::from_rgba(rgba, 32, 16).encode_simple(lossless, 80.0),
+                None => {
+                    let rgb = canvas.to_rgb8();
+                    webp::Encoder::from_rgb(&rgb, 32, 16).encode_simple(lossless, 80.0)
+                }
+            };
+            let encoded = embed_in_webp(encoded.unwrap().to_vec(), &metadata, canvas);
+            assert_same(&read_back(&encoded), &metadata);
+            let decoded = image::load_from_memory(&encoded).unwrap();
+            assert_eq!(decoded.color().has_alpha(), canvas.color().has_alpha());
+        }
+    }
+
+    #[test]
+    fn stripped_outputs_carry_no_metadata() {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let tagged = written(&canvas, &sample(), OutputFormat::Jpeg);
+        let formats = [OutputFormat::Jpeg, OutputFormat::Png, OutputFormat::Webp];
+        for input in formats.map(|format| written(&canvas, &sample(), format)) {
+            let metadata = Metadata::from_bytes(&input, "photo.jpg", MetadataPolicy::Strip);
+            assert!(metadata.is_empty());
+            let jpeg = jpeg_markers(&written(&canvas, &metadata, OutputFormat::Jpeg));
+            assert!(
+                !jpeg.iter().any(|marker| matches!(marker, 0xE1 | 0xE2)),
+                "{:x?}",
+                jpeg
+            );
+            let png = png_chunks(&written(&canvas, &metadata, OutputFormat::Png));
+            assert!(!png
+                .iter()
+                .any(|chunk| [b"eXIf", b"iCCP", b"iTXt"].contains(&chunk)));
+            let webp = webp_chunks(&written(&canvas, &metadata, OutputFormat::Webp));
+            assert!(!webp
+                .iter()
+                .any(|chunk| [b"EXIF", b"ICCP", b"XMP "].contains(&chunk)));
+        }
+        // The same input does carry it all when it is kept.
+        let kept = Metadata::from_bytes(&tagged, "photo.jpg", MetadataPolicy::Keep);
+        let jpeg = jpeg_markers(&written(&canvas, &kept, OutputFormat::Jpeg));
+        assert!(jpeg.contains(&0xE1) && jpeg.contains(&0xE2), "{:x?}", jpeg);
+        let png = png_chunks(&written(&canvas, &kept, OutputFormat::Png));
+        assert!([b"eXIf", b"iCCP", b"iTXt"]
+            .iter()
+            .all(|kind| png.contains(kind)));
+        let webp = webp_chunks(&written(&canvas, &kept, OutputFormat::Webp));
+        assert!([b"EXIF", b"ICCP", b"XMP "]
+            .iter()
+            .all(|kind| webp.contains(kind)));
+    }
+
+    #[test]
+    fn minimal_metadata_keeps_the_capture_time() {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut encoded = Vec::new();
+        canvas
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        let input = Metadata {
+            exif: Some(exif(&[
+                field(Tag::Make, ascii("Canon")),
+                field(Tag::DateTimeOriginal, ascii("2024:06:01 14:32:11")),
+            ])),
+            ..sample()
+        };
+        let input = embed_in_jpeg(encoded.clone(), &input);
+        let kept = Metadata::from_bytes(&input, "photo.jpg", MetadataPolicy::Minimal);
+        assert!(kept.xmp.is_none());
+        assert_eq!(kept.icc, sample().icc);
+        let output = embed_in_jpeg(encoded, &kept);
+        let exif = Reader::new()
+            .read_from_container(&mut Cursor::new(&output))
+            .expect("has EXIF");
+        let time = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).unwrap();
+        assert_eq!(time.display_value().to_string(), "2024-06-01 14:32:11");
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_none());
+    }
+
+    #[test]
+    fn stamped_outputs_name_this_tool_and_its_settings() {
+        let canvas = DynamicImage::from(image::RgbImage::new(32, 16));
+        let mut encoded = Vec::new();
+        canvas
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        let camera = exif(&[
+            field(Tag::Make, ascii("Canon")),
+            field(Tag::Software, ascii("Firmware 1.0")),
+        ]);
+        for input in [Some(camera), None] {
+            let had_camera = input.is_some();
+            let stamped = stamp_exif(input, "width=1080 height=1350");
+            let metadata = Metadata {
+                exif: stamped,
+                ..Metadata::default()
+            };
+            let jpeg = embed_in_jpeg(encoded.clone(), &metadata);
+            let exif = Reader::new()
+                .read_from_container(&mut Cursor::new(&jpeg))
+                .expect("has EXIF");
+            let fields = |tag| exif.fields().filter(|field| field.tag == tag).count();
+            assert_eq!(fields(Tag::Software), 1);
+            let software = exif.get_field(Tag::Software, In::PRIMARY).unwrap();
+            assert_eq!(
+                software.display_value().to_string(),
+                format!("\"{}\"", SOFTWARE)
+            );
+            let comment = exif.get_field(Tag::UserComment, In::PRIMARY).unwrap();
+            let Value::Undefined(comment, _) = &comment.value else {
+                panic!("UserComment is {:?}", comment.value);
+            };
+            assert_eq!(comment[..], *b"ASCII\0\0\0width=1080 height=1350");
+            assert_eq!(exif.get_field(Tag::Make, In::PRIMARY).is_some(), had_camera);
+        }
+    }
+
+    #[test]
+    fn stripping_the_location_keeps_the_camera() {
+        use exif::Rational;
+
+        let degrees =
+            |d| Value::Rational(vec![Rational::from((d, 1)), (0, 1).into(), (0, 1).into()]);
+        let xmp = concat!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description ",
+            "tiff:Make=\"Canon\" exif:GPSLatitude=\"48,51.4N\" exif:GPSLongitude=\"2,21.1E\">",
+            "<photoshop:City>Paris</photoshop:City><xmp:Rating>5</xmp:Rating>",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>",
+        );
+        let tagged = Metadata {
+            exif: Some(exif(&[
+                field(Tag::Make, ascii("Canon")),
+                field(Tag::Model, ascii("EOS R5")),
+                field(Tag::GPSLatitudeRef, ascii("N")),
+                field(Tag::GPSLatitude, degrees(48)),
+                field(Tag::GPSLongitudeRef, ascii("E")),
+                field(Tag::GPSLongitude, degrees(2)),
+            ])),
+            xmp: Some(xmp.as_bytes().to_vec()),
+            ..Metadata::default()
+        };
+        let parsed = Reader::new()
+            .read_raw(tagged.exif.clone().unwrap())
+            .unwrap();
+        assert!(parsed.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+
+        let stripped = tagged.without_location();
+        let exif = Reader::new().read_raw(stripped.exif.unwrap()).unwrap();
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+        assert!(exif
+            .fields()
+            .all(|field| field.tag.context() != exif::Context::Gps));
+        let xmp = String::from_utf8(stripped.xmp.unwrap()).unwrap();
+        assert_eq!(
+            xmp,
+            concat!(
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description ",
+                "tiff:Make=\"Canon\">",
+                "<xmp:Rating>5</xmp:Rating>",
+                "</rdf:Description></rdf:RDF></x:xmpmeta>",
+            )
+        );
+    }
+
+    #[test]
+    fn corrupt_exif_is_dropped_alone() {
+        let corrupt = Metadata {
+            exif: Some(b"MM\0*\0\0\0\x08\xff".to_vec()),
+            ..sample()
+        };
+        for policy in [MetadataPolicy::Keep, MetadataPolicy::Minimal] {
+            let kept = corrupt.clone().for_policy(policy, "photo.jpg");
+            assert!(kept.exif.is_none());
+            assert_eq!(kept.icc, corrupt.icc);
+        }
+    }
+
+    #[test]
+    fn checked_exif_sets_the_orientation_upright() {
+        let input = exif(&[
+            field(Tag::Make, ascii("Canon")),
+            field(Tag::Orientation, Value::Short(vec![6])),
+        ]);
+        let checked = checked_exif(input.clone()).unwrap();
+        let parsed = Reader::new().read_raw(checked.clone()).unwrap();
+        let orientation = parsed.get_field(Tag::Orientation, In::PRIMARY).unwrap();
+        assert_eq!(orientation.value.get_uint(0), Some(1));
+        // Only the value changed, in place.
+        assert_eq!(checked.len(), input.len());
+        let make = parsed.get_field(Tag::Make, In::PRIMARY).unwrap();
+        assert_eq!(make.display_value().to_string(), "\"Canon\"");
+    }
+
+    #[test]
+    fn kept_xmp_has_its_orientation_upright() {
+        let xmp = |attribute, element| {
+            format!(
+                concat!(
+                    "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF>",
+                    "<rdf:Description tiff:Make=\"Canon\" tiff:Orientation=\"{}\"/>",
+                    "<rdf:Description><tiff:Orientation>{}</tiff:Orientation></rdf:Description>",
+                    "</rdf:RDF></x:xmpmeta>",
+                ),
+                attribute, element
+            )
+        };
+        let tagged = Metadata {
+            xmp: Some(xmp(6, 8).into_bytes()),
+            ..Metadata::default()
+        };
+        let kept = tagged.for_policy(MetadataPolicy::Keep, "photo.jpg");
+        assert_eq!(String::from_utf8(kept.xmp.unwrap()).unwrap(), xmp(1, 1));
+    }
+
+    #[test]
+    fn checked_exif_drops_the_thumbnail() {
+        let thumbnail = b"\xff\xd8\xff\xdbstale picture of the input\xff\xd9";
+        let mut writer = exif::experimental::Writer::new();
+        let make = field(Tag::Make, ascii("Canon"));
+        writer.push_field(&make);
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+        let mut input = Cursor::new(Vec::new());
+        writer.write(&mut input, false).unwrap();
+        let input = input.into_inner();
+        assert!(Reader::new()
+            .read_raw(input.clone())
+            .unwrap()
+            .fields()
+            .any(|f| f.ifd_num == In::THUMBNAIL));
+
+        let checked = checked_exif(input.clone()).unwrap();
+        let parsed = Reader::new().read_raw(checked.clone()).unwrap();
+        assert!(parsed.fields().all(|field| field.ifd_num == In::PRIMARY));
+        assert!(!checked
+            .windows(thumbnail.len())
+            .any(|window| window == thumbnail));
+        assert!(parsed.get_field(Tag::Make, In::PRIMARY).is_some());
+    }
+
+    #[test]
+    fn exif_dimensions_are_the_canvas() {
+        let input = exif(&[
+            field(Tag::ImageWidth, Value::Short(vec![4000])),
+            field(Tag::ImageLength, Value::Long(vec![3000])),
+            field(Tag::PixelXDimension, Value::Long(vec![4000])),
+            field(Tag::PixelYDimension, Value::Short(vec![3000])),
+        ]);
+        let metadata = Metadata {
+            exif: Some(input),
+            ..Metadata::default()
+        };
+        // Taller than a SHORT holds, so PixelYDimension has to become a LONG.
+        let canvas = DynamicImage::new_luma8(8, 70000);
+        let output = metadata.for_canvas(&canvas).exif.unwrap();
+        let parsed = Reader::new().read_raw(output).unwrap();
+        let value = |tag| {
+            parsed
+                .get_field(tag, In::PRIMARY)
+                .unwrap()
+                .value
+                .get_uint(0)
+        };
+        assert_eq!(value(Tag::ImageWidth), Some(8));
+        assert_eq!(value(Tag::ImageLength), Some(70000));
+        assert_eq!(value(Tag::PixelXDimension), Some(8));
+        assert_eq!(value(Tag::PixelYDimension), Some(70000));
+    }
+}
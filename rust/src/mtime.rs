@@ -0,0 +1,24 @@
+//! `--preserve-mtime` and `--mtime-from-exif`: outputs dated like their images, for
+//! software that sorts by modification time.
+//!
+//! The time is set once the output is in place, so nothing written afterwards replaces it.
+//! Not every filesystem takes one (FAT stores 2-second steps, some network shares refuse),
+//! which is why failing to set it is only a warning.
+
+use crate::capture_date::capture_time;
+use filetime::FileTime;
+use std::io;
+use std::path::Path;
+
+/// Sets the modification time of `output` to that of `input`, or with `from_exif` to when
+/// `input` was taken, falling back to its modification time if it has no EXIF date.
+pub fn copy_time(input: &Path, output: &Path, from_exif: bool) -> io::Result<()> {
+    let time = if from_exif {
+        let (secs, _) = capture_time(input)
+            .ok_or_else(|| io::Error::other("the image has no capture or modification time"))?;
+        FileTime::from_unix_time(secs, 0)
+    } else {
+        FileTime::from_last_modification_time(&std::fs::metadata(input)?)
+    };
+    filetime::set_file_mtime(output, time)
+}
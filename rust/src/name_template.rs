@@ -0,0 +1,226 @@
+//! `--name-template`: output file names built from placeholders.
+//!
+//! A template like `{date}_{width}x{height}_{stem}` is parsed once at startup, so an unknown
+//! placeholder fails before anything is processed. The output extension is appended unless
+//! the template places `{ext}` itself.
+
+use crate::Orientation;
+use clap::ValueEnum;
+use std::fmt;
+
+/// A value a template can refer to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// Input file name without its extension.
+    Stem,
+    /// Output extension.
+    Ext,
+    /// Output canvas width.
+    Width,
+    /// Output canvas height.
+    Height,
+    /// Capture date from EXIF, or the modification date, as `2024-06-01`.
+    Date,
+    /// `landscape`, `portrait` or `square`.
+    Orientation,
+    /// 1-based position of the image in the run.
+    N,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("stem", Field::Stem),
+    ("ext", Field::Ext),
+    ("width", Field::Width),
+    ("height", Field::Height),
+    ("date", Field::Date),
+    ("orientation", Field::Orientation),
+    ("n", Field::N),
+];
+
+/// Written for `{date}` and `{orientation}` when they can't be determined.
+const UNKNOWN_DATE: &str = "undated";
+const UNKNOWN_ORIENTATION: &str = "unknown";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Clone, Debug)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+/// The values one output name is rendered from.
+pub struct NameValues<'a> {
+    pub stem: &'a str,
+    pub ext: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub date: Option<String>,
+    pub orientation: Option<Orientation>,
+    pub n: usize,
+}
+
+/// Parses and validates a `--name-template` value.
+pub fn parse_name_template(s: &str) -> Result<NameTemplate, String> {
+    let known = || {
+        let names: Vec<String> = FIELDS
+            .iter()
+            .map(|(name, _)| format!("{{{}}}", name))
+            .collect();
+        names.join(", ")
+    };
+    let mut parts = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let literal_end = rest.find(['{', '}']).unwrap_or(rest.len());
+        if literal_end > 0 {
+            parts.push(Part::Literal(rest[..literal_end].to_string()));
+            rest = &rest[literal_end..];
+            continue;
+        }
+        if rest.starts_with('}') {
+            return Err("unmatched '}' in name template".to_string());
+        }
+        let close = rest.find('}').ok_or("unclosed '{' in name template")?;
+        let name = &rest[1..close];
+        let field = FIELDS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|&(_, field)| field)
+            .ok_or_else(|| format!("unknown placeholder {{{}}} (expected {})", name, known()))?;
+        parts.push(Part::Field(field));
+        rest = &rest[close + 1..];
+    }
+
+    let has_stem_like = parts
+        .iter()
+        .any(|p| matches!(p, Part::Field(Field::Stem | Field::N | Field::Date)));
+    if !has_stem_like {
+        return Err(
+            "name template needs {stem}, {n} or {date}, or every output gets the same name"
+                .to_string(),
+        );
+    }
+    if parts
+        .iter()
+        .any(|p| matches!(p, Part::Literal(l) if l.contains(['/', '\\'])))
+    {
+        return Err("name template can't contain path separators".to_string());
+    }
+    if !parts.contains(&Part::Field(Field::Ext)) {
+        parts.push(Part::Literal(".".to_string()));
+        parts.push(Part::Field(Field::Ext));
+    }
+    Ok(NameTemplate { parts })
+}
+
+impl NameTemplate {
+    /// Returns true if rendering needs `field`, so callers only look up costly values
+    /// (the date, the orientation) when they're used.
+    pub fn uses(&self, field: Field) -> bool {
+        self.parts.contains(&Part::Field(field))
+    }
+
+    pub fn render(&self, values: &NameValues) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Field(Field::Stem) => name.push_str(values.stem),
+                Part::Field(Field::Ext) => name.push_str(values.ext),
+                Part::Field(Field::Width) => name.push_str(&values.width.to_string()),
+                Part::Field(Field::Height) => name.push_str(&values.height.to_string()),
+                Part::Field(Field::Date) => {
+                    name.push_str(values.date.as_deref().unwrap_or(UNKNOWN_DATE))
+                }
+                Part::Field(Field::Orientation) => {
+                    let orientation = values.orientation.and_then(|o| o.to_possible_value());
+                    name.push_str(
+                        orientation
+                            .as_ref()
+                            .map_or(UNKNOWN_ORIENTATION, |v| v.get_name()),
+                    )
+                }
+                Part::Field(Field::N) => name.push_str(&values.n.to_string()),
+            }
+        }
+        name
+    }
+
+    /// Returns true if `file_name` could have been rendered from this template, which is how
+    /// earlier outputs are recognised.
+    ///
+    /// Numbers must be digits, dates and orientations one of their possible values, and the
+    /// stem and extension anything non-empty. A template of just `{stem}` and `{ext}` keeps
+    /// input names as they are and so matches nothing.
+    pub fn matches(&self, file_name: &str) -> bool {
+        let distinctive = self.parts.iter().any(|part| match part {
+            Part::Literal(literal) => literal != ".",
+            Part::Field(field) => !matches!(field, Field::Stem | Field::Ext),
+        });
+        distinctive && matches_from(&self.parts, file_name)
+    }
+}
+
+fn matches_from(parts: &[Part], text: &str) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return text.is_empty();
+    };
+    match part {
+        Part::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| matches_from(rest, text)),
+        Part::Field(field) => {
+            // Try every split point; names are short, so backtracking is cheap.
+            (1..=text.len())
+                .filter(|&end| text.is_char_boundary(end))
+                .any(|end| field_accepts(*field, &text[..end]) && matches_from(rest, &text[end..]))
+        }
+    }
+}
+
+fn field_accepts(field: Field, value: &str) -> bool {
+    match field {
+        Field::Stem | Field::Ext => true,
+        Field::Width | Field::Height | Field::N => value.bytes().all(|b| b.is_ascii_digit()),
+        Field::Date => {
+            value == UNKNOWN_DATE
+                || (value.len() == 10
+                    && value.bytes().enumerate().all(|(i, b)| {
+                        if i == 4 || i == 7 {
+                            b == b'-'
+                        } else {
+                            b.is_ascii_digit()
+                        }
+                    }))
+        }
+        Field::Orientation => {
+            value == UNKNOWN_ORIENTATION
+                || Orientation::value_variants()
+                    .iter()
+                    .filter_map(|o| o.to_possible_value())
+                    .any(|v| v.get_name() == value)
+        }
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => write!(f, "{}", literal)?,
+                Part::Field(field) => {
+                    let name = FIELDS
+                        .iter()
+                        .find(|(_, known)| known == field)
+                        .map(|(n, _)| *n);
+                    write!(f, "{{{}}}", name.unwrap_or_default())?
+                }
+            }
+        }
+        Ok(())
+    }
+}
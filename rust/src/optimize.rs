@@ -0,0 +1,49 @@
+//! `--optimize-png`: a lossless oxipng pass over encoded PNG outputs, with the `oxipng`
+//! feature.
+//!
+//! zlib alone does well on the flat border, but oxipng also tries other filters, colour types
+//! and deflate strategies, and keeps whatever comes out smallest.
+
+use crate::output::OutputFormat;
+use crate::stats::Stages;
+use crate::Config;
+use std::error::Error;
+
+/// Explains why `--optimize-png` can't be used by this build, if it can't.
+pub fn unavailable() -> Option<&'static str> {
+    if cfg!(feature = "oxipng") {
+        None
+    } else {
+        Some("PNG optimization not compiled in, rebuild with --features oxipng")
+    }
+}
+
+/// Returns true if an output in `format` gets the oxipng pass; only PNGs do.
+pub fn applies(format: OutputFormat, config: &Config) -> bool {
+    config.optimize_png && format == OutputFormat::Png
+}
+
+/// Runs the oxipng pass over `encoded` if it applies to `format`, timing it as the
+/// optimize stage.
+pub fn optimize_output(
+    encoded: Vec<u8>,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !applies(format, config) {
+        return Ok(encoded);
+    }
+    Stages::time(&mut stages.optimize, || optimize_png(&encoded, config))
+}
+
+#[cfg(feature = "oxipng")]
+fn optimize_png(encoded: &[u8], config: &Config) -> Result<Vec<u8>, Box<dyn Error>> {
+    let options = oxipng::Options::from_preset(config.optimize_level);
+    Ok(oxipng::optimize_from_memory(encoded, &options)?)
+}
+
+#[cfg(not(feature = "oxipng"))]
+fn optimize_png(_encoded: &[u8], _config: &Config) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err(unavailable().unwrap_or_default().into())
+}
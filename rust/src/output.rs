@@ -0,0 +1,564 @@
+//! Output naming and placement.
+//!
+//! Discovery uses the same rules as the writer to recognise files that are already outputs,
+//! so the two can never disagree about what a bordered file is called.
+
+use crate::animation::{is_animated, AnimatedMode};
+use crate::capture_date::{capture_time, format_date};
+use crate::decode::header_dimensions;
+use crate::discover::Job;
+use crate::name_template::{Field, NameValues};
+use crate::{Config, Orientation};
+use clap::ValueEnum;
+use image::ColorType;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Returns the folder outputs for images under `root` are written to.
+pub fn output_root(root: &Path, config: &Config) -> PathBuf {
+    if config.output_zip.is_some() {
+        // Paths below the root are entry names of the archive.
+        PathBuf::new()
+    } else if config.in_place.is_some() {
+        root.to_path_buf()
+    } else if let Some(dir) = &config.output_dir {
+        dir.clone()
+    } else if config.separate_folder {
+        root.join("bordered_images")
+    } else {
+        root.to_path_buf()
+    }
+}
+
+/// Encoding used for an output file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[value(name = "jpg", alias = "jpeg")]
+    Jpeg,
+    Png,
+    /// WebP, lossy unless `--webp-lossless`.
+    Webp,
+    /// AVIF, only with the `avif-encode` feature.
+    Avif,
+    /// TIFF, compressed as `--tiff-compression` says.
+    Tiff,
+    /// Animated GIF, only used for animations kept with `--animated keep`.
+    #[value(skip)]
+    Gif,
+}
+
+impl OutputFormat {
+    /// Picks the format for a file extension: PNG stays PNG, everything else is written as JPEG.
+    pub fn for_extension(ext: &str) -> Self {
+        if ext.eq_ignore_ascii_case("png") {
+            OutputFormat::Png
+        } else {
+            OutputFormat::Jpeg
+        }
+    }
+
+    /// Canonical extension used when an input's own extension doesn't fit the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+            OutputFormat::Tiff => "tif",
+            OutputFormat::Gif => "gif",
+        }
+    }
+
+    /// Returns true if the format can store transparency in its pixels. Other formats get
+    /// translucent pixels blended onto the border.
+    pub fn keeps_alpha(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Png | OutputFormat::Webp | OutputFormat::Avif | OutputFormat::Tiff
+        )
+    }
+
+    /// Explains why the format can't be written by this build, if it can't.
+    pub fn unavailable(self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Avif if !cfg!(feature = "avif-encode") => {
+                Some("AVIF output not compiled in, rebuild with --features avif-encode")
+            }
+            _ => None,
+        }
+    }
+
+    /// Conventional extensions for this format, in lower case.
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            OutputFormat::Jpeg => &["jpg", "jpeg", "jpe", "jfif"],
+            OutputFormat::Png => &["png"],
+            OutputFormat::Webp => &["webp"],
+            OutputFormat::Avif => &["avif"],
+            OutputFormat::Tiff => &["tif", "tiff"],
+            OutputFormat::Gif => &["gif"],
+        }
+    }
+
+    /// Returns true if `ext` is a conventional extension for this format.
+    fn accepts_extension(self, ext: &str) -> bool {
+        self.extensions()
+            .iter()
+            .any(|accepted| accepted.eq_ignore_ascii_case(ext))
+    }
+}
+
+/// `--normalize-extension`: how output extensions are spelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NormalizeExtension {
+    /// The input's extension in lower case, e.g. `.JPEG` becomes `.jpeg`.
+    Lower,
+    /// One extension per format, e.g. `.JPEG` and `.jpe` become `.jpg`.
+    Canonical,
+}
+
+/// `--format`: an output encoding for every image, or `auto` to follow each input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FormatChoice {
+    /// PNG inputs stay PNG, everything else becomes JPEG.
+    Auto,
+    #[value(name = "jpg", alias = "jpeg")]
+    Jpeg,
+    Png,
+    /// WebP, lossy unless `--webp-lossless`.
+    Webp,
+    /// AVIF, only with the `avif-encode` feature.
+    Avif,
+    /// TIFF, compressed as `--tiff-compression` says.
+    Tiff,
+}
+
+impl FormatChoice {
+    /// The encoding every output is forced to, `None` for `auto`.
+    pub fn forced(self) -> Option<OutputFormat> {
+        match self {
+            FormatChoice::Auto => None,
+            FormatChoice::Jpeg => Some(OutputFormat::Jpeg),
+            FormatChoice::Png => Some(OutputFormat::Png),
+            FormatChoice::Webp => Some(OutputFormat::Webp),
+            FormatChoice::Avif => Some(OutputFormat::Avif),
+            FormatChoice::Tiff => Some(OutputFormat::Tiff),
+        }
+    }
+}
+
+/// Compression of TIFF outputs. All of them are lossless.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TiffCompression {
+    Lzw,
+    Deflate,
+    /// Largest files, but readable by anything that reads TIFF.
+    None,
+}
+
+impl From<TiffCompression> for tiff::encoder::Compression {
+    fn from(compression: TiffCompression) -> Self {
+        match compression {
+            TiffCompression::Lzw => tiff::encoder::Compression::Lzw,
+            TiffCompression::Deflate => {
+                tiff::encoder::Compression::Deflate(tiff::encoder::DeflateLevel::Balanced)
+            }
+            TiffCompression::None => tiff::encoder::Compression::Uncompressed,
+        }
+    }
+}
+
+/// zlib effort for PNG outputs. All levels are lossless.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PngCompression {
+    /// Quickest to write, largest files.
+    Fast,
+    Default,
+    /// Slowest, smallest files.
+    Best,
+}
+
+impl From<PngCompression> for image::codecs::png::CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => image::codecs::png::CompressionType::Fast,
+            PngCompression::Default => image::codecs::png::CompressionType::Default,
+            PngCompression::Best => image::codecs::png::CompressionType::Best,
+        }
+    }
+}
+
+/// Row filter for PNG outputs, applied before compressing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the best filter for each row.
+    Adaptive,
+}
+
+impl From<PngFilter> for image::codecs::png::FilterType {
+    fn from(filter: PngFilter) -> Self {
+        match filter {
+            PngFilter::None => image::codecs::png::FilterType::NoFilter,
+            PngFilter::Sub => image::codecs::png::FilterType::Sub,
+            PngFilter::Up => image::codecs::png::FilterType::Up,
+            PngFilter::Avg => image::codecs::png::FilterType::Avg,
+            PngFilter::Paeth => image::codecs::png::FilterType::Paeth,
+            PngFilter::Adaptive => image::codecs::png::FilterType::Adaptive,
+        }
+    }
+}
+
+/// Sample depth of the written pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BitDepth {
+    /// 16 bits per channel if the source has more than 8 and the output format allows it.
+    Keep,
+    /// Always 8 bits per channel.
+    #[value(name = "8")]
+    Eight,
+    /// 16 bits per channel whenever the output format allows it.
+    #[value(name = "16")]
+    Sixteen,
+}
+
+impl BitDepth {
+    /// Returns true if a `source` image written as `format` should stay 16 bits per channel.
+    ///
+    /// Only PNG and TIFF outputs can hold 16-bit samples; the other formats are always
+    /// narrowed to 8.
+    pub fn wants_16bit(self, source: ColorType, format: OutputFormat) -> bool {
+        if !matches!(format, OutputFormat::Png | OutputFormat::Tiff) {
+            return false;
+        }
+        match self {
+            BitDepth::Keep => source.bytes_per_pixel() > source.channel_count(),
+            BitDepth::Eight => false,
+            BitDepth::Sixteen => true,
+        }
+    }
+}
+
+/// Picks the output format for an input file.
+pub fn output_format(input: &Path, config: &Config) -> OutputFormat {
+    if let Some(format) = config.format {
+        return format;
+    }
+    if config.animated == AnimatedMode::Keep && is_animated(input) {
+        return OutputFormat::Gif;
+    }
+    let ext = input.extension().and_then(|e| e.to_str()).unwrap_or("");
+    OutputFormat::for_extension(ext)
+}
+
+/// What an output file name is built from.
+pub struct NameSource<'a> {
+    /// File name of the input, e.g. `IMG_0001.jpg`.
+    pub file_name: &'a str,
+    /// The input on disk, read for `{date}` and `{orientation}`; `None` for archive entries
+    /// and URLs.
+    pub path: Option<&'a Path>,
+    /// 1-based position of the input in the run, for `{n}`.
+    pub seq: usize,
+}
+
+/// `--number`: the sequence number put before each output name, e.g. `001_bordered_a.jpg`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    /// Number of the first input.
+    pub start: usize,
+    /// Digits numbers are zero-padded to.
+    pub width: usize,
+}
+
+impl Sequence {
+    /// Fewest digits numbers are padded to, so small runs still sort as `001`, `002`, ...
+    const MIN_WIDTH: usize = 3;
+
+    pub fn new(start: usize) -> Self {
+        Self {
+            start,
+            width: Self::MIN_WIDTH,
+        }
+    }
+
+    /// Pads to the digits of the last number a run of `count` inputs reaches.
+    pub fn for_count(self, count: usize) -> Self {
+        let last = self.start + count.saturating_sub(1);
+        Self {
+            width: last.to_string().len().max(Self::MIN_WIDTH),
+            ..self
+        }
+    }
+
+    /// The number of the `seq`th input (1-based), with its separator.
+    fn prefix(self, seq: usize) -> String {
+        format!(
+            "{:0width$}_",
+            self.start + seq.saturating_sub(1),
+            width = self.width
+        )
+    }
+}
+
+/// Builds the output file name for an input, numbered with `--number`.
+pub fn output_file_name(source: &NameSource, format: OutputFormat, config: &Config) -> String {
+    let name = unnumbered_file_name(source, format, config);
+    match config.number {
+        Some(sequence) => sequence.prefix(source.seq) + &name,
+        None => name,
+    }
+}
+
+/// Builds the output file name for an input.
+///
+/// The prefix goes before the name and the suffix before the extension, unless a
+/// `--name-template` is set, or `--rename-by-date` and the input has a date; inputs without
+/// one (archive entries, URLs) keep the usual name. The input's extension is kept when it
+/// suits the output format (so `photo.JPEG` stays `.JPEG`), and replaced otherwise, e.g.
+/// `photo.webp` becomes `bordered_photo.jpg`.
+fn unnumbered_file_name(source: &NameSource, format: OutputFormat, config: &Config) -> String {
+    let path = Path::new(source.file_name);
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(source.file_name);
+    let ext = if format.accepts_extension(ext) {
+        ext
+    } else {
+        format.extension()
+    };
+    let ext = match config.normalize_extension {
+        Some(NormalizeExtension::Lower) => ext.to_lowercase(),
+        Some(NormalizeExtension::Canonical) => format.extension().to_string(),
+        None => ext.to_string(),
+    };
+    let ext = ext.as_str();
+    if let Some(pattern) = &config.rename_by_date {
+        if let Some((time, _)) = source.path.and_then(capture_time) {
+            return format!("{}.{}", pattern.render(time), ext);
+        }
+    }
+    let Some(template) = &config.name_template else {
+        return format!("{}{}{}.{}", config.prefix, stem, config.suffix, ext);
+    };
+    let date = source
+        .path
+        .filter(|_| template.uses(Field::Date))
+        .and_then(capture_time)
+        .map(|(time, _)| format_date(time));
+    let orientation = source
+        .path
+        .filter(|_| template.uses(Field::Orientation))
+        .and_then(|path| header_dimensions(path, config))
+        .map(|(width, height)| Orientation::of(width, height, config));
+    template.render(&NameValues {
+        stem,
+        ext,
+        width: config.target_width,
+        height: config.target_height,
+        date,
+        orientation,
+        n: source.seq,
+    })
+}
+
+/// Returns true if `file_name` looks like something we wrote ourselves: it fits the name
+/// template or the date pattern, or has both the prefix and, just before the extension, the
+/// suffix. The thumbnails of such files count too, and with `--number`, either behind a
+/// sequence number.
+pub fn is_output_name(file_name: &str, config: &Config) -> bool {
+    if let Some(output) = file_name.strip_prefix(THUMBNAIL_PREFIX) {
+        if is_output_name(output, config) {
+            return true;
+        }
+    }
+    if config.number.is_some() {
+        let digits = file_name.bytes().take_while(u8::is_ascii_digit).count();
+        let unnumbered = file_name[digits..].strip_prefix('_').filter(|_| digits > 0);
+        if unnumbered.is_some_and(|unnumbered| is_output_name(unnumbered, config)) {
+            return true;
+        }
+    }
+    if let Some(template) = &config.name_template {
+        return template.matches(file_name);
+    }
+    let stem = Path::new(file_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(file_name);
+    if config
+        .rename_by_date
+        .as_ref()
+        .is_some_and(|pattern| pattern.matches(stem))
+    {
+        return true;
+    }
+    if config.prefix.is_empty() && config.suffix.is_empty() {
+        return false;
+    }
+    file_name.starts_with(&config.prefix) && stem.ends_with(&config.suffix)
+}
+
+/// Returns the full output path for a job, mirroring its location below the job's root
+/// unless `--flatten` is set. With `--in-place` it is the image itself.
+pub fn output_path(job: &Job, seq: usize, format: OutputFormat, config: &Config) -> PathBuf {
+    if config.in_place.is_some() {
+        return job.path.clone();
+    }
+    let rel = job.path.strip_prefix(&job.root).unwrap_or(&job.path);
+    let file_name = job.path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let mut output_folder = output_root(&job.root, config);
+    if let Some(folder) = size_folder(config) {
+        output_folder.push(folder);
+    }
+    let output_dir = match rel.parent() {
+        Some(parent) if !config.flatten => output_folder.join(parent),
+        _ => output_folder,
+    };
+    let source = NameSource {
+        file_name,
+        path: job.url.is_none().then_some(job.path.as_path()),
+        seq,
+    };
+    output_dir.join(output_file_name(&source, format, config))
+}
+
+/// Prefix of the `--thumbnail` written next to an output.
+const THUMBNAIL_PREFIX: &str = "thumb_";
+
+/// Returns where the `--thumbnail` of `output` goes, e.g. `thumb_bordered_photo.jpg`.
+pub fn thumbnail_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}{}", THUMBNAIL_PREFIX, name))
+}
+
+/// Subfolder of the outputs at one `--size`, e.g. `1080x1350`, placed right below the output
+/// folder. `None` without `--size`, or when the name template tells the sizes apart itself.
+pub fn size_folder(config: &Config) -> Option<String> {
+    let template_has_size = config
+        .name_template
+        .as_ref()
+        .is_some_and(|template| template.uses(Field::Width) && template.uses(Field::Height));
+    if config.sizes.is_empty() || template_has_size {
+        return None;
+    }
+    Some(format!("{}x{}", config.target_width, config.target_height))
+}
+
+/// Returns the output already at `path`, if there is one. With `--normalize-extension` that
+/// includes an output spelled with another extension of `format` in either case, e.g.
+/// `bordered_photo.JPG` for `bordered_photo.jpg`; case-sensitive filesystems would
+/// otherwise keep both.
+pub fn existing_output(path: &Path, format: OutputFormat, config: &Config) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+    config.normalize_extension?;
+    format
+        .extensions()
+        .iter()
+        .flat_map(|ext| [ext.to_string(), ext.to_uppercase()])
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.exists())
+}
+
+/// Key under which output paths are compared, folded to lower case so `Photo.jpg` and
+/// `photo.jpg` count as the same file, as they are on case-insensitive filesystems.
+pub fn collision_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// How `unclaimed_path` numbers the names after the first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Numbering {
+    /// `name_2.ext`, `name_3.ext`, ...
+    Underscore,
+    /// `name-1.ext`, `name-2.ext`, ..., for names that are dates (`--rename-by-date`).
+    Dash,
+}
+
+/// Returns `path`, or if it is already in `taken`, the first free numbered name next to it.
+/// The returned path is added to `taken`.
+pub fn unclaimed_path(path: PathBuf, taken: &mut HashSet<String>, numbering: Numbering) -> PathBuf {
+    let mut candidate = path.clone();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| e.to_string_lossy());
+    let (separator, mut n) = match numbering {
+        Numbering::Underscore => ('_', 2),
+        Numbering::Dash => ('-', 1),
+    };
+    while taken.contains(&collision_key(&candidate)) {
+        let name = match &ext {
+            Some(ext) => format!("{}{}{}.{}", stem, separator, n, ext),
+            None => format!("{}{}{}", stem, separator, n),
+        };
+        candidate = path.with_file_name(name);
+        n += 1;
+    }
+    taken.insert(collision_key(&candidate));
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::config;
+
+    fn name(file_name: &str, config: &Config) -> String {
+        let source = NameSource {
+            file_name,
+            path: None,
+            seq: 1,
+        };
+        output_file_name(&source, OutputFormat::Jpeg, config)
+    }
+
+    #[test]
+    fn suffixes_go_before_the_kept_extension() {
+        let suffixed = config(&["--prefix", "", "--suffix", "_insta"]);
+        assert_eq!(name("photo.jpeg", &suffixed), "photo_insta.jpeg");
+        assert_eq!(name("IMG_0001.JPG", &suffixed), "IMG_0001_insta.JPG");
+        assert_eq!(name("photo.webp", &suffixed), "photo_insta.jpg");
+        let both = config(&["--suffix", "_insta"]);
+        assert_eq!(name("photo.jpeg", &both), "bordered_photo_insta.jpeg");
+    }
+
+    #[test]
+    fn suffixed_names_are_known_as_outputs() {
+        let suffixed = config(&["--prefix", "", "--suffix", "_insta"]);
+        assert!(is_output_name("photo_insta.jpeg", &suffixed));
+        assert!(is_output_name("thumb_photo_insta.jpeg", &suffixed));
+        assert!(!is_output_name("photo.jpeg", &suffixed));
+        assert!(!is_output_name("photo_insta_raw.jpeg", &suffixed));
+        let both = config(&["--suffix", "_insta"]);
+        assert!(is_output_name("bordered_photo_insta.jpeg", &both));
+        assert!(!is_output_name("bordered_photo.jpeg", &both));
+        assert!(!is_output_name("photo_insta.jpeg", &both));
+    }
+
+    #[test]
+    fn a_suffix_alone_keeps_outputs_off_their_inputs() {
+        let job = Job {
+            input: 0,
+            path: PathBuf::from("shoot/photo.jpeg"),
+            root: PathBuf::from("shoot"),
+            url: None,
+        };
+        let beside = ["--separate-folder=false", "--prefix", ""];
+        let suffixed = config(&[&beside[..], &["--suffix", "_insta"]].concat());
+        let output = output_path(&job, 1, OutputFormat::Jpeg, &suffixed);
+        assert_eq!(output, Path::new("shoot/photo_insta.jpeg"));
+        assert_ne!(collision_key(&output), collision_key(&job.path));
+        // Neither a prefix nor a suffix: the output is the input, which processing refuses.
+        assert_eq!(
+            output_path(&job, 1, OutputFormat::Jpeg, &config(&beside)),
+            job.path
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! `--output-zip`: every output written as an entry of one archive instead of a loose file.
+//!
+//! Entries are added as each image is encoded, so only one image is held in memory at a
+//! time. The archive is an `AtomicFile`: it only appears under its name once the central
+//! directory is written, and an interrupted run leaves nothing that looks complete.
+
+use crate::archive::entry_name;
+use crate::atomic::AtomicFile;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+pub struct OutputZip {
+    writer: ZipWriter<AtomicFile>,
+    path: PathBuf,
+    /// Size of each entry written, by its name.
+    sizes: HashMap<String, u64>,
+}
+
+impl OutputZip {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            writer: ZipWriter::new(AtomicFile::create(path)?),
+            path: path.to_path_buf(),
+            sizes: HashMap::new(),
+        })
+    }
+
+    /// Adds `data` as the entry at the relative output path `name`.
+    pub fn add(&mut self, name: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        // Outputs are already compressed images; deflate wouldn't gain anything.
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let name = entry_name(name);
+        self.writer.start_file(name.as_str(), options)?;
+        self.writer.write_all(data)?;
+        self.sizes.insert(name, data.len() as u64);
+        Ok(())
+    }
+
+    /// Size of the entry written at `name`, if there is one.
+    pub fn entry_size(&self, name: &Path) -> Option<u64> {
+        self.sizes.get(&entry_name(name)).copied()
+    }
+
+    /// Writes the central directory and moves the archive into place, returning its path
+    /// and size.
+    pub fn finish(self) -> Result<(PathBuf, u64), Box<dyn Error>> {
+        self.writer.finish()?.commit()?;
+        let size = std::fs::metadata(&self.path)?.len();
+        Ok((self.path, size))
+    }
+}
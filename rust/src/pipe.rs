@@ -0,0 +1,80 @@
+//! `--pipe`: read one image from standard input and write the result to standard output.
+//!
+//! Standard output carries only the encoded image, so every message goes to standard error.
+
+use crate::border_color;
+use crate::caption::CaptionSource;
+use crate::decode::decode_bytes;
+use crate::metadata::Metadata;
+use crate::output::OutputFormat;
+use crate::size_limit;
+use crate::stats::Stages;
+use crate::{compose_image, encode_output, fit_note, format_size, Config};
+use std::io::{ErrorKind, Read, Write};
+use std::time::Instant;
+
+/// Exit status when the reader of standard output goes away, as if killed by SIGPIPE.
+const BROKEN_PIPE_EXIT: i32 = 141;
+
+/// Processes standard input into standard output and exits the process.
+pub fn run(format: OutputFormat, config: &Config) -> ! {
+    let start = Instant::now();
+    match process(format, config) {
+        Ok(size) => {
+            eprintln!(
+                "✅ Successfully processed <stdin> in {:.2} seconds ({})",
+                start.elapsed().as_secs_f64(),
+                format_size(size as u64)
+            );
+            std::process::exit(0);
+        }
+        Err(e) if is_broken_pipe(e.as_ref()) => std::process::exit(BROKEN_PIPE_EXIT),
+        Err(e) => {
+            eprintln!("❌ Error processing <stdin>: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the size of the written image.
+fn process(format: OutputFormat, config: &Config) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+    if input.is_empty() {
+        return Err("no image data on standard input".into());
+    }
+    let img = decode_bytes(&input, config)?;
+    let metadata = Metadata::from_bytes(&input, "standard input", config.metadata);
+    let picked = config
+        .auto_color
+        .and_then(|mode| border_color::pick(&img, mode));
+    if let Some(color) = picked {
+        eprintln!("🎨 Border color for <stdin>: {}", color);
+    }
+    let size = config.class_size(img.width(), img.height());
+    let config = config.padded(Some((img.width(), img.height())));
+    let config = config.at_size(size);
+    let config = config.with_border_color(picked);
+    let config = config.with_crop_focus(&img);
+    let config = config.with_caption("stdin", CaptionSource::Bytes(&input));
+    let config = config.as_ref();
+    if let Some(note) = fit_note("<stdin>", img.width(), img.height(), config) {
+        eprintln!("{}", note);
+    }
+    let canvas = compose_image(&img, format, config)?;
+
+    // Encode fully before writing, so a failed encode never leaves half an image behind.
+    let mut stages = Stages::default();
+    let encoded = encode_output(&canvas, &metadata, format, config, &mut stages)?;
+    size_limit::warn_if_over("<stdin>", encoded.len() as u64, config, &stages);
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&encoded)?;
+    stdout.flush()?;
+    Ok(encoded.len())
+}
+
+fn is_broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .map(|e| e.kind() == ErrorKind::BrokenPipe)
+        .unwrap_or(false)
+}
@@ -0,0 +1,375 @@
+//! The processing plan: the discovered worklist plus what can be learned about each job
+//! without decoding it.
+//!
+//! Planning reads only file metadata and image headers, so the totals are known before the
+//! first image is processed. It also settles every output path, so two inputs that would
+//! write to the same file are caught before either is written.
+
+use crate::atomic::AtomicFile;
+use crate::decode::header_dimensions;
+use crate::discover::{Job, Rejected, SkipReason, Skipped, Worklist};
+use crate::output::{collision_key, output_format, output_path, unclaimed_path, Numbering};
+use crate::sidecar::apply_sidecar;
+use crate::{format_size, Config, Orientation};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// What to do when several inputs would be written to the same output file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnCollision {
+    /// Keep the first name and number the others, e.g. `bordered_photo_2.jpg`.
+    Rename,
+    /// Stop before processing anything and list the collisions.
+    Error,
+    /// Process only the first input of each collision.
+    Skip,
+}
+
+/// One job of the plan.
+pub struct WorkItem {
+    pub job: Job,
+    /// Width and height from the header; `None` for URLs and files whose header can't be read.
+    pub dimensions: Option<(u32, u32)>,
+    /// Size of the input file on disk; `None` for URLs.
+    pub bytes: Option<u64>,
+    /// Where the outputs are written: one per `--size`, or just one.
+    pub outputs: Vec<PlannedOutput>,
+}
+
+/// One output file of a job.
+pub struct PlannedOutput {
+    /// Canvas size from `--size`; `None` for the single output at `--width`x`--height`.
+    pub size: Option<(u32, u32)>,
+    pub path: PathBuf,
+    /// The output the job would have had, when that was taken by an earlier job and
+    /// `path` is a renamed one.
+    pub renamed_from: Option<PathBuf>,
+}
+
+impl WorkItem {
+    pub fn orientation(&self, config: &Config) -> Option<Orientation> {
+        self.dimensions
+            .map(|(width, height)| Orientation::of(width, height, config))
+    }
+}
+
+/// Everything a run is going to do, in processing order.
+#[derive(Default)]
+pub struct Plan {
+    pub items: Vec<WorkItem>,
+    pub skipped: Vec<Skipped>,
+    pub rejected: Vec<Rejected>,
+}
+
+impl Plan {
+    /// Reads the header and size of every job of `worklist` and works out its output paths,
+    /// resolving collisions as `config.on_collision` says.
+    ///
+    /// Fails only for `--on-collision error`, after listing every collision on stderr.
+    pub fn new(worklist: Worklist, config: &Config) -> Result<Self, String> {
+        let items = worklist
+            .jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, job)| {
+                let (dimensions, bytes) = match job.url {
+                    Some(_) => (None, None),
+                    None => (
+                        header_dimensions(&job.path, config),
+                        std::fs::metadata(&job.path).map(|m| m.len()).ok(),
+                    ),
+                };
+                // A broken sidecar fails the job when it's processed; name it without.
+                let overridden = match job.url {
+                    Some(_) => None,
+                    None => apply_sidecar(&job.path, config).ok().flatten(),
+                };
+                let config = overridden.as_ref().unwrap_or(config);
+                let format = output_format(&job.path, config);
+                let outputs = config
+                    .output_sizes()
+                    .into_iter()
+                    .map(|size| {
+                        let class_size =
+                            dimensions.and_then(|(width, height)| config.class_size(width, height));
+                        size.or(class_size)
+                    })
+                    .map(|size| PlannedOutput {
+                        size,
+                        path: output_path(&job, index + 1, format, &config.at_size(size)),
+                        renamed_from: None,
+                    })
+                    .collect();
+                WorkItem {
+                    job,
+                    dimensions,
+                    bytes,
+                    outputs,
+                }
+            })
+            .collect();
+        let mut plan = Plan {
+            items,
+            skipped: worklist.skipped,
+            rejected: worklist.rejected,
+        };
+        let numbering = match config.rename_by_date {
+            Some(_) => Numbering::Dash,
+            None => Numbering::Underscore,
+        };
+        plan.resolve_collisions(config.on_collision, numbering)?;
+        Ok(plan)
+    }
+
+    /// Finds outputs sharing a path, compared case-insensitively, and renames all but the
+    /// first of each, or skips or reports their jobs.
+    fn resolve_collisions(
+        &mut self,
+        mode: OnCollision,
+        numbering: Numbering,
+    ) -> Result<(), String> {
+        // Outputs as (job, output) indices.
+        let mut first: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut collisions: Vec<((usize, usize), (usize, usize))> = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            for (output_index, output) in item.outputs.iter().enumerate() {
+                let at = (index, output_index);
+                match first.get(&collision_key(&output.path)) {
+                    Some(&earlier) => collisions.push((earlier, at)),
+                    None => {
+                        first.insert(collision_key(&output.path), at);
+                    }
+                }
+            }
+        }
+        if collisions.is_empty() {
+            return Ok(());
+        }
+
+        match mode {
+            OnCollision::Rename => {
+                let mut taken: HashSet<String> = first.into_keys().collect();
+                for (_, (index, output_index)) in collisions {
+                    let output = &mut self.items[index].outputs[output_index];
+                    let renamed = unclaimed_path(output.path.clone(), &mut taken, numbering);
+                    output.renamed_from = Some(std::mem::replace(&mut output.path, renamed));
+                }
+            }
+            OnCollision::Error => {
+                let mut groups: Vec<((usize, usize), Vec<usize>)> = Vec::new();
+                for (earlier, (index, _)) in collisions {
+                    match groups.iter_mut().find(|(first, _)| *first == earlier) {
+                        Some((_, others)) => others.push(index),
+                        None => groups.push((earlier, vec![index])),
+                    }
+                }
+                eprintln!("❌ Output name collisions:");
+                for ((earlier, output_index), others) in &groups {
+                    let inputs: Vec<String> = std::iter::once(earlier)
+                        .chain(others)
+                        .map(|&index| job_label(&self.items[index].job))
+                        .collect();
+                    let output = &self.items[*earlier].outputs[*output_index];
+                    eprintln!("  {}: {}", output.path.display(), inputs.join(", "));
+                }
+                let message =
+                    format!(
+                    "Error: {} output {} written by more than one input, nothing was processed \
+                     (use --on-collision rename or skip)",
+                    groups.len(),
+                    if groups.len() == 1 { "file would be" } else { "files would be" }
+                );
+                return Err(message);
+            }
+            OnCollision::Skip => {
+                // A job loses all its outputs if one of them collides.
+                let mut losers: Vec<(usize, usize)> = Vec::new();
+                for ((earlier, _), (index, _)) in collisions {
+                    if !losers.iter().any(|&(_, loser)| loser == index) {
+                        losers.push((earlier, index));
+                    }
+                }
+                for &(earlier, index) in &losers {
+                    let detail = format!("same output as {}", job_label(&self.items[earlier].job));
+                    let job = &self.items[index].job;
+                    self.skipped.push(Skipped {
+                        input: job.input,
+                        path: PathBuf::from(job_label(job)),
+                        reason: SkipReason::OutputCollision,
+                        detail: Some(detail),
+                    });
+                }
+                let losers: HashSet<usize> = losers.iter().map(|&(_, index)| index).collect();
+                let mut index = 0;
+                self.items.retain(|_| {
+                    index += 1;
+                    !losers.contains(&(index - 1))
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Total size on disk of the planned inputs whose size is known.
+    pub fn total_bytes(&self) -> u64 {
+        self.items.iter().filter_map(|item| item.bytes).sum()
+    }
+
+    /// Number of planned images of `orientation`.
+    pub fn count(&self, orientation: Orientation, config: &Config) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.orientation(config) == Some(orientation))
+            .count()
+    }
+
+    /// Writes `--flatten-map`: a CSV with a `source,output` row for every planned output.
+    pub fn write_map(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = AtomicFile::create(path)?;
+        writeln!(file, "source,output")?;
+        for item in &self.items {
+            for output in &item.outputs {
+                let source = job_label(&item.job);
+                let output = output.path.display().to_string();
+                writeln!(file, "{},{}", csv_field(&source), csv_field(&output))?;
+            }
+        }
+        file.commit()
+    }
+
+    /// Prints a one-line overview, e.g.
+    /// `📋 Plan: 12 images (8 landscape, 3 portrait, 1 square), 41.3 MB, 2 skipped`.
+    pub fn print(&self, config: &Config, out: &mut dyn Write) -> std::io::Result<()> {
+        let unknown = self
+            .items
+            .iter()
+            .filter(|item| item.dimensions.is_none())
+            .count();
+        let classes: Vec<String> = Orientation::value_variants()
+            .iter()
+            .map(|&orientation| {
+                let name = orientation.to_possible_value();
+                let name = name.map(|v| v.get_name().to_string()).unwrap_or_default();
+                (self.count(orientation, config), name)
+            })
+            .chain([(unknown, "unknown".to_string())])
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, class)| format!("{} {}", count, class))
+            .collect();
+        let noun = if self.items.len() == 1 {
+            "image"
+        } else {
+            "images"
+        };
+        write!(out, "📋 Plan: {} {}", self.items.len(), noun)?;
+        if !classes.is_empty() {
+            write!(out, " ({})", classes.join(", "))?;
+        }
+        let outputs: usize = self.items.iter().map(|item| item.outputs.len()).sum();
+        if outputs != self.items.len() {
+            write!(out, ", {} outputs", outputs)?;
+        }
+        write!(out, ", {}", format_size(self.total_bytes()))?;
+        if !self.skipped.is_empty() {
+            write!(out, ", {} skipped", self.skipped.len())?;
+        }
+        let renamed = self
+            .items
+            .iter()
+            .flat_map(|item| &item.outputs)
+            .filter(|output| output.renamed_from.is_some())
+            .count();
+        if renamed > 0 {
+            write!(out, ", {} renamed to avoid collisions", renamed)?;
+        }
+        if !self.rejected.is_empty() {
+            write!(out, ", {} rejected", self.rejected.len())?;
+        }
+        writeln!(out, "\n")
+    }
+}
+
+/// Quotes a CSV field if it holds a comma, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The URL of a URL job, or its path.
+fn job_label(job: &Job) -> String {
+    match &job.url {
+        Some(url) => url.clone(),
+        None => job.path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A plan of one input per output path, in order.
+    fn plan(outputs: &[&str]) -> Plan {
+        let items = outputs
+            .iter()
+            .enumerate()
+            .map(|(input, output)| WorkItem {
+                job: Job {
+                    input,
+                    path: PathBuf::from(format!("input_{}.jpg", input)),
+                    root: PathBuf::from("."),
+                    url: None,
+                },
+                dimensions: None,
+                bytes: None,
+                outputs: vec![PlannedOutput {
+                    size: None,
+                    path: PathBuf::from(output),
+                    renamed_from: None,
+                }],
+            })
+            .collect();
+        Plan {
+            items,
+            ..Plan::default()
+        }
+    }
+
+    #[test]
+    fn names_differing_only_in_case_collide() {
+        assert_eq!(
+            collision_key(Path::new("out/Photo.JPG")),
+            collision_key(Path::new("out/photo.jpg"))
+        );
+
+        let mut renamed = plan(&["out/Photo.JPG", "out/photo.jpg", "out/other.jpg"]);
+        renamed
+            .resolve_collisions(OnCollision::Rename, Numbering::Underscore)
+            .unwrap();
+        let output = &renamed.items[1].outputs[0];
+        assert_eq!(output.path, Path::new("out/photo_2.jpg"));
+        assert_eq!(
+            output.renamed_from.as_deref(),
+            Some(Path::new("out/photo.jpg"))
+        );
+        assert_eq!(renamed.items[0].outputs[0].path, Path::new("out/Photo.JPG"));
+        assert!(renamed.items[2].outputs[0].renamed_from.is_none());
+
+        let mut skipped = plan(&["out/Photo.JPG", "out/photo.jpg"]);
+        skipped
+            .resolve_collisions(OnCollision::Skip, Numbering::Underscore)
+            .unwrap();
+        assert_eq!(skipped.items.len(), 1);
+        assert_eq!(skipped.skipped[0].reason, SkipReason::OutputCollision);
+
+        let mut refused = plan(&["out/Photo.JPG", "out/photo.jpg"]);
+        assert!(refused
+            .resolve_collisions(OnCollision::Error, Numbering::Underscore)
+            .is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! `--preset`: named canvas sizes for social platforms, each with border ratios that suit it.
+//!
+//! A preset only sets defaults: `--width`, `--height` and the ratio flags still win over it.
+//! Its ratios apply to every orientation, so an image gets even borders on a canvas of the
+//! platform's shape whatever its own shape is. `--preset list` prints the table.
+
+/// A named canvas size and the border ratios that go with it.
+#[derive(Debug, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Top and bottom border, each, as a fraction of the canvas height.
+    pub vertical: f64,
+    /// Left and right border, each, as a fraction of the canvas width.
+    pub horizontal: f64,
+    pub description: &'static str,
+}
+
+/// The built-in presets, in the order `--preset list` prints them.
+pub const PRESETS: [Preset; 6] = [
+    Preset {
+        name: "ig-square",
+        width: 1080,
+        height: 1080,
+        vertical: 0.04,
+        horizontal: 0.04,
+        description: "Instagram feed post, 1:1",
+    },
+    Preset {
+        name: "ig-portrait",
+        width: 1080,
+        height: 1350,
+        vertical: 0.032,
+        horizontal: 0.04,
+        description: "Instagram feed post, 4:5",
+    },
+    Preset {
+        name: "ig-landscape",
+        width: 1080,
+        height: 566,
+        vertical: 0.076,
+        horizontal: 0.04,
+        description: "Instagram feed post, 1.91:1",
+    },
+    // Stories keep the top and bottom clear of the profile bar and the reply field.
+    Preset {
+        name: "ig-story",
+        width: 1080,
+        height: 1920,
+        vertical: 0.12,
+        horizontal: 0.04,
+        description: "Instagram or Facebook story, 9:16",
+    },
+    Preset {
+        name: "x-landscape",
+        width: 1600,
+        height: 900,
+        vertical: 0.05,
+        horizontal: 0.028,
+        description: "X (Twitter) post, 16:9",
+    },
+    Preset {
+        name: "fb-landscape",
+        width: 1200,
+        height: 630,
+        vertical: 0.06,
+        horizontal: 0.032,
+        description: "Facebook link or post image, 1.91:1",
+    },
+];
+
+/// What `--preset` asks for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PresetChoice {
+    /// `--preset list`: print the table and exit.
+    List,
+    Preset(&'static Preset),
+}
+
+/// Parses a `--preset`: one of the `PRESETS` names, or `list`.
+pub fn parse_preset(s: &str) -> Result<PresetChoice, String> {
+    if s.eq_ignore_ascii_case("list") {
+        return Ok(PresetChoice::List);
+    }
+    match PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(s))
+    {
+        Some(preset) => Ok(PresetChoice::Preset(preset)),
+        None => {
+            let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+            Err(format!(
+                "unknown preset '{}' (available: {}, or list)",
+                s,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+/// Prints the presets as a table (`--preset list`).
+pub fn print_list() {
+    let header = ("Preset", "Canvas", "Vertical", "Horizontal");
+    println!(
+        "{:<14} {:>11}  {:>8}  {:>10}  Description",
+        header.0, header.1, header.2, header.3
+    );
+    for preset in &PRESETS {
+        println!(
+            "{:<14} {:>11}  {:>8}  {:>10}  {}",
+            preset.name,
+            format!("{}x{}", preset.width, preset.height),
+            preset.vertical,
+            preset.horizontal,
+            preset.description
+        );
+    }
+}
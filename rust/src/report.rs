@@ -0,0 +1,234 @@
+//! `--write-sidecar`: a JSON file next to each output describing how it was made, e.g.
+//! `bordered_photo.jpg.json`.
+//!
+//! `ImageReport` is the schema of one image; anything else that reports on images as JSON
+//! should serialize the same struct, so the files agree.
+
+use crate::atomic::AtomicFile;
+use crate::output::OutputFormat;
+use crate::output_zip::OutputZip;
+use crate::plan::WorkItem;
+use crate::size_limit::QualityFit;
+use crate::{Borders, Config, Orientation, Placement};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What was done to one image to produce one output.
+#[derive(Debug, Serialize)]
+pub struct ImageReport {
+    /// Path or URL of the input.
+    pub source: String,
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Size of the input file; `None` for URLs.
+    pub source_bytes: Option<u64>,
+    pub orientation: Orientation,
+    pub border: BorderRatios,
+    /// Width of each border in pixels, with `--border-px` and the per-side overrides applied.
+    pub borders: Borders,
+    /// `--border-color`, e.g. `white` or `#1A1A1A`.
+    pub border_color: String,
+    /// Factor the source was scaled by to fit inside the borders.
+    pub scale: f64,
+    /// Where the scaled image sits on the canvas, and what `--fit cover` cropped off it.
+    pub placement: Placement,
+    pub output: PathBuf,
+    pub output_width: u32,
+    pub output_height: u32,
+    pub output_bytes: u64,
+    pub encoder: EncoderSettings,
+    /// How `--max-output-size` settled the quality in `encoder`, if it was used.
+    pub quality_fit: Option<QualityFit>,
+    /// Time spent on this output, including the decode for the first output of an image.
+    pub duration_seconds: f64,
+}
+
+/// Border ratios an image got from its orientation, as fractions of the canvas, before any
+/// per-side overrides.
+#[derive(Debug, Serialize)]
+pub struct BorderRatios {
+    /// Top and bottom border, each.
+    pub vertical: f64,
+    /// Left and right border, each.
+    pub horizontal: f64,
+}
+
+/// The settings of the encoder an output was written with.
+#[derive(Debug, Serialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum EncoderSettings {
+    Jpeg {
+        quality: u8,
+        progressive: bool,
+        subsampling: String,
+        encoder: String,
+    },
+    Png {
+        compression: String,
+        filter: String,
+        /// oxipng level of `--optimize-png`, if used.
+        optimize_level: Option<u8>,
+    },
+    Webp {
+        quality: u8,
+        lossless: bool,
+    },
+    Avif {
+        quality: u8,
+        speed: u8,
+    },
+    Tiff {
+        compression: String,
+    },
+    Gif,
+}
+
+impl EncoderSettings {
+    pub fn of(format: OutputFormat, config: &Config) -> Self {
+        match format {
+            OutputFormat::Jpeg => EncoderSettings::Jpeg {
+                quality: config.jpeg_quality,
+                progressive: config.jpeg_progressive,
+                subsampling: name(config.jpeg_subsampling),
+                encoder: name(config.jpeg_encoder),
+            },
+            OutputFormat::Png => EncoderSettings::Png {
+                compression: name(config.png_compression),
+                filter: name(config.png_filter),
+                optimize_level: config.optimize_png.then_some(config.optimize_level),
+            },
+            OutputFormat::Webp => EncoderSettings::Webp {
+                quality: config.webp_quality,
+                lossless: config.webp_lossless,
+            },
+            OutputFormat::Avif => EncoderSettings::Avif {
+                quality: config.avif_quality,
+                speed: config.avif_speed,
+            },
+            OutputFormat::Tiff => EncoderSettings::Tiff {
+                compression: name(config.tiff_compression),
+            },
+            OutputFormat::Gif => EncoderSettings::Gif,
+        }
+    }
+}
+
+/// What `--stamp-metadata` records in an output's EXIF UserComment, as compact JSON.
+#[derive(Debug, Serialize)]
+pub struct SettingsStamp {
+    pub width: u32,
+    pub height: u32,
+    pub landscape_border: BorderRatios,
+    pub portrait_border: BorderRatios,
+    pub square_border: BorderRatios,
+    /// Tall images get these turned on their side.
+    pub panorama_border: BorderRatios,
+    pub border_color: String,
+    pub encoder: EncoderSettings,
+}
+
+impl SettingsStamp {
+    pub fn of(format: OutputFormat, config: &Config) -> Self {
+        let border = |orientation: Orientation| {
+            let (vertical, horizontal) = orientation.border_ratios(config);
+            BorderRatios {
+                vertical,
+                horizontal,
+            }
+        };
+        Self {
+            width: config.target_width,
+            height: config.target_height,
+            landscape_border: border(Orientation::Landscape),
+            portrait_border: border(Orientation::Portrait),
+            square_border: border(Orientation::Square),
+            panorama_border: border(Orientation::Panorama),
+            border_color: config.border_color.to_string(),
+            encoder: EncoderSettings::of(format, config),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl ImageReport {
+    /// Describes the output at `output` of `item`, written with `config`.
+    ///
+    /// `decoded` is the size the image was decoded at, which large JPEGs may have been
+    /// shrunk to; the layout is worked out from it like the canvas was.
+    pub fn new(
+        item: &WorkItem,
+        decoded: Option<(u32, u32)>,
+        output: &Path,
+        output_bytes: u64,
+        format: OutputFormat,
+        config: &Config,
+        elapsed: Duration,
+    ) -> Self {
+        let job = &item.job;
+        let (source_width, source_height) = item
+            .dimensions
+            .or(decoded)
+            .unwrap_or((config.target_width, config.target_height));
+        let (width, height) = decoded.unwrap_or((source_width, source_height));
+        let orientation = Orientation::of(width, height, config);
+        let (vertical, horizontal) = orientation.border_ratios(config);
+        let placement = Placement::of(width, height, config);
+        Self {
+            source: match &job.url {
+                Some(url) => url.clone(),
+                None => job.path.display().to_string(),
+            },
+            source_width,
+            source_height,
+            source_bytes: item.bytes,
+            orientation,
+            border: BorderRatios {
+                vertical,
+                horizontal,
+            },
+            borders: Borders::of(orientation, config),
+            border_color: config.border_color.to_string(),
+            scale: placement.scaled_size().0 as f64 / source_width.max(1) as f64,
+            placement,
+            output: output.to_path_buf(),
+            output_width: config.target_width,
+            output_height: config.target_height,
+            output_bytes,
+            encoder: EncoderSettings::of(format, config),
+            quality_fit: None,
+            duration_seconds: elapsed.as_secs_f64(),
+        }
+    }
+
+    /// Writes the report next to its output, as `<output name>.json`, or into the
+    /// `--output-zip` archive the output went to.
+    pub fn write(&self, zip: Option<&mut OutputZip>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut path = self.output.clone().into_os_string();
+        path.push(".json");
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        match zip {
+            Some(zip) => zip.add(Path::new(&path), &json)?,
+            None => {
+                let mut file = AtomicFile::create(Path::new(&path))?;
+                file.write_all(&json)?;
+                file.commit()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The command-line name of a setting's value, e.g. `444`.
+fn name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
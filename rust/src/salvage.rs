@@ -0,0 +1,212 @@
+//! `--salvage`: keep the decoded part of truncated JPEGs.
+//!
+//! The JPEG decoder doesn't stop at the end of a truncated file; it leaves every block it
+//! never received at mid-grey. Truncation is detected from the file itself (the stream ends
+//! before its end-of-image marker), and the grey blocks after the last decoded one are
+//! painted in the border color, so the missing part reads as border rather than as picture.
+
+use crate::border_color::BorderColor;
+use image::{DynamicImage, ImageBuffer, Pixel};
+use std::path::Path;
+
+/// Side of the blocks the decoder works in.
+const BLOCK: u32 = 8;
+
+/// Value every sample of a never-decoded block has, and how far it may drift through
+/// color conversion.
+const FILL_VALUE: u8 = 128;
+const FILL_TOLERANCE: u8 = 2;
+
+/// Returns true if `path` is a JPEG that ends before its end-of-image marker.
+pub fn is_truncated_jpeg(path: &Path) -> bool {
+    std::fs::read(path).is_ok_and(|data| is_truncated(&data))
+}
+
+/// Returns true if `data` is a JPEG stream that ends before its end-of-image marker.
+///
+/// The stream is walked segment by segment from the start-of-image marker, stepping over
+/// the entropy-coded data of each scan, so whatever a file holds after the end of its
+/// image (a motion photo's video, a vendor's trailer) is never looked at.
+fn is_truncated(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut at = 2;
+    loop {
+        // Anything between segments, fill bytes included, is skipped up to the next marker.
+        let Some(skip) = data
+            .get(at..)
+            .and_then(|rest| rest.iter().position(|&b| b == 0xFF))
+        else {
+            return true;
+        };
+        at += skip;
+        let Some(&marker) = data.get(at + 1) else {
+            return true;
+        };
+        at += match marker {
+            0xFF => 1,
+            0xD9 => return false,
+            // Markers without a length.
+            0x00 | 0x01 | 0xD0..=0xD7 => 2,
+            _ => match data.get(at + 2..at + 4) {
+                Some(&[high, low]) => 2 + u16::from_be_bytes([high, low]) as usize,
+                _ => return true,
+            },
+        };
+        if marker == 0xDA {
+            at = match scan_end(data, at) {
+                Some(end) => end,
+                None => return true,
+            };
+        }
+    }
+}
+
+/// Returns where the entropy-coded data starting at `at` ends: at the first marker that
+/// isn't a stuffed 0xFF byte or a restart marker, or `None` if the data runs out first.
+fn scan_end(data: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        at += data.get(at..)?.iter().position(|&b| b == 0xFF)?;
+        match *data.get(at + 1)? {
+            0x00 | 0xD0..=0xD7 | 0xFF => at += 1,
+            _ => return Some(at),
+        }
+    }
+}
+
+/// Paints the never-decoded tail of a truncated JPEG's pixels in the border color.
+///
+/// Returns false if the image holds no such tail, i.e. there was nothing to salvage.
+///
+/// JPEGs have no alpha, so the color is painted opaque; grayscale images get its gray.
+pub fn fill_missing(img: &mut DynamicImage, border: BorderColor) -> bool {
+    match img {
+        DynamicImage::ImageLuma8(buf) => fill_missing_blocks(buf, border),
+        DynamicImage::ImageRgb8(buf) => fill_missing_blocks(buf, border),
+        _ => false,
+    }
+}
+
+fn fill_missing_blocks<P: Pixel<Subpixel = u8>>(
+    img: &mut ImageBuffer<P, Vec<u8>>,
+    border: BorderColor,
+) -> bool {
+    let (width, height) = img.dimensions();
+    let cols = width.div_ceil(BLOCK);
+    let rows = height.div_ceil(BLOCK);
+    let is_fill = |img: &ImageBuffer<P, Vec<u8>>, col: u32, row: u32| {
+        block_pixels(col, row, width, height).all(|(x, y)| {
+            img.get_pixel(x, y)
+                .channels()
+                .iter()
+                .all(|&v| v.abs_diff(FILL_VALUE) <= FILL_TOLERANCE)
+        })
+    };
+
+    // The last block row that received any data, and the first missing column in it.
+    let Some(last_row) = (0..rows)
+        .rev()
+        .find(|&row| (0..cols).any(|col| !is_fill(img, col, row)))
+    else {
+        return false;
+    };
+    let cut = (0..cols)
+        .rposition(|col| !is_fill(img, col, last_row))
+        .map_or(0, |c| c as u32 + 1);
+    if last_row == rows - 1 && cut == cols {
+        return false;
+    }
+
+    let border: P = border.opaque().pixel();
+    let fill = |img: &mut ImageBuffer<P, Vec<u8>>, col: u32, row: u32| {
+        for (x, y) in block_pixels(col, row, width, height) {
+            img.put_pixel(x, y, border);
+        }
+    };
+    for row in last_row + 1..rows {
+        for col in 0..cols {
+            fill(img, col, row);
+        }
+    }
+    // With chroma subsampling a decoding unit is two block rows tall, so the row above
+    // can end at the same column.
+    let rows_to_trim = if last_row > 0 {
+        vec![last_row - 1, last_row]
+    } else {
+        vec![last_row]
+    };
+    for row in rows_to_trim {
+        for col in (cut..cols).rev() {
+            if !is_fill(img, col, row) {
+                break;
+            }
+            fill(img, col, row);
+        }
+    }
+    true
+}
+
+fn block_pixels(col: u32, row: u32, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let xs = col * BLOCK..((col + 1) * BLOCK).min(width);
+    let ys = row * BLOCK..((row + 1) * BLOCK).min(height);
+    ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use std::io::Cursor;
+
+    /// A JPEG of a gradient, whose scan holds plenty of stuffed 0xFF bytes.
+    fn jpeg() -> Vec<u8> {
+        let img = RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 255]));
+        let mut encoded = Vec::new();
+        DynamicImage::from(img)
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn complete_jpegs_are_not_truncated() {
+        assert!(!is_truncated(&jpeg()));
+    }
+
+    #[test]
+    fn data_after_the_end_of_image_is_ignored() {
+        // A motion photo's video, whose bytes happen to hold a start-of-scan marker and no
+        // end-of-image marker after it.
+        let mut data = jpeg();
+        data.extend(b"ftypmp42");
+        data.extend([0; 100]);
+        data.extend([0xFF, 0xDA, 0x12, 0x34, 0xFF, 0x00, 0x56]);
+        assert!(!is_truncated(&data));
+    }
+
+    #[test]
+    fn jpegs_cut_short_are_truncated() {
+        let data = jpeg();
+        for keep in [data.len() / 2, data.len() - 2, 4] {
+            assert!(is_truncated(&data[..keep]), "cut to {} bytes", keep);
+        }
+        // Other formats are never JPEGs cut short.
+        assert!(!is_truncated(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn the_missing_part_of_a_truncated_jpeg_is_filled() {
+        let data = jpeg();
+        // Halfway through the scan.
+        let scan = data.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        let mut img = image::load_from_memory(&data[..(scan + data.len()) / 2]).unwrap();
+        let red = BorderColor([255, 0, 0, 255]);
+        assert!(fill_missing(&mut img, red));
+        let img = img.to_rgb8();
+        assert_eq!(img.get_pixel(63, 63).0, [255, 0, 0]);
+        assert_ne!(img.get_pixel(0, 0).0, [255, 0, 0]);
+        let mut whole = image::load_from_memory(&data).unwrap();
+        assert!(!fill_missing(&mut whole, red));
+    }
+}
@@ -0,0 +1,116 @@
+//! `--shadow`: a soft drop shadow under the photo, so it seems to float above the canvas.
+//!
+//! The shadow is an alpha mask of the photo's shape, rounded corners included, blurred and
+//! laid over the canvas in black before the photo goes on top. Like `--background blur`,
+//! the blur runs on a copy reduced by up to `MAX_REDUCTION`, and only on the one channel of
+//! the mask around the photo, not on the canvas.
+
+use crate::border_color::BorderColor;
+use crate::corners::{self, CornerRadius};
+use crate::Placement;
+use image::imageops::{self, FilterType};
+use image::{GrayImage, ImageBuffer, Luma, Pixel};
+
+/// Most the mask is scaled down by for blurring.
+const MAX_REDUCTION: f32 = 8.0;
+
+/// Smallest blur radius in pixels of the reduced mask, as for `--background blur`.
+const REDUCED_RADIUS: f32 = 4.0;
+
+/// How far past the photo the blur spreads, in blur radii (sigmas): beyond three it leaves
+/// less than one percent.
+const SPREAD: f32 = 3.0;
+
+/// A `--shadow`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shadow {
+    /// How far the shadow is moved right and down from the photo, in pixels.
+    pub offset: (i32, i32),
+    /// Gaussian blur radius (sigma) in pixels.
+    pub blur: f32,
+    /// Opacity of the shadow at its darkest, from 0.0 to 1.0.
+    pub opacity: f32,
+}
+
+/// Parses a `--shadow-offset`: "X,Y" in pixels, negative for left or up, e.g. "8,12".
+pub fn parse_shadow_offset(s: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("invalid offset '{}' (expected X,Y in pixels, e.g. 8,12)", s);
+    let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok((x, y))
+}
+
+impl Shadow {
+    /// Darkens `canvas` with the shadow of the image at `placement`, rounded with `corners`.
+    /// Whatever of it falls off the canvas is dropped.
+    pub fn draw<P: Pixel>(
+        &self,
+        canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+        placement: Placement,
+        corners: Option<CornerRadius>,
+    ) {
+        let reduction = (self.blur / REDUCED_RADIUS).clamp(1.0, MAX_REDUCTION) as f64;
+        let margin = (self.blur * SPREAD).ceil() as u32;
+        let (width, height) = (placement.width + 2 * margin, placement.height + 2 * margin);
+        let mask = self.mask(placement, corners, margin, reduction);
+        let mask = imageops::resize(&mask, width, height, FilterType::Triangle);
+
+        let black: P = BorderColor::BLACK.pixel();
+        let left = placement.x as i64 + self.offset.0 as i64 - margin as i64;
+        let top = placement.y as i64 + self.offset.1 as i64 - margin as i64;
+        let (canvas_width, canvas_height) = canvas.dimensions();
+        // The part the photo goes over whole, corners aside, needs no shadow.
+        let inset = corners.map_or(0.0, |radius| {
+            radius.resolve(placement.width, placement.height).ceil()
+        }) as i64;
+        let covered_x = placement.x as i64 + inset..(placement.x + placement.width) as i64 - inset;
+        let covered_y = placement.y as i64 + inset..(placement.y + placement.height) as i64 - inset;
+        for (x, y, &Luma([value])) in mask.enumerate_pixels() {
+            let (canvas_x, canvas_y) = (left + x as i64, top + y as i64);
+            let on_canvas = (0..canvas_width as i64).contains(&canvas_x)
+                && (0..canvas_height as i64).contains(&canvas_y);
+            let covered = covered_x.contains(&canvas_x) && covered_y.contains(&canvas_y);
+            if value == 0 || !on_canvas || covered {
+                continue;
+            }
+            let (canvas_x, canvas_y) = (canvas_x as u32, canvas_y as u32);
+            let alpha = value as f64 / 255.0 * self.opacity as f64;
+            let under = *canvas.get_pixel(canvas_x, canvas_y);
+            canvas.put_pixel(canvas_x, canvas_y, corners::mix(under, black, alpha));
+        }
+    }
+
+    /// The blurred mask of the photo's shape with `margin` around it, scaled down by
+    /// `reduction`.
+    fn mask(
+        &self,
+        placement: Placement,
+        corners: Option<CornerRadius>,
+        margin: u32,
+        reduction: f64,
+    ) -> GrayImage {
+        let reduced = |size: u32| ((size as f64 / reduction).ceil() as u32).max(1);
+        let (width, height) = (
+            reduced(placement.width + 2 * margin),
+            reduced(placement.height + 2 * margin),
+        );
+        let (photo_width, photo_height) = (
+            placement.width as f64 / reduction,
+            placement.height as f64 / reduction,
+        );
+        let radius = corners.map_or(0.0, |radius| {
+            radius.resolve(placement.width, placement.height) / reduction
+        });
+        let start = margin as f64 / reduction;
+        let mut mask = GrayImage::new(width, height);
+        for (x, y, pixel) in mask.enumerate_pixels_mut() {
+            let (x, y) = (x as f64 + 0.5 - start, y as f64 + 0.5 - start);
+            if (0.0..photo_width).contains(&x) && (0.0..photo_height).contains(&y) {
+                let coverage = corners::coverage(x, y, photo_width, photo_height, radius);
+                *pixel = Luma([(coverage * 255.0).round() as u8]);
+            }
+        }
+        imageops::blur(&mask, self.blur / reduction as f32)
+    }
+}
@@ -0,0 +1,112 @@
+//! `--caption-exif`: the camera and exposure settings an image was taken with, from its
+//! EXIF, written the way photographers do, e.g. `f/1.8` and `1/250s`.
+
+use exif::{Exif, Field, In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+/// What an image's EXIF says about how it was taken; any of it may be missing.
+#[derive(Debug, Default)]
+pub struct ShotInfo {
+    /// Make and model, e.g. `SONY ILCE-7M3`, the make left out where the model starts
+    /// with it, as in `Canon EOS R5`.
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    /// Focal length in millimeters, e.g. `35`.
+    pub focal: Option<String>,
+    /// E.g. `f/1.8`.
+    pub aperture: Option<String>,
+    /// Exposure time, e.g. `1/250s` or `2s`.
+    pub shutter: Option<String>,
+    /// E.g. `200`.
+    pub iso: Option<String>,
+}
+
+/// Reads the EXIF of the image at `path`; `None` if it has none.
+pub fn read_exif(path: &Path) -> Option<Exif> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    Reader::new().read_from_container(&mut reader).ok()
+}
+
+/// Reads the EXIF of the encoded image `data`; `None` if it has none.
+pub fn read_exif_bytes(data: &[u8]) -> Option<Exif> {
+    Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+}
+
+impl ShotInfo {
+    /// The settings `exif` records; `None` if it records none of them.
+    pub fn of(exif: &Exif) -> Option<Self> {
+        let field = |tag| exif.get_field(tag, In::PRIMARY);
+        let text = |tag| field(tag).and_then(ascii);
+        let number = |tag| field(tag).and_then(rational);
+        let camera = match (text(Tag::Make), text(Tag::Model)) {
+            (Some(make), Some(model)) => {
+                let brand = make
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase();
+                match model.to_lowercase().starts_with(&brand) {
+                    true => Some(model),
+                    false => Some(format!("{} {}", make, model)),
+                }
+            }
+            (make, model) => model.or(make),
+        };
+        let shutter = number(Tag::ExposureTime).map(|time| match time < 1.0 {
+            true => format!("1/{}s", (1.0 / time).round()),
+            false => format!("{}s", decimal(time)),
+        });
+        let iso = field(Tag::PhotographicSensitivity).and_then(|field| field.value.get_uint(0));
+        let info = ShotInfo {
+            camera,
+            lens: text(Tag::LensModel),
+            focal: number(Tag::FocalLength).map(decimal),
+            aperture: number(Tag::FNumber).map(|f| format!("f/{}", decimal(f))),
+            shutter,
+            iso: iso.filter(|&iso| iso > 0).map(|iso| iso.to_string()),
+        };
+        let any = [
+            &info.camera,
+            &info.lens,
+            &info.focal,
+            &info.aperture,
+            &info.shutter,
+            &info.iso,
+        ]
+        .iter()
+        .any(|value| value.is_some());
+        any.then_some(info)
+    }
+}
+
+/// The text of an ASCII field, trimmed; `None` if it is empty.
+fn ascii(field: &Field) -> Option<String> {
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let text = String::from_utf8_lossy(values.first()?);
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The value of a positive rational field.
+fn rational(field: &Field) -> Option<f64> {
+    let Value::Rational(values) = &field.value else {
+        return None;
+    };
+    let value = values.first().filter(|value| value.denom != 0)?.to_f64();
+    (value > 0.0).then_some(value)
+}
+
+/// `value` with one decimal, or none if it is whole, e.g. `1.8` and `8`.
+fn decimal(value: f64) -> String {
+    let rounded = (value * 10.0).round() / 10.0;
+    match rounded.fract() == 0.0 {
+        true => format!("{}", rounded),
+        false => format!("{:.1}", rounded),
+    }
+}
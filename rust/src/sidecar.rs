@@ -0,0 +1,207 @@
+//! Per-image sidecar files overriding settings for a single image.
+//!
+//! A sidecar is a TOML file next to the image, named `IMG_1234.jpg.toml` or
+//! `IMG_1234.wba.toml`. Its keys use the command-line flag names with underscores:
+//!
+//! ```toml
+//! landscape_vert = 0.12
+//! width = 2048
+//! format = "png"
+//! ```
+//!
+//! Values set in the sidecar win over flags, which win over the defaults.
+
+use crate::border_color::{parse_border_color, AutoColorMode, BorderColor, BorderColorChoice};
+use crate::jpeg::Subsampling;
+use crate::output::OutputFormat;
+use crate::Config;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The settings a sidecar may override. Unknown keys are an error, so typos don't go unnoticed.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sidecar {
+    width: Option<u32>,
+    height: Option<u32>,
+    landscape_vert: Option<f64>,
+    landscape_horiz: Option<f64>,
+    portrait_vert: Option<f64>,
+    portrait_horiz: Option<f64>,
+    square_vert: Option<f64>,
+    square_horiz: Option<f64>,
+    panorama_vert: Option<f64>,
+    panorama_horiz: Option<f64>,
+    border_color: Option<String>,
+    jpeg_quality: Option<u8>,
+    jpeg_subsampling: Option<String>,
+    format: Option<String>,
+}
+
+/// Returns the sidecar file of `image`, if it has one.
+pub fn sidecar_path(image: &Path) -> Option<PathBuf> {
+    let mut full = image.as_os_str().to_owned();
+    full.push(".toml");
+    let candidates = [PathBuf::from(full), image.with_extension("wba.toml")];
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+/// Returns `config` with the overrides of `image`'s sidecar applied, or `None` if the image
+/// has no sidecar.
+///
+/// Errors name the sidecar and, for parse errors, the offending key and line.
+pub fn apply_sidecar(image: &Path, config: &Config) -> Result<Option<Config>, String> {
+    let Some(path) = sidecar_path(image) else {
+        return Ok(None);
+    };
+    let invalid = |e: &dyn std::fmt::Display| format!("invalid sidecar {}: {}", path.display(), e);
+    let text = std::fs::read_to_string(&path).map_err(|e| invalid(&e))?;
+    let sidecar: Sidecar = toml::from_str(&text).map_err(|e| invalid(&e))?;
+
+    let mut config = config.clone();
+    if sidecar.width.is_some() || sidecar.height.is_some() {
+        // The sidecar's size wins over `--portrait-size` and friends and `--longest-edge` too.
+        config.class_sizes.clear();
+        config.longest_edge = None;
+    }
+    if let Some(v) = sidecar.width {
+        config.target_width = v;
+    }
+    if let Some(v) = sidecar.height {
+        config.target_height = v;
+    }
+    let ratios = [
+        sidecar.landscape_vert,
+        sidecar.landscape_horiz,
+        sidecar.portrait_vert,
+        sidecar.portrait_horiz,
+        sidecar.square_vert,
+        sidecar.square_horiz,
+        sidecar.panorama_vert,
+        sidecar.panorama_horiz,
+    ];
+    if ratios.iter().any(Option::is_some) {
+        // Ratios from the sidecar win over `--border-px` too.
+        config.border_px = None;
+    }
+    if let Some(v) = sidecar.landscape_vert {
+        config.landscape_vert_border = v;
+    }
+    if let Some(v) = sidecar.landscape_horiz {
+        config.landscape_horiz_border = v;
+    }
+    if let Some(v) = sidecar.portrait_vert {
+        config.portrait_vert_border = v;
+    }
+    if let Some(v) = sidecar.portrait_horiz {
+        config.portrait_horiz_border = v;
+    }
+    if let Some(v) = sidecar.square_vert {
+        config.square_vert_border = v;
+    }
+    if let Some(v) = sidecar.square_horiz {
+        config.square_horiz_border = v;
+    }
+    if let Some(v) = sidecar.panorama_vert {
+        config.panorama_vert_border = v;
+    }
+    if let Some(v) = sidecar.panorama_horiz {
+        config.panorama_horiz_border = v;
+    }
+    if let Some(color) = sidecar.border_color {
+        match parse_border_color(&color) {
+            Ok(BorderColorChoice::Fixed(color)) => {
+                config.border_color = color;
+                config.auto_color = None;
+            }
+            Ok(BorderColorChoice::Auto) => {
+                config.auto_color = config.auto_color.or(Some(AutoColorMode::Average));
+            }
+            Err(e) => return Err(invalid(&format!("border_color: {}", e))),
+        }
+    }
+    if let Some(v) = sidecar.jpeg_quality {
+        config.jpeg_quality = v;
+    }
+    if let Some(name) = sidecar.jpeg_subsampling {
+        config.jpeg_subsampling = Subsampling::from_str(&name, true).map_err(|_| {
+            invalid(&format!(
+                "jpeg_subsampling: unknown subsampling '{}' (expected 444, 422 or 420)",
+                name
+            ))
+        })?;
+    }
+    if let Some(name) = sidecar.format {
+        let format = OutputFormat::from_str(&name, true).map_err(|_| {
+            invalid(&format!(
+                "format: unknown output format '{}' (expected jpg, png, webp, avif or tiff)",
+                name
+            ))
+        })?;
+        if let Some(message) = format.unavailable() {
+            return Err(invalid(&format!("format: {}", message)));
+        }
+        config.format = Some(format);
+    }
+    config.check_borders().map_err(|e| invalid(&e))?;
+    let keeps_alpha = config.format.is_some_and(OutputFormat::keeps_alpha);
+    if config.border_color == BorderColor::TRANSPARENT && !keeps_alpha {
+        return Err(invalid(
+            &"border_color: transparent needs a format with transparency, e.g. format = \"png\"",
+        ));
+    }
+    Ok(Some(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{config, temp_dir};
+
+    #[test]
+    fn sidecars_win_over_flags_which_win_over_defaults() {
+        let dir = temp_dir("sidecar-precedence");
+        let image = dir.join("photo.jpg");
+        std::fs::write(
+            dir.join("photo.jpg.toml"),
+            "width = 2048\nlandscape_vert = 0.12\n",
+        )
+        .unwrap();
+        let flags = config(&[
+            "--width",
+            "1500",
+            "--landscape-vert",
+            "0.2",
+            "--jpeg-quality",
+            "80",
+        ]);
+        let merged = apply_sidecar(&image, &flags)
+            .unwrap()
+            .expect("has a sidecar");
+        let defaults = config(&[]);
+        assert_eq!(merged.target_width, 2048);
+        assert_eq!(merged.landscape_vert_border, 0.12);
+        assert_eq!(merged.jpeg_quality, 80);
+        assert_eq!(merged.target_height, defaults.target_height);
+        assert_eq!(merged.portrait_vert_border, defaults.portrait_vert_border);
+    }
+
+    #[test]
+    fn misspelled_keys_are_named_with_their_line() {
+        let dir = temp_dir("sidecar-malformed");
+        let image = dir.join("photo.jpg");
+        std::fs::write(dir.join("photo.wba.toml"), "width = 2048\nwidht = 1080\n").unwrap();
+        let error = apply_sidecar(&image, &config(&[]))
+            .err()
+            .expect("is rejected");
+        let sidecar = dir.join("photo.wba.toml");
+        assert!(
+            error.starts_with(&format!("invalid sidecar {}: ", sidecar.display())),
+            "{}",
+            error
+        );
+        assert!(error.contains("unknown field `widht`"), "{}", error);
+        assert!(error.contains("line 2"), "{}", error);
+    }
+}
@@ -0,0 +1,133 @@
+//! `--max-output-size`: lossy outputs re-encoded at lower qualities until they fit.
+//!
+//! The configured quality is tried first; if the file comes out too large, a binary search
+//! between it and `--min-quality` finds the highest quality that fits. Every candidate is
+//! encoded in memory, and encodes after the first are timed as the quality search stage.
+
+use crate::metadata::Metadata;
+use crate::output::OutputFormat;
+use crate::stats::Stages;
+use crate::{format_size, write_canvas, Config};
+use image::DynamicImage;
+use serde::Serialize;
+use std::error::Error;
+
+/// The quality a lossy output ended up at under `--max-output-size`.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct QualityFit {
+    pub quality: u8,
+    /// Encodes it took, the first at the configured quality included.
+    pub tries: u32,
+    /// False if even `--min-quality` was too large; the output is written at it anyway.
+    pub fits: bool,
+}
+
+/// Returns true if outputs in `format` are searched for a quality that fits. Only the lossy
+/// encodings have a quality to lower.
+pub fn applies(format: OutputFormat, config: &Config) -> bool {
+    config.max_output_size.is_some() && quality(format, config).is_some()
+}
+
+/// The quality setting of `format`, if the format is lossy.
+fn quality(format: OutputFormat, config: &Config) -> Option<u8> {
+    match format {
+        OutputFormat::Jpeg => Some(config.jpeg_quality),
+        OutputFormat::Webp if !config.webp_lossless => Some(config.webp_quality),
+        OutputFormat::Avif => Some(config.avif_quality),
+        _ => None,
+    }
+}
+
+/// Returns `config` with the quality of `format` set to `quality`.
+pub fn with_quality(config: &Config, format: OutputFormat, quality: u8) -> Config {
+    let mut config = config.clone();
+    match format {
+        OutputFormat::Jpeg => config.jpeg_quality = quality,
+        OutputFormat::Webp => config.webp_quality = quality,
+        OutputFormat::Avif => config.avif_quality = quality,
+        _ => {}
+    }
+    config
+}
+
+/// Encodes `canvas` at the highest quality whose output fits `--max-output-size`, down to
+/// `--min-quality`, and records the result in `stages.fit`.
+///
+/// Must only be called when `applies` says so.
+pub fn encode_to_fit(
+    canvas: &DynamicImage,
+    metadata: &Metadata,
+    format: OutputFormat,
+    config: &Config,
+    stages: &mut Stages,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let limit = config.max_output_size.unwrap_or(u64::MAX);
+    let start_quality = quality(format, config).unwrap_or(100);
+    let mut tries = 0;
+    let mut encode = |quality: u8, stages: &mut Stages| {
+        let stage = if tries == 0 {
+            &mut stages.encode
+        } else {
+            &mut stages.quality_search
+        };
+        tries += 1;
+        let config = with_quality(config, format, quality);
+        let mut encoded = Vec::new();
+        Stages::time(stage, || {
+            write_canvas(canvas, metadata, format, &config, &mut encoded)
+        })?;
+        Ok::<_, Box<dyn Error>>(encoded)
+    };
+
+    let first = encode(start_quality, stages)?;
+    if first.len() as u64 <= limit || start_quality <= config.min_quality {
+        let fits = first.len() as u64 <= limit;
+        stages.fit = Some(QualityFit {
+            quality: start_quality,
+            tries: 1,
+            fits,
+        });
+        return Ok(first);
+    }
+    // The highest quality in `low..=high` that fits, or the lowest tried if none does.
+    let (mut low, mut high) = (config.min_quality, start_quality - 1);
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    let mut lowest = (start_quality, first);
+    while low <= high {
+        let mid = low + (high - low) / 2;
+        let encoded = encode(mid, stages)?;
+        if encoded.len() as u64 <= limit {
+            best = Some((mid, encoded));
+            low = mid + 1;
+        } else {
+            // `--min-quality` is at least 1, so this stays in range.
+            lowest = (mid, encoded);
+            high = mid - 1;
+        }
+    }
+    let ((quality, encoded), fits) = match best {
+        Some(best) => (best, true),
+        None => (lowest, false),
+    };
+    stages.fit = Some(QualityFit {
+        quality,
+        tries,
+        fits,
+    });
+    Ok(encoded)
+}
+
+/// Warns about an output that didn't fit even at `--min-quality`.
+pub fn warn_if_over(label: &str, size: u64, config: &Config, stages: &Stages) {
+    if let (Some(fit), Some(limit)) = (stages.fit, config.max_output_size) {
+        if !fit.fits {
+            eprintln!(
+                "⚠️  {} is {} even at quality {}, over --max-output-size {}",
+                label,
+                format_size(size),
+                fit.quality,
+                format_size(limit)
+            );
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Run statistics and the end-of-run summary.
+
+use crate::discover::{SkipReason, Skipped};
+use crate::format_size;
+use crate::size_limit::QualityFit;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Most canvas sizes the summary lists one by one.
+const MAX_LISTED_DIMENSIONS: usize = 5;
+
+/// Time one image spent in each step, shown next to its total so slow encoders (AVIF) or
+/// slow downloads stand out.
+#[derive(Default)]
+pub struct Stages {
+    pub download: Duration,
+    pub decode: Duration,
+    pub border: Duration,
+    pub encode: Duration,
+    /// The `--optimize-png` pass.
+    pub optimize: Duration,
+    /// Encodes after the first while looking for a quality under `--max-output-size`.
+    pub quality_search: Duration,
+    /// The quality `--max-output-size` settled on, for the log line and the reports.
+    pub fit: Option<QualityFit>,
+}
+
+impl Stages {
+    /// Runs `f`, adding the time it takes to `stage`.
+    pub fn time<T>(stage: &mut Duration, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        *stage += start.elapsed();
+        result
+    }
+
+    /// `, quality N` when `--max-output-size` lowered the quality, for the log line.
+    pub fn fitted_quality(&self) -> String {
+        match self.fit {
+            Some(fit) if fit.tries > 1 => format!(", quality {}", fit.quality),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Formats as e.g. `decode 0.12s, border 0.30s, encode 4.81s`; a download only shows up
+/// for URL inputs, optimizing only for `--optimize-png` and the quality search only when
+/// the first encode was too large.
+impl fmt::Display for Stages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.download.is_zero() {
+            write!(f, "download {:.2}s, ", self.download.as_secs_f64())?;
+        }
+        write!(
+            f,
+            "decode {:.2}s, border {:.2}s, encode {:.2}s",
+            self.decode.as_secs_f64(),
+            self.border.as_secs_f64(),
+            self.encode.as_secs_f64()
+        )?;
+        if !self.optimize.is_zero() {
+            write!(f, ", optimize {:.2}s", self.optimize.as_secs_f64())?;
+        }
+        if !self.quality_search.is_zero() {
+            write!(
+                f,
+                ", quality search {:.2}s",
+                self.quality_search.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of a single input, for the per-folder breakdown.
+struct InputStats {
+    label: String,
+    ok: usize,
+    fail: usize,
+    skipped: usize,
+}
+
+/// Counters and timings accumulated across all inputs of a run.
+pub struct Stats {
+    total_ok: usize,
+    total_fail: usize,
+    /// Successes that came from truncated files (`--salvage`), also counted in `total_ok`.
+    salvaged: usize,
+    /// Outputs written below their quality setting to fit `--max-output-size`, and those of
+    /// them that still didn't fit.
+    quality_lowered: usize,
+    over_size_limit: usize,
+    /// Outputs `--dry-run` found would be written, counted instead of successes.
+    planned: usize,
+    /// The `--output-zip` archive and its final size.
+    archive: Option<(PathBuf, u64)>,
+    total_duration: Duration,
+    /// Size of everything written, so encoder settings can be compared.
+    total_bytes: u64,
+    fastest: Option<(String, Duration)>,
+    slowest: Option<(String, Duration)>,
+    /// How many outputs were written at each canvas size, which varies with `--size`, the
+    /// per-orientation sizes and `--pad-only`.
+    dimensions: BTreeMap<(u32, u32), usize>,
+    per_input: Vec<InputStats>,
+    skipped: BTreeMap<SkipReason, usize>,
+    /// Skipped files that came with a detail, listed under their reason in the summary.
+    skipped_details: Vec<(SkipReason, String)>,
+    /// How many files each `.borderignore` file left out.
+    ignored_by: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Creates empty statistics with one breakdown row per input label.
+    pub fn new<'a>(labels: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            total_ok: 0,
+            total_fail: 0,
+            salvaged: 0,
+            quality_lowered: 0,
+            over_size_limit: 0,
+            planned: 0,
+            archive: None,
+            total_duration: Duration::ZERO,
+            total_bytes: 0,
+            fastest: None,
+            slowest: None,
+            dimensions: BTreeMap::new(),
+            per_input: labels
+                .into_iter()
+                .map(|label| InputStats {
+                    label: label.to_string(),
+                    ok: 0,
+                    fail: 0,
+                    skipped: 0,
+                })
+                .collect(),
+            skipped: BTreeMap::new(),
+            skipped_details: Vec::new(),
+            ignored_by: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_success(&mut self, input: usize, filename: &str, elapsed: Duration, bytes: u64) {
+        self.total_ok += 1;
+        self.total_duration += elapsed;
+        self.total_bytes += bytes;
+        self.per_input[input].ok += 1;
+        if self
+            .fastest
+            .as_ref()
+            .map(|(_, d)| elapsed < *d)
+            .unwrap_or(true)
+        {
+            self.fastest = Some((filename.to_string(), elapsed));
+        }
+        if self
+            .slowest
+            .as_ref()
+            .map(|(_, d)| elapsed > *d)
+            .unwrap_or(true)
+        {
+            self.slowest = Some((filename.to_string(), elapsed));
+        }
+    }
+
+    /// Records the canvas size of the last recorded success.
+    pub fn record_dimensions(&mut self, width: u32, height: u32) {
+        *self.dimensions.entry((width, height)).or_default() += 1;
+    }
+
+    /// Marks the last recorded success as salvaged from a truncated file.
+    pub fn record_salvaged(&mut self) {
+        self.salvaged += 1;
+    }
+
+    /// Records how the last success fared under `--max-output-size`.
+    pub fn record_fit(&mut self, fit: &QualityFit) {
+        if fit.tries > 1 {
+            self.quality_lowered += 1;
+        }
+        if !fit.fits {
+            self.over_size_limit += 1;
+        }
+    }
+
+    /// Records an output `--dry-run` would write.
+    pub fn record_planned(&mut self, input: usize) {
+        self.planned += 1;
+        self.per_input[input].ok += 1;
+    }
+
+    pub fn record_archive(&mut self, path: PathBuf, size: u64) {
+        self.archive = Some((path, size));
+    }
+
+    pub fn failures(&self) -> usize {
+        self.total_fail
+    }
+
+    pub fn record_failure(&mut self, input: usize) {
+        self.total_fail += 1;
+        self.per_input[input].fail += 1;
+    }
+
+    pub fn record_skip(&mut self, skipped: &Skipped) {
+        self.per_input[skipped.input].skipped += 1;
+        *self.skipped.entry(skipped.reason).or_default() += 1;
+        if skipped.reason == SkipReason::BorderIgnored {
+            // One line per ignore file says more than one per image.
+            let file = skipped.detail.clone().unwrap_or_default();
+            *self.ignored_by.entry(file).or_default() += 1;
+        } else if let Some(detail) = &skipped.detail {
+            let line = format!("{} ({})", skipped.path.display(), detail);
+            self.skipped_details.push((skipped.reason, line));
+        }
+    }
+
+    /// Lists the canvas sizes written with their counts, e.g. `1080x1080 (3), 1080x1350
+    /// (1)`; past `MAX_LISTED_DIMENSIONS` sizes, only how many and the smallest and largest.
+    fn describe_dimensions(&self) -> String {
+        if self.dimensions.len() <= MAX_LISTED_DIMENSIONS {
+            let sizes: Vec<String> = self
+                .dimensions
+                .iter()
+                .map(|((width, height), count)| format!("{}x{} ({})", width, height, count))
+                .collect();
+            return sizes.join(", ");
+        }
+        let area = |&(&(width, height), _): &(&(u32, u32), &usize)| width as u64 * height as u64;
+        let smallest = self.dimensions.iter().min_by_key(area);
+        let largest = self.dimensions.iter().max_by_key(area);
+        match (smallest, largest) {
+            (Some(((small_w, small_h), _)), Some(((large_w, large_h), _))) => format!(
+                "{} different, from {}x{} to {}x{}",
+                self.dimensions.len(),
+                small_w,
+                small_h,
+                large_w,
+                large_h
+            ),
+            _ => String::new(),
+        }
+    }
+
+    pub fn print_summary(&self) {
+        println!("\n📊 === Processing Summary ===");
+        println!("✅ Total images processed: {}", self.total_ok);
+        if self.planned > 0 {
+            println!("📝 Outputs that would be written: {}", self.planned);
+        }
+        if self.salvaged > 0 {
+            println!("🩹 Salvaged from truncated files: {}", self.salvaged);
+        }
+        if self.quality_lowered > 0 {
+            println!(
+                "📉 Quality lowered to fit --max-output-size: {}",
+                self.quality_lowered
+            );
+        }
+        if self.over_size_limit > 0 {
+            println!(
+                "⚠️  Over --max-output-size even at --min-quality: {}",
+                self.over_size_limit
+            );
+        }
+        println!("❌ Failed images: {}", self.total_fail);
+        for (reason, count) in &self.skipped {
+            println!("⏭️  Skipped ({}): {}", reason.describe(), count);
+            for (_, line) in self.skipped_details.iter().filter(|(r, _)| r == reason) {
+                println!("    {}", line);
+            }
+            if *reason == SkipReason::BorderIgnored {
+                for (file, count) in &self.ignored_by {
+                    println!("    {}: {}", file, count);
+                }
+            }
+        }
+        if self.total_ok > 0 {
+            let avg = self.total_duration.as_secs_f64() / self.total_ok as f64;
+            println!("⏱️  Average processing time: {:.2} seconds", avg);
+            println!("💾 Total output size: {}", format_size(self.total_bytes));
+            if self.dimensions.len() > 1 {
+                println!("📐 Output dimensions: {}", self.describe_dimensions());
+            }
+            if let Some((name, d)) = &self.fastest {
+                println!(
+                    "🚀 Fastest image: {} ({:.2} seconds)",
+                    name,
+                    d.as_secs_f64()
+                );
+            }
+            if let Some((name, d)) = &self.slowest {
+                println!(
+                    "🐢 Slowest image: {} ({:.2} seconds)",
+                    name,
+                    d.as_secs_f64()
+                );
+            }
+        }
+        if let Some((path, size)) = &self.archive {
+            println!(
+                "📦 Output archive: {} ({})",
+                path.display(),
+                format_size(*size)
+            );
+        }
+        if self.per_input.len() > 1 {
+            println!("\n📁 Per input:");
+            for input in &self.per_input {
+                println!(
+                    "  {}: {} ok, {} failed, {} skipped",
+                    input.label, input.ok, input.fail, input.skipped
+                );
+            }
+        }
+        println!();
+    }
+}
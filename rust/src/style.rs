@@ -0,0 +1,40 @@
+//! `--style`: named presets for the borders and their color.
+//!
+//! A preset only sets defaults: `--border-top` and friends, `--border-px` and `--border-color`
+//! still win over it. Its borders are fractions of the canvas's shorter side, so equal ones
+//! stay equal in pixels on canvases that aren't square.
+
+use crate::border_color::BorderColor;
+use crate::Borders;
+use clap::ValueEnum;
+
+/// A named preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Style {
+    /// An instant photo: thin equal borders on top, left and right, a thick one at the
+    /// bottom, and a warm off-white.
+    Polaroid,
+}
+
+impl Style {
+    /// The borders on a canvas whose shorter side is `short` pixels.
+    pub fn borders(self, short: u32) -> Borders {
+        let short = short as f64;
+        match self {
+            // Roughly the proportions of instant film: 5 mm sides on an 88 mm wide frame.
+            Style::Polaroid => Borders {
+                top: short * 0.055,
+                bottom: short * 0.22,
+                left: short * 0.055,
+                right: short * 0.055,
+            },
+        }
+    }
+
+    /// The border color the preset uses unless `--border-color` is given.
+    pub fn border_color(self) -> BorderColor {
+        match self {
+            Style::Polaroid => BorderColor([0xF7, 0xF5, 0xF0, 0xFF]),
+        }
+    }
+}
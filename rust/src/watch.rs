@@ -0,0 +1,244 @@
+//! `--watch`: keep processing images as they appear in the input folders.
+//!
+//! File events only mark a path as pending. A pending file is processed once its size has
+//! stopped changing between two checks, so an export that is still being written isn't
+//! picked up half-finished. Each file is processed again only when its size or
+//! modification time changes.
+
+use crate::borderignore::IgnoreFiles;
+use crate::discover::{
+    is_supported_image, screen_image, Input, Job, Rejected, Screened, SkipReason, Skipped, Source,
+    Worklist,
+};
+use crate::output::output_root;
+use crate::plan::Plan;
+use crate::stats::Stats;
+use crate::{run_plan, Config};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a pending file's size must stay the same before it's processed.
+const SETTLE_TIME: Duration = Duration::from_millis(1000);
+
+/// How often pending files are checked and Ctrl-C is noticed.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Size and modification time of a file, used to tell whether it changed.
+type Fingerprint = (u64, Option<SystemTime>);
+
+/// A file seen in an event but not yet stable.
+struct Pending {
+    input: usize,
+    root: PathBuf,
+    size: Option<u64>,
+    checked: Instant,
+}
+
+/// Watches the folder inputs and processes new or modified images until Ctrl-C.
+///
+/// `initial` is the plan of the first pass; its images count as processed already.
+pub fn watch(
+    inputs: &[Input],
+    initial: &Plan,
+    config: &Config,
+    stats: &mut Stats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Events carry absolute paths, so folders are matched by their canonical form.
+    let mut folders: Vec<(usize, &PathBuf, PathBuf)> = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        if let Source::Folder(folder) = &input.source {
+            folders.push((index, folder, folder.canonicalize()?));
+        }
+    }
+    if folders.is_empty() {
+        return Err("Error: --watch needs at least one input folder".into());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mode = if config.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    for (_, _, canonical) in &folders {
+        watcher.watch(canonical, mode)?;
+    }
+
+    let mut processed: HashMap<PathBuf, Fingerprint> = initial
+        .items
+        .iter()
+        .filter_map(|item| Some((item.job.path.clone(), fingerprint(&item.job.path)?)))
+        .collect();
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+
+    println!("\n👀 Watching for new images, press Ctrl-C to stop");
+    while !stop.load(Ordering::SeqCst) {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+                for event_path in event.paths {
+                    let Some((input, root, path)) =
+                        folders.iter().find_map(|(input, folder, canonical)| {
+                            let rel = event_path.strip_prefix(canonical).ok()?;
+                            let path = folder.join(rel);
+                            watched_image(&path, rel, folder, config)
+                                .then_some((input, folder, path))
+                        })
+                    else {
+                        continue;
+                    };
+                    // Every event restarts the settle timer.
+                    pending.insert(
+                        path,
+                        Pending {
+                            input: *input,
+                            root: root.to_path_buf(),
+                            size: None,
+                            checked: Instant::now(),
+                        },
+                    );
+                }
+            }
+            Ok(Err(e)) => eprintln!("❌ Watch error: {}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let ready = settle(&mut pending);
+        if ready.is_empty() {
+            continue;
+        }
+        let mut worklist = Worklist::default();
+        for (path, input, root) in ready {
+            let Some(print) = fingerprint(&path) else {
+                continue;
+            };
+            if processed.get(&path) == Some(&print) {
+                continue;
+            }
+            processed.insert(path.clone(), print);
+            // Ignore files are read afresh, so edits to them apply to later events.
+            match IgnoreFiles::new(&root).ignoring(&path) {
+                Ok(None) => {}
+                Ok(Some(ignore_file)) => {
+                    worklist.skipped.push(Skipped {
+                        input,
+                        path,
+                        reason: SkipReason::BorderIgnored,
+                        detail: Some(ignore_file.display().to_string()),
+                    });
+                    continue;
+                }
+                Err(reason) => {
+                    worklist.rejected.push(Rejected {
+                        input,
+                        label: path.display().to_string(),
+                        reason,
+                    });
+                    continue;
+                }
+            }
+            match screen_image(&path, &root, config) {
+                Screened::Ignored => {}
+                // We write outputs into the watched folder ourselves.
+                Screened::Skipped(SkipReason::AlreadyOutput, _) => {}
+                Screened::Skipped(reason, detail) => worklist.skipped.push(Skipped {
+                    input,
+                    path,
+                    reason,
+                    detail,
+                }),
+                Screened::Accepted => worklist.jobs.push(Job {
+                    input,
+                    path,
+                    root,
+                    url: None,
+                }),
+            }
+        }
+        // An event means the image changed since its output was written, so that output
+        // is stale and gets replaced even without --force.
+        let config = Config {
+            force: true,
+            ..config.clone()
+        };
+        run_plan(&Plan::new(worklist, &config)?, &config, stats, &mut None)?;
+    }
+    println!("\n🛑 Stopped watching");
+    Ok(())
+}
+
+/// Checks the pending files whose settle time has passed and returns the ones whose size
+/// hasn't changed since the previous check, removing them (and vanished files) from `pending`.
+fn settle(pending: &mut HashMap<PathBuf, Pending>) -> Vec<(PathBuf, usize, PathBuf)> {
+    let mut ready = Vec::new();
+    pending.retain(|path, entry| {
+        if entry.checked.elapsed() < SETTLE_TIME {
+            return true;
+        }
+        let Ok(size) = std::fs::metadata(path).map(|m| m.len()) else {
+            return false;
+        };
+        if entry.size == Some(size) {
+            ready.push((path.clone(), entry.input, entry.root.clone()));
+            return false;
+        }
+        entry.size = Some(size);
+        entry.checked = Instant::now();
+        true
+    });
+    ready.sort();
+    ready
+}
+
+fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+/// Returns true if a file event for `path` (`rel` below `folder`) concerns an image the
+/// folder scan would have picked up, applying the same hidden, symlink, depth and
+/// output-folder rules.
+fn watched_image(path: &Path, rel: &Path, folder: &Path, config: &Config) -> bool {
+    // Skip our own outputs when they go to a folder inside the watched one. The output
+    // folder may be spelled differently from the input, so compare real paths.
+    let real = (
+        output_root(folder, config).canonicalize(),
+        folder.canonicalize(),
+        path.canonicalize(),
+    );
+    if let (Ok(output_folder), Ok(folder), Ok(path)) = real {
+        let nested = output_folder != folder && output_folder.starts_with(&folder);
+        if nested && path.starts_with(&output_folder) {
+            return false;
+        }
+    }
+    if !config.recursive && rel.components().count() != 1 {
+        return false;
+    }
+    if !config.include_hidden
+        && rel
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return false;
+    }
+    let is_symlink = std::fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_symlink && !config.follow_symlinks {
+        return false;
+    }
+    path.is_file() && is_supported_image(path, config)
+}